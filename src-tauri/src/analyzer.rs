@@ -0,0 +1,325 @@
+use serde::{Deserialize, Serialize};
+use tantivy::tokenizer::{
+    AsciiFoldingFilter, Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer,
+    TextAnalyzer, Token, TokenStream, Tokenizer,
+};
+
+/// Name the note analyzer is registered under in the search index.
+pub const TOKENIZER_NAME: &str = "notes";
+
+/// Tokens longer than this (in bytes) are dropped, as with tantivy's default analyzer.
+const MAX_TOKEN_BYTES: usize = 40;
+
+const STEMMING_LANGUAGES: &[(&str, Language)] = &[
+    ("arabic", Language::Arabic),
+    ("danish", Language::Danish),
+    ("dutch", Language::Dutch),
+    ("english", Language::English),
+    ("finnish", Language::Finnish),
+    ("french", Language::French),
+    ("german", Language::German),
+    ("greek", Language::Greek),
+    ("hungarian", Language::Hungarian),
+    ("italian", Language::Italian),
+    ("norwegian", Language::Norwegian),
+    ("portuguese", Language::Portuguese),
+    ("romanian", Language::Romanian),
+    ("russian", Language::Russian),
+    ("spanish", Language::Spanish),
+    ("swedish", Language::Swedish),
+    ("tamil", Language::Tamil),
+    ("turkish", Language::Turkish),
+];
+
+/// How note titles and content are split into searchable words.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerSettings {
+    /// Reduce words to their stem in this language (e.g. "english"), so
+    /// "running" finds "runs". `None` matches words as written.
+    #[serde(default)]
+    pub stemming: Option<String>,
+    /// Index Chinese, Japanese and Korean text as overlapping character pairs,
+    /// since it isn't separated into words by spaces.
+    #[serde(default)]
+    pub cjk: bool,
+    /// Fold accented letters to ASCII, so "cafe" finds "café".
+    #[serde(default)]
+    pub ascii_folding: bool,
+}
+
+impl AnalyzerSettings {
+    fn language(&self) -> Result<Option<Language>, String> {
+        let Some(name) = self.stemming.as_deref() else {
+            return Ok(None);
+        };
+        STEMMING_LANGUAGES
+            .iter()
+            .find(|(lang, _)| lang.eq_ignore_ascii_case(name.trim()))
+            .map(|(_, language)| Some(*language))
+            .ok_or_else(|| {
+                let supported: Vec<&str> =
+                    STEMMING_LANGUAGES.iter().map(|(lang, _)| *lang).collect();
+                format!(
+                    "Unsupported stemming language \"{}\" (supported: {})",
+                    name,
+                    supported.join(", ")
+                )
+            })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.language().map(|_| ())
+    }
+
+    /// Short identifier for the configuration. Indexes built with different
+    /// analyzers are kept apart, so changing it starts a fresh index.
+    pub fn key(&self) -> String {
+        let mut parts = Vec::new();
+        if let Ok(Some(_)) = self.language() {
+            parts.push(
+                self.stemming
+                    .as_deref()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_lowercase(),
+            );
+        }
+        if self.cjk {
+            parts.push("cjk".to_string());
+        }
+        if self.ascii_folding {
+            parts.push("fold".to_string());
+        }
+        if parts.is_empty() {
+            "default".to_string()
+        } else {
+            parts.join("-")
+        }
+    }
+
+    pub fn build(&self) -> TextAnalyzer {
+        let builder = if self.cjk {
+            TextAnalyzer::builder(CjkTokenizer).dynamic()
+        } else {
+            TextAnalyzer::builder(SimpleTokenizer::default()).dynamic()
+        };
+        let mut builder = builder
+            .filter_dynamic(RemoveLongFilter::limit(MAX_TOKEN_BYTES))
+            .filter_dynamic(LowerCaser);
+        if self.ascii_folding {
+            builder = builder.filter_dynamic(AsciiFoldingFilter);
+        }
+        if let Ok(Some(language)) = self.language() {
+            builder = builder.filter_dynamic(Stemmer::new(language));
+        }
+        builder.build()
+    }
+}
+
+/// Han, kana and Hangul: scripts written without spaces between words.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}'     // Hangul Jamo
+        | '\u{3040}'..='\u{30FF}'   // Hiragana, Katakana
+        | '\u{3130}'..='\u{318F}'   // Hangul Compatibility Jamo
+        | '\u{3400}'..='\u{4DBF}'   // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul Syllables
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{FF66}'..='\u{FF9F}'   // Halfwidth Katakana
+        | '\u{20000}'..='\u{2FFFF}' // CJK Extensions B and later
+    )
+}
+
+/// Splits other text into alphanumeric words like tantivy's simple tokenizer,
+/// and runs of CJK characters into overlapping pairs ("東京都" -> "東京", "京都").
+/// A lone CJK character is kept as a single token.
+#[derive(Clone)]
+pub struct CjkTokenizer;
+
+pub struct CjkTokenStream {
+    tokens: Vec<Token>,
+    current: usize, // 1-based; 0 before the first `advance`
+}
+
+impl Tokenizer for CjkTokenizer {
+    type TokenStream<'a> = CjkTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> CjkTokenStream {
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let byte_at = |i: usize| chars.get(i).map_or(text.len(), |(offset, _)| *offset);
+        let mut tokens = Vec::new();
+        let mut push = |from: usize, to: usize| {
+            let (offset_from, offset_to) = (byte_at(from), byte_at(to));
+            tokens.push(Token {
+                offset_from,
+                offset_to,
+                position: tokens.len(),
+                text: text[offset_from..offset_to].to_string(),
+                position_length: 1,
+            });
+        };
+
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i].1;
+            if is_cjk(c) {
+                let end = (i..chars.len())
+                    .find(|&j| !is_cjk(chars[j].1))
+                    .unwrap_or(chars.len());
+                if end - i == 1 {
+                    push(i, end);
+                } else {
+                    for start in i..end - 1 {
+                        push(start, start + 2);
+                    }
+                }
+                i = end;
+            } else if c.is_alphanumeric() {
+                let end = (i..chars.len())
+                    .find(|&j| !chars[j].1.is_alphanumeric() || is_cjk(chars[j].1))
+                    .unwrap_or(chars.len());
+                push(i, end);
+                i = end;
+            } else {
+                i += 1;
+            }
+        }
+
+        CjkTokenStream { tokens, current: 0 }
+    }
+}
+
+impl TokenStream for CjkTokenStream {
+    fn advance(&mut self) -> bool {
+        if self.current < self.tokens.len() {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.current - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.current - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (text, byte offsets, position) of each token.
+    fn cjk_tokens(text: &str) -> Vec<(String, usize, usize, usize)> {
+        let mut stream = CjkTokenizer.token_stream(text);
+        let mut tokens = Vec::new();
+        while stream.advance() {
+            let token = stream.token();
+            tokens.push((
+                token.text.clone(),
+                token.offset_from,
+                token.offset_to,
+                token.position,
+            ));
+        }
+        tokens
+    }
+
+    fn texts(text: &str) -> Vec<String> {
+        cjk_tokens(text).into_iter().map(|(t, ..)| t).collect()
+    }
+
+    fn analyzed(settings: &AnalyzerSettings, text: &str) -> Vec<String> {
+        let mut analyzer = settings.build();
+        let mut stream = analyzer.token_stream(text);
+        let mut tokens = Vec::new();
+        while stream.advance() {
+            tokens.push(stream.token().text.clone());
+        }
+        tokens
+    }
+
+    #[test]
+    fn splits_cjk_runs_into_bigrams() {
+        assert_eq!(texts("東京都"), vec!["東京", "京都"]);
+        assert_eq!(texts("한국어"), vec!["한국", "국어"]);
+        assert_eq!(texts("東京、大阪"), vec!["東京", "大阪"]);
+    }
+
+    #[test]
+    fn keeps_a_lone_cjk_character() {
+        assert_eq!(texts("猫"), vec!["猫"]);
+        assert_eq!(texts("a 猫 b"), vec!["a", "猫", "b"]);
+    }
+
+    #[test]
+    fn separates_latin_words_from_cjk_text() {
+        assert_eq!(
+            texts("Rust言語で書くnotes, v2"),
+            vec!["Rust", "言語", "語で", "で書", "書く", "notes", "v2"]
+        );
+        assert_eq!(texts("café au lait"), vec!["café", "au", "lait"]);
+        assert!(texts(" ,.! ").is_empty());
+    }
+
+    #[test]
+    fn offsets_are_byte_offsets_after_multibyte_characters() {
+        let text = "é東京 ok";
+        assert_eq!(
+            cjk_tokens(text),
+            vec![
+                ("é".to_string(), 0, 2, 0),
+                ("東京".to_string(), 2, 8, 1),
+                ("ok".to_string(), 9, 11, 2),
+            ]
+        );
+        for (token, from, to, _) in cjk_tokens(text) {
+            assert_eq!(&text[from..to], token);
+        }
+    }
+
+    #[test]
+    fn builds_the_configured_filters() {
+        let settings = AnalyzerSettings {
+            stemming: Some("English".to_string()),
+            cjk: true,
+            ascii_folding: true,
+        };
+        assert_eq!(
+            analyzed(&settings, "Running Cafés 東京都"),
+            vec!["run", "cafe", "東京", "京都"]
+        );
+        assert_eq!(
+            analyzed(&AnalyzerSettings::default(), "Running Cafés"),
+            vec!["running", "cafés"]
+        );
+    }
+
+    #[test]
+    fn keys_and_validation() {
+        assert_eq!(AnalyzerSettings::default().key(), "default");
+        let settings = AnalyzerSettings {
+            stemming: Some(" English ".to_string()),
+            cjk: true,
+            ascii_folding: true,
+        };
+        assert_eq!(settings.key(), "english-cjk-fold");
+        assert!(settings.validate().is_ok());
+
+        let unsupported = AnalyzerSettings {
+            stemming: Some("klingon".to_string()),
+            ..Default::default()
+        };
+        assert!(unsupported
+            .validate()
+            .unwrap_err()
+            .starts_with("Unsupported stemming language \"klingon\""));
+        // An unsupported language doesn't stem, so it shares the default index
+        assert_eq!(unsupported.key(), "default");
+    }
+}
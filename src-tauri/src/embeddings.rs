@@ -0,0 +1,835 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::indexer::IndexProgress;
+use crate::{extract_title, frontmatter, AppState, SearchIndex};
+
+/// Vectors live in app data, one file per notes folder and model:
+/// `embeddings/<folder key>-<model>.json`. They're derived data, so they stay
+/// out of the notes folder (and out of its git repository).
+const EMBEDDINGS_DIR: &str = "embeddings";
+
+pub const DEFAULT_MODEL: &str = "nomic-embed-text";
+const DEFAULT_OLLAMA_URL: &str = "http://127.0.0.1:11434";
+
+/// Notes are embedded in chunks of about this many characters, split at
+/// headings and blank lines.
+const CHUNK_CHARS: usize = 1000;
+/// Chunks sent to the embedder per request.
+const EMBED_BATCH: usize = 16;
+/// Characters of a chunk kept to show with results.
+const EXCERPT_CHARS: usize = 200;
+/// Send an `embedding-progress` event at most every this many notes.
+const PROGRESS_INTERVAL: usize = 20;
+
+/// Turns text into vectors. Implementations must run locally: notes never
+/// leave the machine.
+pub trait Embedder {
+    /// Identifies the model; vectors from different models are never compared.
+    fn model(&self) -> &str;
+    /// One vector per text, in order.
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String>;
+}
+
+/// Embeddings from a local Ollama server (`OLLAMA_HOST`, or the default port).
+pub struct OllamaEmbedder {
+    base_url: String,
+    model: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Serialize)]
+struct OllamaEmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+impl OllamaEmbedder {
+    pub fn new(model: &str) -> Self {
+        let base_url = match std::env::var("OLLAMA_HOST") {
+            Ok(host) if !host.trim().is_empty() => {
+                let host = host.trim().trim_end_matches('/');
+                if host.contains("://") {
+                    host.to_string()
+                } else {
+                    format!("http://{}", host)
+                }
+            }
+            _ => DEFAULT_OLLAMA_URL.to_string(),
+        };
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(2))
+            .timeout(Duration::from_secs(120))
+            .build();
+        Self {
+            base_url,
+            model: model.to_string(),
+            agent,
+        }
+    }
+}
+
+impl Embedder for OllamaEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let response = self
+            .agent
+            .post(&format!("{}/api/embed", self.base_url))
+            .send_json(OllamaEmbedRequest {
+                model: &self.model,
+                input: texts,
+            })
+            .map_err(|e| match e {
+                ureq::Error::Status(404, _) => format!(
+                    "Embedding model '{}' is not installed. Run: ollama pull {}",
+                    self.model, self.model
+                ),
+                ureq::Error::Status(code, response) => format!(
+                    "Ollama returned {}: {}",
+                    code,
+                    response.into_string().unwrap_or_default()
+                ),
+                ureq::Error::Transport(_) => {
+                    "Couldn't reach Ollama. Make sure it's installed and running.".to_string()
+                }
+            })?;
+        let body: OllamaEmbedResponse = response
+            .into_json()
+            .map_err(|e| format!("Unexpected response from Ollama: {}", e))?;
+        if body.embeddings.len() != texts.len() {
+            return Err("Ollama returned the wrong number of embeddings".to_string());
+        }
+        Ok(body.embeddings)
+    }
+}
+
+/// A piece of a note to embed.
+struct Chunk {
+    line: usize, // 1-based line in the note where the chunk starts
+    text: String,
+}
+
+/// Split a note (without frontmatter) into chunks of about `CHUNK_CHARS`,
+/// starting a new chunk at each heading.
+fn chunk_note(content: &str) -> Vec<Chunk> {
+    let body = frontmatter::body(content);
+    let first_line = content[..content.len() - body.len()].lines().count() + 1;
+
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut current: Option<Chunk> = None;
+    for (i, line) in body.lines().enumerate() {
+        let trimmed = line.trim();
+        let full = current
+            .as_ref()
+            .is_some_and(|c| c.text.chars().count() + trimmed.chars().count() > CHUNK_CHARS);
+        if trimmed.is_empty() || trimmed.starts_with('#') || full {
+            chunks.extend(current.take());
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        let chunk = current.get_or_insert_with(|| Chunk {
+            line: first_line + i,
+            text: String::new(),
+        });
+        if !chunk.text.is_empty() {
+            chunk.text.push('\n');
+        }
+        chunk.text.push_str(trimmed);
+    }
+    chunks.extend(current);
+
+    // Merge paragraphs into chunks up to the size limit, keeping headings as chunk starts
+    let mut merged: Vec<Chunk> = Vec::new();
+    for chunk in chunks {
+        match merged.last_mut() {
+            Some(last)
+                if !chunk.text.starts_with('#')
+                    && last.text.chars().count() + chunk.text.chars().count() < CHUNK_CHARS =>
+            {
+                last.text.push_str("\n\n");
+                last.text.push_str(&chunk.text);
+            }
+            _ => merged.push(chunk),
+        }
+    }
+    merged
+}
+
+/// Vectors are stored as base64 of their little-endian `f32`s, which is far
+/// smaller than JSON numbers.
+mod vector_base64 {
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(vector: &[f32], serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes: Vec<u8> = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredChunk {
+    line: usize,
+    excerpt: String,
+    #[serde(with = "vector_base64")]
+    vector: Vec<f32>, // Normalized, so a dot product is the cosine similarity
+}
+
+#[derive(Serialize, Deserialize)]
+struct NoteVectors {
+    // File (mtime ns, size) when embedded. Missing from older stores, whose
+    // notes are then embedded again but stay searchable until they are
+    #[serde(default)]
+    stamp: (u64, u64),
+    title: String,
+    chunks: Vec<StoredChunk>,
+}
+
+/// Embedded chunks of every note in a notes folder, for one model.
+#[derive(Serialize, Deserialize, Default)]
+pub struct EmbeddingStore {
+    model: String,
+    notes: HashMap<String, NoteVectors>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    dirty: bool, // Changed since last saved
+    #[serde(skip)]
+    last_error: Option<String>, // Why the last sync stopped early, if it did
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticResult {
+    pub id: String,
+    pub title: String,
+    pub score: f32,      // Cosine similarity of the best matching chunk
+    pub line: usize,     // Where that chunk starts
+    pub excerpt: String, // The start of that chunk
+}
+
+fn store_path(app_data: &Path, notes_folder: &Path, model: &str) -> PathBuf {
+    let model_slug: String = model
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    app_data.join(EMBEDDINGS_DIR).join(format!(
+        "{}-{}.json",
+        crate::index_store::folder_key(notes_folder),
+        model_slug
+    ))
+}
+
+fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+impl EmbeddingStore {
+    fn load(path: PathBuf, model: &str) -> Self {
+        let store = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<EmbeddingStore>(&content).ok())
+            .filter(|store| store.model == model);
+        Self {
+            path,
+            ..store.unwrap_or_else(|| EmbeddingStore {
+                model: model.to_string(),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let content = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        crate::fsutil::write_atomic(&self.path, content.as_bytes()).map_err(|e| e.to_string())
+    }
+
+    /// Drop notes that are gone and list those added or changed since they
+    /// were embedded.
+    fn changed_notes(
+        &mut self,
+        files: Vec<(String, PathBuf, (u64, u64))>,
+    ) -> Vec<(String, PathBuf, (u64, u64))> {
+        let before = self.notes.len();
+        {
+            let current: HashSet<&str> = files.iter().map(|(id, _, _)| id.as_str()).collect();
+            self.notes.retain(|id, _| current.contains(id.as_str()));
+        }
+        self.dirty |= self.notes.len() != before;
+
+        files
+            .into_iter()
+            .filter(|(id, _, stamp)| self.notes.get(id).is_none_or(|n| n.stamp != *stamp))
+            .collect()
+    }
+
+    /// Notes ranked by their chunk most similar to `query` (normalized).
+    fn rank(&self, query: &[f32], exclude: Option<&str>, limit: usize) -> Vec<SemanticResult> {
+        let mut results: Vec<SemanticResult> = self
+            .notes
+            .iter()
+            .filter(|(id, _)| Some(id.as_str()) != exclude)
+            .filter_map(|(id, note)| {
+                let (score, best) = note
+                    .chunks
+                    .iter()
+                    .map(|chunk| (dot(query, &chunk.vector), chunk))
+                    .max_by(|a, b| a.0.total_cmp(&b.0))?;
+                Some(SemanticResult {
+                    id: id.clone(),
+                    title: note.title.clone(),
+                    score,
+                    line: best.line,
+                    excerpt: best.excerpt.clone(),
+                })
+            })
+            .collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        results.truncate(limit);
+        results
+    }
+
+    /// Notes closest in meaning to note `id`, compared by the average of its chunks.
+    fn related(&self, id: &str, limit: usize) -> Result<Vec<SemanticResult>, String> {
+        let Some(note) = self.notes.get(id) else {
+            return Err(self
+                .last_error
+                .clone()
+                .unwrap_or_else(|| "This note hasn't been embedded yet".to_string()));
+        };
+        if note.chunks.is_empty() {
+            return Err("This note has no text to compare".to_string());
+        }
+        let mut centroid = vec![0.0; note.chunks[0].vector.len()];
+        for chunk in &note.chunks {
+            for (sum, v) in centroid.iter_mut().zip(&chunk.vector) {
+                *sum += v;
+            }
+        }
+        Ok(self.rank(&normalize(centroid), Some(id), limit))
+    }
+}
+
+/// The store in `slot`, loading the one at `path` if another (or none) is loaded.
+fn store_for<'a>(
+    slot: &'a mut Option<EmbeddingStore>,
+    path: &Path,
+    model: &str,
+) -> &'a mut EmbeddingStore {
+    if slot.as_ref().is_none_or(|store| store.path != path) {
+        if let Some(previous) = slot.as_ref().filter(|store| store.dirty) {
+            let _ = previous.save();
+        }
+        *slot = Some(EmbeddingStore::load(path.to_path_buf(), model));
+    }
+    slot.as_mut().expect("embedding store")
+}
+
+/// Embed notes added or changed since they were last embedded, and drop
+/// deleted ones. The store is only locked between notes, so searches can rank
+/// what's stored meanwhile. What's done is kept even if the embedder fails partway.
+fn sync(
+    slot: &Mutex<Option<EmbeddingStore>>,
+    ctx: &Context,
+    progress: &mut dyn FnMut(usize, usize),
+) -> Result<(), String> {
+    let path = store_path(&ctx.app_data, &ctx.notes_folder, ctx.embedder.model());
+    let changed = {
+        let mut guard = slot.lock().expect("embeddings mutex");
+        store_for(&mut guard, &path, ctx.embedder.model()).changed_notes(SearchIndex::note_files(
+            &ctx.notes_folder,
+            &ctx.ignored_dirs,
+        ))
+    };
+    let total = changed.len();
+
+    let mut result = Ok(());
+    for (done, (id, file, stamp)) in changed.into_iter().enumerate() {
+        if done % PROGRESS_INTERVAL == 0 {
+            progress(done, total);
+        }
+        let Ok(content) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+        let embedded = embed_note(&*ctx.embedder, &content);
+
+        let mut guard = slot.lock().expect("embeddings mutex");
+        // Another folder or model took over meanwhile, and gets its own sync
+        let Some(store) = guard.as_mut().filter(|store| store.path == path) else {
+            return Ok(());
+        };
+        match embedded {
+            Ok(chunks) => {
+                store.notes.insert(
+                    id,
+                    NoteVectors {
+                        stamp,
+                        title: extract_title(&content),
+                        chunks,
+                    },
+                );
+                store.dirty = true;
+            }
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    if total > 0 {
+        progress(total, total);
+    }
+
+    let mut guard = slot.lock().expect("embeddings mutex");
+    if let Some(store) = guard.as_mut().filter(|store| store.path == path) {
+        store.last_error = result.as_ref().err().cloned();
+        if store.dirty {
+            store.save()?;
+            store.dirty = false;
+        }
+    }
+    result
+}
+
+fn embed_note(embedder: &dyn Embedder, content: &str) -> Result<Vec<StoredChunk>, String> {
+    let chunks = chunk_note(content);
+    let mut stored = Vec::with_capacity(chunks.len());
+    for batch in chunks.chunks(EMBED_BATCH) {
+        let texts: Vec<String> = batch.iter().map(|c| c.text.clone()).collect();
+        for (chunk, vector) in batch.iter().zip(embedder.embed(&texts)?) {
+            stored.push(StoredChunk {
+                line: chunk.line,
+                excerpt: chunk.text.chars().take(EXCERPT_CHARS).collect(),
+                vector: normalize(vector),
+            });
+        }
+    }
+    Ok(stored)
+}
+
+/// Where and with what to embed the current notes folder.
+pub struct Context {
+    pub app_data: PathBuf,
+    pub notes_folder: PathBuf,
+    pub ignored_dirs: Vec<String>,
+    pub embedder: Box<dyn Embedder + Send>,
+}
+
+/// Queue bringing the embeddings for `ctx` up to date on the embedding
+/// worker, starting the worker on first use. It's separate from the search
+/// indexing worker, so a slow embedder never holds up search.
+pub fn queue_sync(app: &AppHandle, state: &AppState, mut ctx: Context) {
+    let mut sender = state.embedding_jobs.lock().expect("embedding jobs mutex");
+    if let Some(tx) = sender.as_ref() {
+        match tx.send(ctx) {
+            Ok(()) => return,
+            // The worker is gone (it panicked); start a new one
+            Err(mpsc::SendError(returned)) => ctx = returned,
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let worker_app = app.clone();
+    std::thread::spawn(move || run(worker_app, rx));
+    let _ = tx.send(ctx);
+    *sender = Some(tx);
+}
+
+fn run(app: AppHandle, jobs: Receiver<Context>) {
+    while let Ok(first) = jobs.recv() {
+        // Requests that piled up during the last sync are covered by one
+        let ctx = jobs.try_iter().last().unwrap_or(first);
+        let state = app.state::<AppState>();
+        let mut report = |done: usize, total: usize| {
+            let _ = app.emit("embedding-progress", IndexProgress { done, total });
+        };
+        if let Err(e) = sync(&state.embeddings, &ctx, &mut report) {
+            eprintln!("Embedding notes failed: {}", e);
+        }
+    }
+}
+
+/// Catch up with changed notes in the background, once semantic search has
+/// been used (so the embedder isn't contacted otherwise).
+pub fn notes_changed(app: &AppHandle, state: &AppState) {
+    if state.embeddings.lock().expect("embeddings mutex").is_none() {
+        return;
+    }
+    if let Ok(ctx) = crate::embedding_context(app, state) {
+        queue_sync(app, state, ctx);
+    }
+}
+
+/// Run `f` with the stored vectors for the folder and model in `ctx`.
+fn with_store<T>(app: &AppHandle, ctx: &Context, f: impl FnOnce(&EmbeddingStore) -> T) -> T {
+    let state = app.state::<AppState>();
+    let mut guard = state.embeddings.lock().expect("embeddings mutex");
+    let path = store_path(&ctx.app_data, &ctx.notes_folder, ctx.embedder.model());
+    f(store_for(&mut guard, &path, ctx.embedder.model()))
+}
+
+/// Notes whose meaning is closest to `query`, among those embedded so far.
+/// Notes changed since are embedded afterwards in the background.
+pub fn semantic_search(
+    app: &AppHandle,
+    ctx: Context,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<SemanticResult>, String> {
+    let query_vector = ctx
+        .embedder
+        .embed(&[query.to_string()])?
+        .pop()
+        .map(normalize)
+        .ok_or("No embedding returned for the query")?;
+    let results = with_store(app, &ctx, |store| store.rank(&query_vector, None, limit));
+    queue_sync(app, &app.state::<AppState>(), ctx);
+    Ok(results)
+}
+
+/// Notes closest in meaning to note `id`, from stored embeddings. Notes changed
+/// since are embedded afterwards in the background.
+pub fn related_notes(
+    app: &AppHandle,
+    ctx: Context,
+    id: &str,
+    limit: usize,
+) -> Result<Vec<SemanticResult>, String> {
+    let related = with_store(app, &ctx, |store| store.related(id, limit));
+    queue_sync(app, &app.state::<AppState>(), ctx);
+    related
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::{Hash, Hasher};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Stands in for a model: each word adds to one of a few dimensions picked
+    /// by its hash, so texts sharing words get similar vectors. Texts
+    /// containing "FAIL" make the whole request fail.
+    struct StubEmbedder {
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl Embedder for StubEmbedder {
+        fn model(&self) -> &str {
+            "stub"
+        }
+
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            if texts.iter().any(|text| text.contains("FAIL")) {
+                return Err("Embedder unavailable".to_string());
+            }
+            Ok(texts.iter().map(|text| word_vector(text)).collect())
+        }
+    }
+
+    fn word_vector(text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; 16];
+        for word in text.split_whitespace() {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            word.trim_matches('#').to_lowercase().hash(&mut hasher);
+            vector[(hasher.finish() % 16) as usize] += 1.0;
+        }
+        vector
+    }
+
+    /// A notes folder and app data directory, removed when dropped.
+    struct Dirs {
+        root: PathBuf,
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl Dirs {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "scratch-embeddings-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(root.join("notes")).unwrap();
+            Dirs {
+                root,
+                requests: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn write(&self, id: &str, content: &str, modified: u64) {
+            let path = self.root.join("notes").join(format!("{}.md", id));
+            std::fs::write(&path, content).unwrap();
+            let time = std::time::UNIX_EPOCH + Duration::from_secs(modified);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        }
+
+        fn context(&self) -> Context {
+            Context {
+                app_data: self.root.join("app"),
+                notes_folder: self.root.join("notes"),
+                ignored_dirs: vec![],
+                embedder: Box::new(StubEmbedder {
+                    requests: self.requests.clone(),
+                }),
+            }
+        }
+
+        fn requests(&self) -> usize {
+            self.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Drop for Dirs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn stored_ids(slot: &Mutex<Option<EmbeddingStore>>) -> Vec<String> {
+        let guard = slot.lock().unwrap();
+        let mut ids: Vec<String> = guard.as_ref().unwrap().notes.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    fn chunks(content: &str) -> Vec<(usize, String)> {
+        chunk_note(content)
+            .into_iter()
+            .map(|chunk| (chunk.line, chunk.text))
+            .collect()
+    }
+
+    #[test]
+    fn chunks_start_at_headings_and_count_frontmatter_lines() {
+        let content = "---\ntitle: Plan\n---\n# One\n  para a\n\npara b\n## Two\ntext\n";
+        assert_eq!(
+            chunks(content),
+            vec![
+                (4, "# One\npara a\n\npara b".to_string()),
+                (8, "## Two\ntext".to_string()),
+            ]
+        );
+        assert_eq!(chunks("\n\n"), vec![]);
+    }
+
+    #[test]
+    fn chunks_merge_paragraphs_up_to_the_size_limit() {
+        let line = "w".repeat(300);
+        let paragraph = [line.as_str(); 5].join("\n");
+        assert_eq!(
+            chunks(&paragraph),
+            vec![
+                (1, [line.as_str(); 3].join("\n")),
+                (4, [line.as_str(); 2].join("\n")),
+            ]
+        );
+
+        let short = "s".repeat(400);
+        let content = format!("{}\n\n{}\n\n{}\n", short, short, short);
+        assert_eq!(
+            chunks(&content),
+            vec![(1, format!("{}\n\n{}", short, short)), (5, short.clone()),]
+        );
+    }
+
+    #[test]
+    fn sync_embeds_changed_notes_and_drops_deleted_ones() {
+        let dirs = Dirs::new("sync");
+        dirs.write("a", "# A\napples and pears", 100);
+        dirs.write("b", "# B\nboats", 100);
+        let slot = Mutex::new(None);
+        let ctx = dirs.context();
+
+        let mut reports = Vec::new();
+        sync(&slot, &ctx, &mut |done, total| reports.push((done, total))).unwrap();
+        assert_eq!(stored_ids(&slot), vec!["a", "b"]);
+        assert_eq!(reports, vec![(0, 2), (2, 2)]);
+        assert_eq!(dirs.requests(), 2);
+
+        // Nothing changed: nothing embedded
+        sync(&slot, &ctx, &mut |_, _| {}).unwrap();
+        assert_eq!(dirs.requests(), 2);
+
+        // Same mtime to the second, but a different size
+        dirs.write("a", "# A\napples, pears and plums", 100);
+        sync(&slot, &ctx, &mut |_, _| {}).unwrap();
+        assert_eq!(dirs.requests(), 3);
+
+        dirs.write("b", "# Boats\nsail", 200);
+        std::fs::remove_file(dirs.root.join("notes/a.md")).unwrap();
+        sync(&slot, &ctx, &mut |_, _| {}).unwrap();
+        assert_eq!(dirs.requests(), 4);
+        assert_eq!(stored_ids(&slot), vec!["b"]);
+
+        // Saved, and loaded back by a fresh store
+        let path = store_path(&ctx.app_data, &ctx.notes_folder, "stub");
+        let loaded = EmbeddingStore::load(path, "stub");
+        assert_eq!(loaded.notes["b"].title, "Boats");
+        assert_eq!(loaded.notes["b"].stamp, (200_000_000_000, 12));
+        assert!(!loaded.notes.contains_key("a"));
+    }
+
+    #[test]
+    fn sync_keeps_what_was_embedded_when_the_embedder_fails() {
+        let dirs = Dirs::new("failure");
+        dirs.write("good", "fine", 100);
+        dirs.write("bad", "FAIL", 100);
+        let slot = Mutex::new(None);
+        let ctx = dirs.context();
+
+        assert_eq!(
+            sync(&slot, &ctx, &mut |_, _| {}),
+            Err("Embedder unavailable".to_string())
+        );
+        let embedded = stored_ids(&slot);
+        assert!(!embedded.contains(&"bad".to_string()));
+        {
+            let guard = slot.lock().unwrap();
+            let store = guard.as_ref().unwrap();
+            assert_eq!(store.last_error.as_deref(), Some("Embedder unavailable"));
+            assert!(!store.dirty);
+            assert_eq!(store.related("bad", 5).unwrap_err(), "Embedder unavailable");
+        }
+        let path = store_path(&ctx.app_data, &ctx.notes_folder, "stub");
+        let mut saved: Vec<String> = EmbeddingStore::load(path, "stub")
+            .notes
+            .into_keys()
+            .collect();
+        saved.sort();
+        assert_eq!(saved, embedded);
+
+        dirs.write("bad", "fixed", 200);
+        sync(&slot, &ctx, &mut |_, _| {}).unwrap();
+        assert_eq!(stored_ids(&slot), vec!["bad", "good"]);
+        assert_eq!(slot.lock().unwrap().as_ref().unwrap().last_error, None);
+    }
+
+    fn store(notes: &[(&str, &[&str])]) -> EmbeddingStore {
+        let notes = notes
+            .iter()
+            .map(|(id, texts)| {
+                let chunks = texts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, text)| StoredChunk {
+                        line: i + 1,
+                        excerpt: text.to_string(),
+                        vector: normalize(word_vector(text)),
+                    })
+                    .collect();
+                let vectors = NoteVectors {
+                    stamp: (0, 0),
+                    title: id.to_uppercase(),
+                    chunks,
+                };
+                (id.to_string(), vectors)
+            })
+            .collect();
+        EmbeddingStore {
+            model: "stub".to_string(),
+            notes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ranks_notes_by_their_best_chunk() {
+        let store = store(&[
+            ("fruit", &["pears", "apples and oranges"]),
+            ("boats", &["sailing boats"]),
+            ("empty", &[]),
+            ("same", &["apples and oranges"]),
+        ]);
+        let query = normalize(word_vector("apples oranges"));
+
+        let ranked = store.rank(&query, None, 10);
+        let order: Vec<&str> = ranked.iter().map(|r| r.id.as_str()).collect();
+        // Equal scores fall back to ID order; notes without chunks are left out
+        assert_eq!(order, vec!["fruit", "same", "boats"]);
+        assert_eq!((ranked[0].line, ranked[0].title.as_str()), (2, "FRUIT"));
+        assert_eq!(ranked[0].excerpt, "apples and oranges");
+        assert!(ranked[0].score > ranked[2].score);
+
+        let ranked = store.rank(&query, Some("fruit"), 1);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].id, "same");
+    }
+
+    #[test]
+    fn related_notes_compare_the_average_of_a_notes_chunks() {
+        let store = store(&[
+            ("trip", &["sailing boats", "harbour map"]),
+            ("sea", &["boats harbour"]),
+            ("food", &["apples"]),
+            ("empty", &[]),
+        ]);
+        let related: Vec<String> = store
+            .related("trip", 5)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(related[0], "sea");
+        assert!(!related.contains(&"trip".to_string()));
+
+        assert_eq!(
+            store.related("empty", 5).unwrap_err(),
+            "This note has no text to compare"
+        );
+        assert_eq!(
+            store.related("missing", 5).unwrap_err(),
+            "This note hasn't been embedded yet"
+        );
+    }
+}
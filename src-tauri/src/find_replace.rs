@@ -0,0 +1,642 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::snippets::HighlightRange;
+use crate::{
+    abs_path_from_id, extract_title, id_from_abs_path, is_visible_notes_entry, validate_folder_path,
+};
+
+/// Most matches returned by one search, so a very common pattern can't flood the UI.
+const MAX_MATCHES: usize = 5000;
+/// Characters of the line kept before a match, and the longest context, in characters.
+const CONTEXT_BEFORE_CHARS: usize = 40;
+const MAX_CONTEXT_CHARS: usize = 150;
+
+/// Undo records live in `.scratch/replace-undo/<token>.json`; only the most
+/// recent ones are kept. Tokens are `<unix millis>-<sequence>`.
+const UNDO_DIR: &str = "replace-undo";
+const MAX_UNDO_RECORDS: usize = 20;
+
+/// Tells apart undo records saved within the same millisecond.
+static UNDO_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FindOptions {
+    pub regex: bool, // Pattern is a regular expression rather than literal text
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub folder: Option<String>, // Only notes in this folder (and below)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultMatch {
+    pub line: usize,               // 1-based
+    pub column: usize,             // 1-based, in UTF-16 code units
+    pub length: usize,             // In UTF-16 code units
+    pub context: String,           // The line around the match
+    pub highlight: HighlightRange, // The match within `context`
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteMatches {
+    pub id: String,
+    pub title: String,
+    pub matches: Vec<VaultMatch>,
+}
+
+/// A match as returned by `find`, to pick which matches a replace applies to.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Hash)]
+pub struct MatchRef {
+    pub id: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReplaceSelection {
+    pub options: FindOptions,
+    /// Matches to replace; `None` replaces every match. Matches that moved
+    /// since they were found are left alone.
+    pub matches: Option<Vec<MatchRef>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceResult {
+    pub undo_token: Option<String>, // `None` if nothing was replaced
+    pub changed_ids: Vec<String>,
+    pub replaced: usize,
+}
+
+/// A pending change to one note.
+pub struct Edit {
+    pub id: String,
+    pub path: PathBuf,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct UndoRecord {
+    created: i64, // Unix seconds
+    edits: Vec<UndoEdit>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UndoEdit {
+    pub id: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// Compile the pattern with its options. Matching is per line, so a regex
+/// never spans a line break.
+pub fn matcher(pattern: &str, options: &FindOptions) -> Result<Regex, String> {
+    if pattern.is_empty() {
+        return Err("Search pattern is empty".to_string());
+    }
+    let mut source = if options.regex {
+        pattern.to_string()
+    } else {
+        regex::escape(pattern)
+    };
+    if options.whole_word {
+        source = format!(r"\b(?:{})\b", source);
+    }
+    RegexBuilder::new(&source)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid regular expression: {}", e))
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// Lines of `content` without their terminators, with the terminators.
+fn lines(content: &str) -> impl Iterator<Item = (&str, &str)> {
+    content.split_inclusive('\n').map(|raw| {
+        let line = raw.trim_end_matches(['\n', '\r']);
+        (line, &raw[line.len()..])
+    })
+}
+
+/// The part of `line` shown around a match, and the match within it.
+fn context(line: &str, m: &Range<usize>) -> (String, HighlightRange) {
+    let start = line[..m.start]
+        .char_indices()
+        .rev()
+        .nth(CONTEXT_BEFORE_CHARS.saturating_sub(1))
+        .map_or(0, |(idx, _)| idx);
+    let end = line[start..]
+        .char_indices()
+        .nth(MAX_CONTEXT_CHARS)
+        .map_or(line.len(), |(idx, _)| start + idx)
+        .max(m.end);
+
+    let text = &line[start..end];
+    let highlight = HighlightRange {
+        start: utf16_len(&line[start..m.start]),
+        end: utf16_len(&line[start..m.end]),
+    };
+    (text.to_string(), highlight)
+}
+
+/// Every (non-empty) match in a note.
+pub fn find_in_content(re: &Regex, content: &str) -> Vec<VaultMatch> {
+    let mut matches = Vec::new();
+    for (i, (line, _)) in lines(content).enumerate() {
+        for m in re.find_iter(line).filter(|m| !m.is_empty()) {
+            let (context, highlight) = context(line, &m.range());
+            matches.push(VaultMatch {
+                line: i + 1,
+                column: utf16_len(&line[..m.start()]) + 1,
+                length: utf16_len(m.as_str()),
+                context,
+                highlight,
+            });
+        }
+    }
+    matches
+}
+
+/// Replace matches in a note. With `expand`, `$1`/`$name` in the replacement
+/// refer to capture groups. `selected` limits the replacement to matches at
+/// those (line, column) positions. Returns the new content and the number of
+/// replacements.
+pub fn replace_in_content(
+    re: &Regex,
+    content: &str,
+    replacement: &str,
+    expand: bool,
+    selected: Option<&HashSet<(usize, usize)>>,
+) -> (String, usize) {
+    let mut updated = String::with_capacity(content.len());
+    let mut count = 0;
+
+    for (i, (line, terminator)) in lines(content).enumerate() {
+        let mut last = 0;
+        for caps in re.captures_iter(line) {
+            let Some(m) = caps.get(0).filter(|m| !m.is_empty()) else {
+                continue;
+            };
+            let column = utf16_len(&line[..m.start()]) + 1;
+            if selected.is_some_and(|s| !s.contains(&(i + 1, column))) {
+                continue;
+            }
+            updated.push_str(&line[last..m.start()]);
+            if expand {
+                caps.expand(replacement, &mut updated);
+            } else {
+                updated.push_str(replacement);
+            }
+            last = m.end();
+            count += 1;
+        }
+        updated.push_str(&line[last..]);
+        updated.push_str(terminator);
+    }
+
+    (updated, count)
+}
+
+/// Note files to search: the whole notes folder, or one folder in it.
+pub fn note_files(
+    notes_root: &Path,
+    ignored_dirs: &[String],
+    folder: Option<&str>,
+) -> Result<Vec<(String, PathBuf)>, String> {
+    let walk_root = match folder
+        .map(|f| f.trim_matches('/'))
+        .filter(|f| !f.is_empty())
+    {
+        Some(folder) => {
+            validate_folder_path(folder)?;
+            notes_root.join(folder.replace('/', std::path::MAIN_SEPARATOR_STR))
+        }
+        None => notes_root.to_path_buf(),
+    };
+    if !walk_root.is_dir() {
+        return Err("Folder not found".to_string());
+    }
+
+    use walkdir::WalkDir;
+    let mut files: Vec<(String, PathBuf)> = WalkDir::new(&walk_root)
+        .max_depth(10)
+        .into_iter()
+        .filter_entry(|e| is_visible_notes_entry(e, ignored_dirs))
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| {
+            let id = id_from_abs_path(notes_root, entry.path(), ignored_dirs)?;
+            Some((id, entry.into_path()))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Search every note for a pattern, grouped by note in ID order.
+pub fn find(
+    notes_root: &Path,
+    ignored_dirs: &[String],
+    pattern: &str,
+    options: &FindOptions,
+) -> Result<Vec<NoteMatches>, String> {
+    let re = matcher(pattern, options)?;
+    let mut results = Vec::new();
+    let mut total = 0;
+
+    for (id, path) in note_files(notes_root, ignored_dirs, options.folder.as_deref())? {
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let mut matches = find_in_content(&re, &content);
+        if matches.is_empty() {
+            continue;
+        }
+        matches.truncate(MAX_MATCHES - total);
+        total += matches.len();
+        results.push(NoteMatches {
+            id,
+            title: extract_title(&content),
+            matches,
+        });
+        if total >= MAX_MATCHES {
+            break;
+        }
+    }
+
+    Ok(results)
+}
+
+/// Work out the edits for a replacement without writing anything.
+pub fn plan_replace(
+    notes_root: &Path,
+    ignored_dirs: &[String],
+    pattern: &str,
+    replacement: &str,
+    selection: &ReplaceSelection,
+) -> Result<(Vec<Edit>, usize), String> {
+    let re = matcher(pattern, &selection.options)?;
+
+    // Note ID -> selected (line, column) positions
+    let selected: Option<std::collections::HashMap<&str, HashSet<(usize, usize)>>> =
+        selection.matches.as_ref().map(|refs| {
+            let mut by_note: std::collections::HashMap<&str, HashSet<(usize, usize)>> =
+                Default::default();
+            for r in refs {
+                by_note
+                    .entry(r.id.as_str())
+                    .or_default()
+                    .insert((r.line, r.column));
+            }
+            by_note
+        });
+
+    let files = match &selected {
+        // Only the selected notes need reading
+        Some(by_note) => {
+            let mut ids: Vec<&str> = by_note.keys().copied().collect();
+            ids.sort();
+            ids.into_iter()
+                .map(|id| Ok((id.to_string(), abs_path_from_id(notes_root, id)?)))
+                .collect::<Result<Vec<_>, String>>()?
+        }
+        None => note_files(
+            notes_root,
+            ignored_dirs,
+            selection.options.folder.as_deref(),
+        )?,
+    };
+
+    let mut edits = Vec::new();
+    let mut replaced = 0;
+    for (id, path) in files {
+        let Ok(before) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let positions = selected
+            .as_ref()
+            .and_then(|by_note| by_note.get(id.as_str()));
+        let (after, count) = replace_in_content(
+            &re,
+            &before,
+            replacement,
+            selection.options.regex,
+            positions,
+        );
+        if count > 0 && after != before {
+            replaced += count;
+            edits.push(Edit {
+                id,
+                path,
+                before,
+                after,
+            });
+        }
+    }
+    Ok((edits, replaced))
+}
+
+/// Write every edit, or none: if a write fails, notes already written are
+/// put back the way they were.
+pub fn apply(edits: &[Edit]) -> Result<(), String> {
+    for (i, edit) in edits.iter().enumerate() {
+        if let Err(e) = crate::fsutil::write_atomic(&edit.path, edit.after.as_bytes()) {
+            for done in &edits[..i] {
+                let _ = crate::fsutil::write_atomic(&done.path, done.before.as_bytes());
+            }
+            return Err(format!("Failed to write {}: {}", edit.id, e));
+        }
+    }
+    Ok(())
+}
+
+fn undo_dir(notes_root: &Path) -> PathBuf {
+    notes_root.join(".scratch").join(UNDO_DIR)
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Record applied edits so they can be undone. Returns the undo token.
+pub fn save_undo(notes_root: &Path, edits: &[Edit]) -> Result<String, String> {
+    let dir = undo_dir(notes_root);
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    // Claim a new record file; `create_new` never replaces an existing record
+    let (token, path) = loop {
+        let token = format!(
+            "{}-{}",
+            now_millis(),
+            UNDO_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(format!("{}.json", token));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(_) => break (token, path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let record = UndoRecord {
+        created: now_millis() / 1000,
+        edits: edits
+            .iter()
+            .map(|e| UndoEdit {
+                id: e.id.clone(),
+                before: e.before.clone(),
+                after: e.after.clone(),
+            })
+            .collect(),
+    };
+    let written = serde_json::to_string(&record)
+        .map_err(|e| e.to_string())
+        .and_then(|content| {
+            crate::fsutil::write_atomic(&path, content.as_bytes()).map_err(|e| e.to_string())
+        });
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
+    }
+
+    prune_undo(notes_root);
+    Ok(token)
+}
+
+/// Keep only the newest `MAX_UNDO_RECORDS` undo records.
+fn prune_undo(notes_root: &Path) {
+    let Ok(entries) = std::fs::read_dir(undo_dir(notes_root)) else {
+        return;
+    };
+    let mut records: Vec<((i64, u64), PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let (millis, sequence) = path.file_stem()?.to_str()?.split_once('-')?;
+            Some(((millis.parse().ok()?, sequence.parse().ok()?), path))
+        })
+        .collect();
+    records.sort_by_key(|(order, _)| std::cmp::Reverse(*order));
+    for (_, path) in records.into_iter().skip(MAX_UNDO_RECORDS) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn undo_path(notes_root: &Path, token: &str) -> Result<PathBuf, String> {
+    let valid = token.split_once('-').is_some_and(|(millis, sequence)| {
+        [millis, sequence]
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+    });
+    if !valid {
+        return Err("Invalid undo token".to_string());
+    }
+    Ok(undo_dir(notes_root).join(format!("{}.json", token)))
+}
+
+pub fn load_undo(notes_root: &Path, token: &str) -> Result<Vec<UndoEdit>, String> {
+    let content = std::fs::read_to_string(undo_path(notes_root, token)?)
+        .map_err(|_| "This replacement can no longer be undone".to_string())?;
+    let record: UndoRecord = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    Ok(record.edits)
+}
+
+pub fn remove_undo(notes_root: &Path, token: &str) {
+    if let Ok(path) = undo_path(notes_root, token) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch notes folder, removed when dropped.
+    struct Vault(PathBuf);
+
+    impl Vault {
+        fn new(name: &str, notes: &[(&str, &str)]) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "scratch-find-replace-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            for (id, content) in notes {
+                let path = root.join(format!("{}.md", id));
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, content).unwrap();
+            }
+            Vault(root)
+        }
+    }
+
+    impl Drop for Vault {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn selection(
+        options: FindOptions,
+        matches: Option<Vec<(&str, usize, usize)>>,
+    ) -> ReplaceSelection {
+        ReplaceSelection {
+            options,
+            matches: matches.map(|refs| {
+                refs.into_iter()
+                    .map(|(id, line, column)| MatchRef {
+                        id: id.to_string(),
+                        line,
+                        column,
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    fn changes(edits: &[Edit]) -> Vec<(&str, &str)> {
+        edits
+            .iter()
+            .map(|e| (e.id.as_str(), e.after.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn plans_replacing_every_match() {
+        let vault = Vault::new(
+            "all",
+            &[
+                ("a", "# A\r\nfoo Foo\r\nfood\r\n"),
+                ("work/b", "# B\nno match\n"),
+                ("work/c", "foo"),
+            ],
+        );
+        let options = FindOptions {
+            whole_word: true,
+            ..Default::default()
+        };
+        let (edits, replaced) =
+            plan_replace(&vault.0, &[], "foo", "bar", &selection(options, None)).unwrap();
+        assert_eq!(replaced, 3);
+        assert_eq!(
+            changes(&edits),
+            vec![("a", "# A\r\nbar bar\r\nfood\r\n"), ("work/c", "bar")]
+        );
+        // Nothing is written until the edits are applied
+        assert_eq!(
+            std::fs::read_to_string(vault.0.join("work/c.md")).unwrap(),
+            "foo"
+        );
+    }
+
+    #[test]
+    fn plans_only_selected_matches_in_the_folder() {
+        let vault = Vault::new(
+            "selected",
+            &[
+                ("a", "x foo foo\nfoo\n"),
+                ("work/b", "foo"),
+                ("other", "foo"),
+            ],
+        );
+        let options = FindOptions {
+            case_sensitive: true,
+            folder: Some("/work/".to_string()),
+            ..Default::default()
+        };
+        let (edits, replaced) = plan_replace(
+            &vault.0,
+            &[],
+            "foo",
+            "bar",
+            &selection(options.clone(), None),
+        )
+        .unwrap();
+        assert_eq!((changes(&edits), replaced), (vec![("work/b", "bar")], 1));
+
+        // Line 1 column 7 is the second "foo"; line 9 no longer exists
+        let picked = Some(vec![("a", 1, 7), ("a", 9, 1), ("other", 1, 1)]);
+        let (edits, replaced) =
+            plan_replace(&vault.0, &[], "foo", "bar", &selection(options, picked)).unwrap();
+        assert_eq!(
+            (changes(&edits), replaced),
+            (vec![("a", "x foo bar\nfoo\n"), ("other", "bar")], 2)
+        );
+    }
+
+    #[test]
+    fn plans_regex_replacements_with_capture_groups() {
+        let vault = Vault::new("regex", &[("a", "2024-01-31 and 1999-12-01\n")]);
+        let options = FindOptions {
+            regex: true,
+            ..Default::default()
+        };
+        let (edits, replaced) = plan_replace(
+            &vault.0,
+            &[],
+            r"(\d{4})-(?P<month>\d{2})-(\d{2})",
+            "$3/${month}/$1",
+            &selection(options, None),
+        )
+        .unwrap();
+        assert_eq!(
+            (changes(&edits), replaced),
+            (vec![("a", "31/01/2024 and 01/12/1999\n")], 2)
+        );
+        assert!(plan_replace(
+            &vault.0,
+            &[],
+            "(",
+            "",
+            &selection(
+                FindOptions {
+                    regex: true,
+                    ..Default::default()
+                },
+                None
+            )
+        )
+        .is_err());
+        assert!(plan_replace(&vault.0, &[], "", "x", &ReplaceSelection::default()).is_err());
+    }
+
+    #[test]
+    fn undo_tokens_are_unique() {
+        let vault = Vault::new("undo", &[("a", "after")]);
+        let edits = [Edit {
+            id: "a".to_string(),
+            path: vault.0.join("a.md"),
+            before: "before".to_string(),
+            after: "after".to_string(),
+        }];
+        let first = save_undo(&vault.0, &edits).unwrap();
+        let second = save_undo(&vault.0, &edits).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(load_undo(&vault.0, &first).unwrap()[0].before, "before");
+
+        remove_undo(&vault.0, &first);
+        assert!(load_undo(&vault.0, &first).is_err());
+        assert!(load_undo(&vault.0, &second).is_ok());
+        assert!(load_undo(&vault.0, "../x").is_err());
+        assert!(load_undo(&vault.0, "123").is_err());
+    }
+}
@@ -0,0 +1,366 @@
+use serde_json::{Map, Value};
+
+/// Parsed frontmatter fields. YAML values are mapped onto JSON values
+/// (non-string keys are stringified) so they pass straight through to the frontend.
+pub type Frontmatter = Map<String, Value>;
+
+/// Byte ranges of a leading `---` ... `---` block.
+struct Block {
+    yaml_start: usize, // Just after the opening `---`
+    yaml_end: usize,   // The newline before the closing `---`
+    body_start: usize, // After the closing `---` and its line break
+}
+
+fn find_block(content: &str) -> Option<Block> {
+    let lead = content.len() - content.trim_start().len();
+    let rest = content[lead..].strip_prefix("---")?;
+    let end = rest.find("\n---")?;
+
+    let yaml_start = lead + 3;
+    let yaml_end = yaml_start + end;
+    let after_close = &content[yaml_end + 4..];
+    let line_break = if after_close.starts_with("\r\n") {
+        2
+    } else if after_close.starts_with('\n') {
+        1
+    } else {
+        0
+    };
+
+    Some(Block {
+        yaml_start,
+        yaml_end,
+        body_start: yaml_end + 4 + line_break,
+    })
+}
+
+/// Content with the frontmatter block removed.
+pub fn body(content: &str) -> &str {
+    match find_block(content) {
+        Some(block) => &content[block.body_start..],
+        None => content,
+    }
+}
+
+fn parse_yaml(yaml: &str) -> Result<Frontmatter, String> {
+    if yaml.trim().is_empty() {
+        return Ok(Map::new());
+    }
+    match serde_yaml::from_str::<serde_yaml::Value>(yaml) {
+        Ok(serde_yaml::Value::Mapping(mapping)) => Ok(mapping
+            .into_iter()
+            .map(|(key, value)| (key_to_string(&key), yaml_to_json(value)))
+            .collect()),
+        Ok(serde_yaml::Value::Null) => Ok(Map::new()),
+        Ok(_) => Err("Frontmatter is not a key/value mapping".to_string()),
+        Err(e) => Err(format!("Invalid frontmatter: {}", e)),
+    }
+}
+
+/// Parse the frontmatter block. Missing or malformed frontmatter yields an empty map.
+pub fn parse(content: &str) -> Frontmatter {
+    find_block(content)
+        .and_then(|block| parse_yaml(&content[block.yaml_start..block.yaml_end]).ok())
+        .unwrap_or_default()
+}
+
+fn key_to_string(key: &serde_yaml::Value) -> String {
+    match key {
+        serde_yaml::Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn yaml_to_json(value: serde_yaml::Value) -> Value {
+    match value {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::from(i)
+            } else if let Some(u) = n.as_u64() {
+                Value::from(u)
+            } else {
+                n.as_f64()
+                    .and_then(serde_json::Number::from_f64)
+                    .map(Value::Number)
+                    .unwrap_or(Value::Null)
+            }
+        }
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(seq) => {
+            Value::Array(seq.into_iter().map(yaml_to_json).collect())
+        }
+        serde_yaml::Value::Mapping(mapping) => Value::Object(
+            mapping
+                .into_iter()
+                .map(|(key, value)| (key_to_string(&key), yaml_to_json(value)))
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => yaml_to_json(tagged.value),
+    }
+}
+
+/// Non-empty `title:` field.
+pub fn title(frontmatter: &Frontmatter) -> Option<String> {
+    let title = match frontmatter.get("title")? {
+        Value::String(s) => s.trim().to_string(),
+        Value::Number(n) => n.to_string(),
+        _ => return None,
+    };
+    (!title.is_empty()).then_some(title)
+}
+
+/// Values of a list field. Accepts a YAML list or a comma/space separated
+/// string (`tags: [a, b]`, `tags: a, b`, `tags: a b`); a leading `#` is dropped.
+pub fn string_list(frontmatter: &Frontmatter, key: &str) -> Vec<String> {
+    let items: Vec<String> = match frontmatter.get(key) {
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(|v| match v {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Some(Value::String(s)) => s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    items
+        .iter()
+        .map(|item| item.trim().trim_start_matches('#').to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Top-level key declared on a frontmatter line, if the line starts one.
+fn line_key(line: &str) -> Option<String> {
+    if line.starts_with([' ', '\t', '#', '-']) || line.trim().is_empty() {
+        return None;
+    }
+    let line = line.trim_end();
+    let key = if let Some(quoted) = line.strip_prefix('"').or_else(|| line.strip_prefix('\'')) {
+        let quote = &line[..1];
+        let end = quoted.find(quote)?;
+        if !quoted[end + 1..].trim_start().starts_with(':') {
+            return None;
+        }
+        quoted[..end].to_string()
+    } else {
+        let colon = line
+            .find(": ")
+            .or_else(|| line.strip_suffix(':').map(|s| s.len()))?;
+        line[..colon].trim().to_string()
+    };
+    Some(key)
+}
+
+/// Serialize a single `key: value` entry as YAML lines.
+fn render_field(key: &str, value: &Value) -> Result<String, String> {
+    let mut mapping = serde_yaml::Mapping::new();
+    let value = serde_yaml::to_value(value).map_err(|e| e.to_string())?;
+    mapping.insert(serde_yaml::Value::String(key.to_string()), value);
+    serde_yaml::to_string(&mapping).map_err(|e| e.to_string())
+}
+
+/// Apply a field patch to a note's frontmatter and return the updated content.
+/// Keys set to `null` are removed; other keys are replaced in place or appended.
+/// Untouched lines (including comments and formatting) are kept verbatim, and
+/// the block is created or dropped as needed.
+pub fn apply_patch(content: &str, patch: &Frontmatter) -> Result<String, String> {
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let block = find_block(content);
+
+    let (yaml, body) = match &block {
+        Some(block) => {
+            let yaml = &content[block.yaml_start..block.yaml_end];
+            // Refuse to edit frontmatter we can't understand rather than mangling it
+            parse_yaml(yaml)?;
+            (yaml, &content[block.body_start..])
+        }
+        None => ("", content),
+    };
+
+    // Split into lines, dropping the remainder of the opening `---` line
+    let mut lines: Vec<String> = yaml
+        .split('\n')
+        .skip(1)
+        .map(|line| line.trim_end_matches('\r').to_string())
+        .collect();
+
+    for (key, value) in patch {
+        // Extent of the existing entry: its key line plus continuation lines
+        let start = lines
+            .iter()
+            .position(|line| line_key(line).as_deref() == Some(key.as_str()));
+        let range = start.map(|start| {
+            let mut end = start + 1;
+            while end < lines.len()
+                && line_key(&lines[end]).is_none()
+                && !lines[end].starts_with('#')
+            {
+                end += 1;
+            }
+            // Leave trailing blank lines to the next entry
+            while end > start + 1 && lines[end - 1].trim().is_empty() {
+                end -= 1;
+            }
+            start..end
+        });
+
+        let replacement: Vec<String> = if value.is_null() {
+            Vec::new()
+        } else {
+            render_field(key, value)?
+                .trim_end_matches('\n')
+                .split('\n')
+                .map(str::to_string)
+                .collect()
+        };
+
+        match range {
+            Some(range) => {
+                lines.splice(range, replacement);
+            }
+            None => {
+                let insert_at = lines
+                    .iter()
+                    .rposition(|line| !line.trim().is_empty())
+                    .map_or(0, |i| i + 1);
+                lines.splice(insert_at..insert_at, replacement);
+            }
+        }
+    }
+
+    if lines.iter().all(|line| line.trim().is_empty()) {
+        return Ok(body.to_string());
+    }
+
+    let mut result = String::new();
+    if let Some(block) = &block {
+        // Keep anything before the opening delimiter (e.g. a BOM or blank lines)
+        result.push_str(&content[..block.yaml_start - 3]);
+    }
+    result.push_str("---");
+    result.push_str(newline);
+    for line in &lines {
+        result.push_str(line);
+        result.push_str(newline);
+    }
+    result.push_str("---");
+    result.push_str(newline);
+    result.push_str(body);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn patch(value: Value) -> Frontmatter {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn parses_block_and_body() {
+        let content = "---\ntitle: Hello\ntags: [a, b]\n1: one\n---\n# Body\n";
+        let fm = parse(content);
+        assert_eq!(fm.get("title"), Some(&json!("Hello")));
+        assert_eq!(fm.get("tags"), Some(&json!(["a", "b"])));
+        assert_eq!(fm.get("1"), Some(&json!("one")));
+        assert_eq!(body(content), "# Body\n");
+    }
+
+    #[test]
+    fn missing_or_malformed_frontmatter_is_empty() {
+        assert!(parse("# Just a note\n---\n").is_empty());
+        assert!(parse("---\ntitle: [unclosed\n---\nbody").is_empty());
+        assert!(parse("---\n- a list\n---\n").is_empty());
+        assert_eq!(body("no frontmatter"), "no frontmatter");
+    }
+
+    #[test]
+    fn handles_crlf_and_leading_blank_lines() {
+        let content = "\r\n---\r\ntitle: Win\r\n---\r\nBody\r\n";
+        assert_eq!(title(&parse(content)).as_deref(), Some("Win"));
+        assert_eq!(body(content), "Body\r\n");
+    }
+
+    #[test]
+    fn title_and_string_lists() {
+        assert_eq!(
+            title(&parse("---\ntitle: 2024\n---\n")).as_deref(),
+            Some("2024")
+        );
+        assert_eq!(title(&parse("---\ntitle: '  '\n---\n")), None);
+
+        let fm = parse("---\na: [x, '#y', 3]\nb: one, two three\nc: true\n---\n");
+        assert_eq!(string_list(&fm, "a"), vec!["x", "y", "3"]);
+        assert_eq!(string_list(&fm, "b"), vec!["one", "two", "three"]);
+        assert!(string_list(&fm, "c").is_empty());
+        assert!(string_list(&fm, "missing").is_empty());
+    }
+
+    #[test]
+    fn patch_replaces_in_place_and_keeps_other_lines() {
+        let content = "---\n# comment\ntitle: Old\ntags:\n  - a\n  - b\n\nstatus: draft # inline\n---\nBody\n";
+        let updated =
+            apply_patch(content, &patch(json!({ "tags": ["c"], "title": "New" }))).unwrap();
+        assert_eq!(
+            updated,
+            "---\n# comment\ntitle: New\ntags:\n- c\n\nstatus: draft # inline\n---\nBody\n"
+        );
+    }
+
+    #[test]
+    fn patch_removes_appends_and_creates() {
+        let content = "---\ntitle: T\nstatus: draft\n---\nBody";
+        assert_eq!(
+            apply_patch(content, &patch(json!({ "status": null, "done": true }))).unwrap(),
+            "---\ntitle: T\ndone: true\n---\nBody"
+        );
+        assert_eq!(
+            apply_patch("Body\n", &patch(json!({ "title": "Fresh" }))).unwrap(),
+            "---\ntitle: Fresh\n---\nBody\n"
+        );
+        // Removing the last field drops the block
+        assert_eq!(
+            apply_patch(
+                "---\ntitle: T\n---\nBody\n",
+                &patch(json!({ "title": null }))
+            )
+            .unwrap(),
+            "Body\n"
+        );
+    }
+
+    #[test]
+    fn patch_keeps_crlf_and_refuses_invalid_yaml() {
+        assert_eq!(
+            apply_patch("---\r\na: 1\r\n---\r\nBody\r\n", &patch(json!({ "a": 2 }))).unwrap(),
+            "---\r\na: 2\r\n---\r\nBody\r\n"
+        );
+        assert!(apply_patch("---\na: [oops\n---\n", &patch(json!({ "a": 1 }))).is_err());
+    }
+
+    #[test]
+    fn line_keys() {
+        assert_eq!(line_key("title: x").as_deref(), Some("title"));
+        assert_eq!(line_key("empty:").as_deref(), Some("empty"));
+        assert_eq!(line_key("\"quoted key\": x").as_deref(), Some("quoted key"));
+        assert_eq!(line_key("  nested: x"), None);
+        assert_eq!(line_key("- item"), None);
+        assert_eq!(line_key("url http://x"), None);
+    }
+}
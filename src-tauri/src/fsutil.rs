@@ -0,0 +1,155 @@
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Distinguishes temp files of concurrent writes to the same target
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Temp file next to the target: `.<name>.<pid>-<n>.tmp`. The leading dot and
+/// `.tmp` extension keep it out of note listings and the file watcher.
+fn temp_path_for(path: &Path) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let mut name = OsString::from(".");
+    name.push(file_name);
+    name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    Ok(path.with_file_name(name))
+}
+
+/// Crash-safe file write: the content goes to a temp file in the same directory,
+/// is fsynced, then renamed over the target. Readers (and a crash at any point)
+/// see either the old or the new content, never a truncated file.
+///
+/// Symlinks are resolved so the link itself is kept, and existing file
+/// permissions are carried over to the new file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let target = match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => std::fs::canonicalize(path)?,
+        _ => path.to_path_buf(),
+    };
+    let temp = temp_path_for(&target)?;
+
+    let result = (|| {
+        let mut file = std::fs::File::create(&temp)?;
+        file.write_all(contents)?;
+        if let Ok(meta) = std::fs::metadata(&target) {
+            file.set_permissions(meta.permissions())?;
+        }
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&temp, &target)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+        return result;
+    }
+
+    // Persist the rename itself; directories can't be opened for syncing on Windows
+    #[cfg(unix)]
+    if let Some(dir) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if let Ok(dir) = std::fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
+
+    Ok(())
+}
+
+/// `write_atomic` for async commands, run on the blocking thread pool.
+pub async fn write_atomic_async(path: &Path, contents: impl Into<Vec<u8>>) -> io::Result<()> {
+    let path = path.to_path_buf();
+    let contents = contents.into();
+    tokio::task::spawn_blocking(move || write_atomic(&path, &contents))
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory, removed when dropped.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "scratch-fsutil-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Dir(root)
+        }
+
+        fn file_names(&self) -> Vec<String> {
+            let mut names: Vec<String> = std::fs::read_dir(&self.0)
+                .unwrap()
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn overwrites_without_leaving_temp_files() {
+        let dir = Dir::new("overwrite");
+        let path = dir.0.join("note.md");
+
+        write_atomic(&path, b"a much longer first version").unwrap();
+        write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(dir.file_names(), vec!["note.md"]);
+    }
+
+    #[test]
+    fn cleans_up_the_temp_file_on_failure() {
+        let dir = Dir::new("failure");
+        // Renaming a file over a directory fails after the temp file is written
+        std::fs::create_dir(dir.0.join("taken")).unwrap();
+
+        assert!(write_atomic(&dir.0.join("taken"), b"content").is_err());
+        assert_eq!(dir.file_names(), vec!["taken"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_symlinks_and_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = Dir::new("symlink");
+        let target = dir.0.join("target.md");
+        let link = dir.0.join("link.md");
+        std::fs::write(&target, "old").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"new").unwrap();
+
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "new");
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(dir.file_names(), vec!["link.md", "target.md"]);
+    }
+}
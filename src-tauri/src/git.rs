@@ -1,22 +1,18 @@
+//! Git operations on the notes folder, run in-process with libgit2. The git
+//! CLI (`git_cli`) is only a fallback, for when the library fails where the
+//! CLI may not and git is installed.
+
+use git2::build::CheckoutBuilder;
+use git2::{
+    BranchType, Cred, CredentialType, Delta, DiffFindOptions, DiffOptions, ErrorClass, ErrorCode,
+    FetchOptions, IndexAddOption, Oid, Patch, PushOptions, RemoteCallbacks, Repository,
+    RepositoryState, Status, StatusOptions,
+};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-/// Create a `Command` for git that hides the console window on Windows.
-fn git_cmd() -> Command {
-    let cmd = Command::new("git");
-    #[cfg(target_os = "windows")]
-    {
-        use std::os::windows::process::CommandExt;
-        let mut cmd = cmd;
-        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
-        cmd
-    }
-    #[cfg(not(target_os = "windows"))]
-    {
-        cmd
-    }
-}
+use crate::git_cli;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -26,12 +22,25 @@ pub struct GitStatus {
     pub has_upstream: bool, // Whether the current branch tracks an upstream
     pub remote_url: Option<String>, // URL of the 'origin' remote
     pub changed_count: usize,
-    pub ahead_count: i32, // -1 if no upstream tracking
+    pub changed_files: Vec<ChangedFile>,
+    pub ahead_count: i32,  // -1 if no upstream tracking
     pub behind_count: i32, // -1 if no upstream tracking
     pub current_branch: Option<String>,
+    pub merge_in_progress: bool, // A merge stopped on conflicts and hasn't been committed or aborted
+    pub conflict_count: usize,   // Files with unresolved conflicts
     pub error: Option<String>,
 }
 
+/// A file with uncommitted changes, staged or not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedFile {
+    pub path: String,             // Relative to the repository root
+    pub status: FileChange,       // Untracked files count as added, conflicted ones as modified
+    pub old_path: Option<String>, // Path before a staged rename
+    pub id: Option<String>,       // Note ID, for notes; filled in by the caller
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GitResult {
@@ -40,13 +49,150 @@ pub struct GitResult {
     pub error: Option<String>,
 }
 
-/// Check if git CLI is available
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileChange {
+    Added,
+    Modified,
+    Renamed,
+    Deleted,
+}
+
+/// A commit that touched a file, as listed by `log_file`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileCommit {
+    pub hash: String,
+    pub short_hash: String,
+    pub author: String,
+    pub email: String,
+    pub date: i64, // Author date, unix seconds
+    pub message: String,
+    pub change: FileChange,
+    pub path: String,             // The file's path in this commit
+    pub old_path: Option<String>, // Its path before, when this commit renamed it
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffLineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub content: String,
+    pub old_line: Option<u32>, // 1-based; None for added lines
+    pub new_line: Option<u32>, // 1-based; None for removed lines
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunk {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiff {
+    pub hunks: Vec<DiffHunk>,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+/// A file with unresolved conflicts and its three versions. A missing side
+/// means the file was deleted (or never existed) there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConflictedFile {
+    pub path: String, // Relative to the repository root
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+    pub base: Option<String>, // Common ancestor
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ConflictSide {
+    Base,
+    Ours,
+    Theirs,
+}
+
+impl ConflictSide {
+    /// The side's stage in the index
+    fn stage(self) -> i32 {
+        match self {
+            ConflictSide::Base => 1,
+            ConflictSide::Ours => 2,
+            ConflictSide::Theirs => 3,
+        }
+    }
+}
+
+/// Why an operation failed
+enum Failure {
+    /// Shown as is; the CLI wouldn't do better
+    Message(String),
+    Library(git2::Error),
+}
+
+impl From<git2::Error> for Failure {
+    fn from(e: git2::Error) -> Self {
+        Failure::Library(e)
+    }
+}
+
+fn success(message: impl Into<String>) -> GitResult {
+    GitResult {
+        success: true,
+        message: Some(message.into()),
+        error: None,
+    }
+}
+
+fn failure(error: impl Into<String>) -> GitResult {
+    GitResult {
+        success: false,
+        message: None,
+        error: Some(error.into()),
+    }
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// App data kept in the notes folder that stays local: version history,
+/// replace-in-vault undo data and the trash. It's neither listed as changed
+/// nor committed.
+pub(crate) const LOCAL_ONLY_DIRS: &[&str] =
+    &[".scratch/history", ".scratch/replace-undo", ".trash"];
+
+/// Whether a path relative to the repository root is under `LOCAL_ONLY_DIRS`.
+pub(crate) fn is_local_only(path: &str) -> bool {
+    LOCAL_ONLY_DIRS.iter().any(|dir| {
+        path.strip_prefix(dir)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Git is built in, so it's always available; the CLI is optional
 pub fn is_available() -> bool {
-    git_cmd()
-        .arg("--version")
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
+    true
+}
+
+/// Whether the git CLI is installed, to fall back to
+fn cli_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(git_cli::is_available)
 }
 
 /// Check if a directory is a git repository
@@ -56,17 +202,9 @@ pub fn is_git_repo(path: &Path) -> bool {
 
 /// Initialize a git repository
 pub fn git_init(path: &Path) -> Result<(), String> {
-    let output = git_cmd()
-        .arg("init")
-        .current_dir(path)
-        .output()
-        .map_err(|e| format!("Failed to run git init: {}", e))?;
-
-    if output.status.success() {
-        Ok(())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
-    }
+    Repository::init(path)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to initialize repository: {}", e.message()))
 }
 
 /// Get the current git status
@@ -75,331 +213,603 @@ pub fn get_status(path: &Path) -> GitStatus {
         return GitStatus::default();
     }
 
+    match Repository::open(path).and_then(|repo| read_status(&repo)) {
+        Ok(status) => status,
+        Err(_) if cli_available() => git_cli::get_status(path),
+        Err(e) => GitStatus {
+            is_repo: true,
+            ahead_count: -1,
+            behind_count: -1,
+            error: Some(e.message().to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+fn read_status(repo: &Repository) -> Result<GitStatus, git2::Error> {
     let mut status = GitStatus {
         is_repo: true,
         ahead_count: -1,
         behind_count: -1,
+        current_branch: current_branch(repo),
+        merge_in_progress: repo.state() == RepositoryState::Merge,
         ..Default::default()
     };
 
-    // Get current branch
-    if let Ok(output) = git_cmd()
-        .args(["branch", "--show-current"])
-        .current_dir(path)
-        .output()
-    {
-        if output.status.success() {
-            let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if !branch.is_empty() {
-                status.current_branch = Some(branch);
-            }
-        }
+    status.has_remote = !repo.remotes()?.is_empty();
+    if status.has_remote {
+        status.remote_url = repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|remote| remote.url().map(str::to_string));
     }
 
-    // Check for remote
-    if let Ok(output) = git_cmd()
-        .args(["remote"])
-        .current_dir(path)
-        .output()
-    {
-        status.has_remote = output.status.success()
-            && !String::from_utf8_lossy(&output.stdout).trim().is_empty();
+    let (files, conflicts) = changes(repo)?;
+    status.changed_count = files.len();
+    status.changed_files = files;
+    status.conflict_count = conflicts;
 
-        // Get remote URL if remote exists
-        if status.has_remote {
-            status.remote_url = get_remote_url(path);
+    // Ahead/behind counts, if the branch tracks an upstream that has been fetched
+    if status.has_remote {
+        if let Some((ahead, behind)) = status
+            .current_branch
+            .as_deref()
+            .and_then(|branch| ahead_behind(repo, branch))
+        {
+            status.has_upstream = true;
+            status.ahead_count = ahead as i32;
+            status.behind_count = behind as i32;
         }
     }
 
-    // Get status with porcelain format for easy parsing
-    if let Ok(output) = git_cmd()
-        .args(["status", "--porcelain"])
-        .current_dir(path)
-        .output()
-    {
-        if output.status.success() {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            status.changed_count = stdout.lines().filter(|line| !line.is_empty()).count();
-        }
-    }
+    Ok(status)
+}
 
-    // Get ahead/behind count if we have a remote
-    if status.has_remote && status.current_branch.is_some() {
-        match git_cmd()
-            .args(["rev-list", "--left-right", "--count", "@{upstream}...HEAD"])
-            .current_dir(path)
-            .output()
-        {
-            Ok(output) => {
-                if output.status.success() {
-                    status.has_upstream = true;
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let parts: Vec<&str> = stdout.trim().split('\t').collect();
-                    if parts.len() == 2 {
-                        // parts[0] is behind count, parts[1] is ahead count
-                        status.behind_count = parts[0].parse().unwrap_or(0);
-                        status.ahead_count = parts[1].parse().unwrap_or(0);
-                    }
-                } else {
-                    // Command failed - likely no upstream configured
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    if stderr.contains("no upstream") || stderr.contains("unknown revision") {
-                        status.has_upstream = false;
-                        status.ahead_count = -1; // Sentinel value indicating no upstream
-                        status.behind_count = -1;
-                    }
-                }
+/// The checked-out branch, also before its first commit; `None` when detached
+fn current_branch(repo: &Repository) -> Option<String> {
+    let head = repo.find_reference("HEAD").ok()?;
+    head.symbolic_target()?
+        .strip_prefix("refs/heads/")
+        .map(str::to_string)
+}
+
+fn ahead_behind(repo: &Repository, branch: &str) -> Option<(usize, usize)> {
+    let local = repo.find_branch(branch, BranchType::Local).ok()?;
+    let upstream = local.upstream().ok()?;
+    repo.graph_ahead_behind(local.get().target()?, upstream.get().target()?)
+        .ok()
+}
+
+/// Changed files (including untracked ones) and how many are conflicted
+fn changes(repo: &Repository) -> Result<(Vec<ChangedFile>, usize), git2::Error> {
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .renames_head_to_index(true)
+        .renames_index_to_workdir(true);
+
+    let mut conflicts = 0;
+    let files = repo
+        .statuses(Some(&mut options))?
+        .iter()
+        .filter(|entry| !is_local_only(&lossy(entry.path_bytes())))
+        .map(|entry| {
+            let flags = entry.status();
+            // Staged renames, or files moved on disk and not staged yet
+            let renamed = if flags.contains(Status::INDEX_RENAMED) {
+                entry.head_to_index()
+            } else if flags.contains(Status::WT_RENAMED) {
+                entry.index_to_workdir()
+            } else {
+                None
+            };
+            let (path, old_path) = match renamed {
+                Some(delta) => (
+                    delta.new_file().path_bytes().map(lossy).unwrap_or_default(),
+                    delta.old_file().path_bytes().map(lossy),
+                ),
+                None => (lossy(entry.path_bytes()), None),
+            };
+            let status = if flags.is_conflicted() {
+                conflicts += 1;
+                FileChange::Modified
+            } else if old_path.is_some() {
+                FileChange::Renamed
+            } else if flags.intersects(Status::INDEX_DELETED | Status::WT_DELETED) {
+                FileChange::Deleted
+            } else if flags.intersects(Status::INDEX_NEW | Status::WT_NEW) {
+                FileChange::Added
+            } else {
+                FileChange::Modified
+            };
+            ChangedFile {
+                path,
+                status,
+                old_path,
+                id: None,
             }
-            Err(_) => {
-                status.has_upstream = false;
-                status.ahead_count = -1;
-                status.behind_count = -1;
+        })
+        .collect();
+    Ok((files, conflicts))
+}
+
+/// Changed files (including untracked ones)
+pub fn changed_files(path: &Path) -> Result<Vec<ChangedFile>, String> {
+    match Repository::open(path).and_then(|repo| changes(&repo)) {
+        Ok((files, _)) => Ok(files),
+        Err(_) if cli_available() => Ok(git_cli::get_status(path).changed_files),
+        Err(e) => Err(e.message().to_string()),
+    }
+}
+
+/// Parents of the merge being concluded, if one is in progress
+fn merge_heads(repo: &Repository) -> Vec<Oid> {
+    std::fs::read_to_string(repo.path().join("MERGE_HEAD"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| Oid::from_str(line.trim()).ok())
+        .collect()
+}
+
+/// Commit a tree on top of HEAD (concluding a merge in progress). Returns
+/// false if there was nothing to commit.
+fn commit_tree(repo: &Repository, tree_id: Oid, message: &str) -> Result<bool, git2::Error> {
+    let head = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => None,
+        Err(e) => return Err(e),
+    };
+    let merge_heads = merge_heads(repo);
+    let tree = repo.find_tree(tree_id)?;
+    let unchanged = match &head {
+        Some(head) => head.tree_id() == tree_id,
+        None => tree.is_empty(),
+    };
+    if unchanged && merge_heads.is_empty() {
+        return Ok(false);
+    }
+
+    let message = git2::message_prettify(message, None)?;
+    if message.is_empty() {
+        return Err(git2::Error::from_str(
+            "Aborting commit due to empty commit message",
+        ));
+    }
+    let signature = repo.signature()?;
+    let mut parents: Vec<git2::Commit> = head.into_iter().collect();
+    for id in &merge_heads {
+        parents.push(repo.find_commit(*id)?);
+    }
+    let parents: Vec<&git2::Commit> = parents.iter().collect();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        &message,
+        &tree,
+        &parents,
+    )?;
+    if !merge_heads.is_empty() {
+        repo.cleanup_state()?;
+    }
+    Ok(true)
+}
+
+/// Stage and commit only the given changes, leaving others (staged or not)
+/// as they are
+pub fn commit_files(path: &Path, files: &[ChangedFile], message: &str) -> GitResult {
+    if files.is_empty() {
+        return failure("Nothing selected to commit");
+    }
+
+    let committed = Repository::open(path)
+        .map_err(Failure::from)
+        .and_then(|repo| commit_files_in(&repo, files, message));
+    match committed {
+        Ok(true) => success(format!(
+            "Committed {} file{}",
+            files.len(),
+            if files.len() == 1 { "" } else { "s" }
+        )),
+        Ok(false) => success("Nothing to commit"),
+        Err(Failure::Message(message)) => failure(message),
+        Err(Failure::Library(_)) if cli_available() => git_cli::commit_files(path, files, message),
+        Err(Failure::Library(e)) => failure(format!("Failed to commit: {}", e.message())),
+    }
+}
+
+fn commit_files_in(
+    repo: &Repository,
+    files: &[ChangedFile],
+    message: &str,
+) -> Result<bool, Failure> {
+    if repo.state() != RepositoryState::Clean {
+        return Err(Failure::Message(
+            "Can't commit selected files during a merge. Commit all changes to finish it."
+                .to_string(),
+        ));
+    }
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| git2::Error::from_str("Repository has no working directory"))?;
+
+    // Stage the files, and the removal of a renamed file's old path
+    let mut index = repo.index()?;
+    for file in files {
+        for path in std::iter::once(&file.path).chain(&file.old_path) {
+            let path = Path::new(path);
+            if workdir.join(path).exists() {
+                index.add_path(path)?;
+            } else {
+                index.remove_path(path)?;
             }
         }
     }
+    index.write()?;
 
-    status
+    // Commit HEAD's tree with just these files updated, so other staged
+    // changes stay staged rather than being committed along
+    let mut selected = git2::Index::new()?;
+    if let Ok(head) = repo.head().and_then(|head| head.peel_to_tree()) {
+        selected.read_tree(&head)?;
+    }
+    for file in files {
+        for path in std::iter::once(&file.path).chain(&file.old_path) {
+            let path = Path::new(path);
+            match index.get_path(path, 0) {
+                Some(entry) => selected.add(&entry)?,
+                None => selected.remove_path(path)?,
+            }
+        }
+    }
+    let tree = selected.write_tree_to(repo)?;
+    Ok(commit_tree(repo, tree, message)?)
 }
 
 /// Stage all changes and commit
 pub fn commit_all(path: &Path, message: &str) -> GitResult {
-    // Stage all changes
-    let stage_output = match git_cmd()
-        .args(["add", "-A"])
-        .current_dir(path)
-        .output()
-    {
-        Ok(output) => output,
-        Err(e) => {
-            return GitResult {
-                success: false,
-                message: None,
-                error: Some(format!("Failed to run git add: {}", e)),
-            };
+    let committed = Repository::open(path).and_then(|repo| {
+        let mut index = repo.index()?;
+        let mut skip_local =
+            |path: &Path, _: &[u8]| i32::from(is_local_only(&path.to_string_lossy()));
+        index.add_all(["*"], IndexAddOption::DEFAULT, Some(&mut skip_local))?;
+        index.update_all(["*"], Some(&mut skip_local))?; // Deletions
+        index.write()?;
+        let tree = index.write_tree()?;
+        commit_tree(&repo, tree, message)
+    });
+    match committed {
+        Ok(true) => success("Changes committed"),
+        Ok(false) => success("Nothing to commit"),
+        Err(_) if cli_available() => git_cli::commit_all(path, message),
+        Err(e) => failure(format!("Failed to commit: {}", e.message())),
+    }
+}
+
+/// Connect and read timeouts, matching the CLI's `ConnectTimeout` and low-speed limits
+fn set_network_timeouts() {
+    static SET: OnceLock<()> = OnceLock::new();
+    SET.get_or_init(|| {
+        // SAFETY: runs once, before this process starts any network operation
+        unsafe {
+            let _ = git2::opts::set_server_connect_timeout_in_milliseconds(10_000);
+            let _ = git2::opts::set_server_timeout_in_milliseconds(10_000);
         }
+    });
+}
+
+/// Unencrypted default SSH keys, tried after the SSH agent
+fn default_ssh_keys() -> Vec<PathBuf> {
+    let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) else {
+        return Vec::new();
     };
+    let ssh_dir = PathBuf::from(home).join(".ssh");
+    ["id_ed25519", "id_ecdsa", "id_rsa"]
+        .iter()
+        .map(|name| ssh_dir.join(name))
+        .filter(|key| key.is_file())
+        .collect()
+}
 
-    // Check if staging succeeded
-    if !stage_output.status.success() {
-        let stderr = String::from_utf8_lossy(&stage_output.stderr).to_string();
-        let stdout = String::from_utf8_lossy(&stage_output.stdout).to_string();
-        return GitResult {
-            success: false,
-            message: None,
-            error: Some(format!(
-                "Failed to stage changes: {}{}",
-                stderr,
-                if stdout.is_empty() { String::new() } else { format!("\n{}", stdout) }
-            )),
-        };
+/// Credentials from the SSH agent, default SSH keys or git's credential helpers
+fn remote_callbacks(config: &git2::Config) -> RemoteCallbacks<'_> {
+    let ssh_keys = default_ssh_keys();
+    let mut attempts = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed == CredentialType::USERNAME {
+            return Cred::username(username.unwrap_or("git"));
+        }
+        // libgit2 asks again after a credential is rejected; offer each one once
+        attempts += 1;
+        if allowed.contains(CredentialType::SSH_KEY) {
+            let username = username.unwrap_or("git");
+            if attempts == 1 {
+                return Cred::ssh_key_from_agent(username);
+            }
+            if let Some(key) = ssh_keys.get(attempts - 2) {
+                return Cred::ssh_key(username, None, key, None);
+            }
+        } else if attempts == 1 && allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            return Cred::credential_helper(config, url, username);
+        } else if attempts == 1 && allowed.contains(CredentialType::DEFAULT) {
+            return Cred::default();
+        }
+        Err(git2::Error::new(
+            ErrorCode::Auth,
+            ErrorClass::Callback,
+            "No usable credentials",
+        ))
+    });
+    callbacks
+}
+
+/// Errors the CLI may get past: credentials only its helpers or SSH config
+/// can supply, host keys, proxies and protocols libgit2 doesn't handle, or
+/// repository features it doesn't support
+fn cli_may_succeed(e: &git2::Error) -> bool {
+    matches!(e.code(), ErrorCode::Auth | ErrorCode::Certificate)
+        || matches!(
+            e.class(),
+            ErrorClass::Ssh
+                | ErrorClass::Http
+                | ErrorClass::Ssl
+                | ErrorClass::Callback
+                | ErrorClass::Repository
+        )
+}
+
+/// User-friendly messages for common remote errors (auth, network)
+fn remote_error(e: &git2::Error) -> String {
+    if e.code() == ErrorCode::Auth || e.class() == ErrorClass::Ssh {
+        "Authentication failed. Check your SSH keys or credentials.".to_string()
+    } else if e.class() == ErrorClass::Net
+        || e.message().starts_with("failed to connect")
+        || e.message().starts_with("failed to resolve")
+    {
+        "Could not connect to remote. Check your internet connection.".to_string()
+    } else {
+        e.message().to_string()
     }
+}
 
-    // Commit
-    let commit_output = git_cmd()
-        .args(["commit", "-m", message])
-        .current_dir(path)
-        .output();
+fn push_error(e: &git2::Error) -> String {
+    if e.code() == ErrorCode::NotFastForward {
+        "Push rejected because the remote has changes you don't have. Pull first.".to_string()
+    } else if e.message().contains("not found") {
+        "Remote repository not found. Check the URL.".to_string()
+    } else {
+        remote_error(e)
+    }
+}
 
-    match commit_output {
-        Ok(output) => {
-            if output.status.success() {
-                GitResult {
-                    success: true,
-                    message: Some("Changes committed".to_string()),
-                    error: None,
-                }
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                // "nothing to commit" is not really an error
-                if stderr.contains("nothing to commit") {
-                    GitResult {
-                        success: true,
-                        message: Some("Nothing to commit".to_string()),
-                        error: None,
-                    }
-                } else {
-                    GitResult {
-                        success: false,
-                        message: None,
-                        error: Some(stderr),
-                    }
-                }
-            }
-        }
-        Err(e) => GitResult {
-            success: false,
-            message: None,
-            error: Some(format!("Failed to commit: {}", e)),
-        },
+/// The remote the current branch tracks, or 'origin'
+fn default_remote(repo: &Repository) -> String {
+    current_branch(repo)
+        .and_then(|branch| {
+            repo.branch_upstream_remote(&format!("refs/heads/{}", branch))
+                .ok()
+        })
+        .and_then(|remote| remote.as_str().map(str::to_string))
+        .unwrap_or_else(|| "origin".to_string())
+}
+
+fn fetch_remote(repo: &Repository, remote: &str) -> Result<(), git2::Error> {
+    set_network_timeouts();
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(remote)?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(remote_callbacks(&config));
+    remote.fetch::<&str>(&[], Some(&mut options), None)
+}
+
+/// Push a refspec, failing if the remote rejects it
+fn push_refspec(repo: &Repository, remote: &str, refspec: &str) -> Result<(), Failure> {
+    set_network_timeouts();
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(remote)?;
+    let mut rejection = None;
+    {
+        let mut callbacks = remote_callbacks(&config);
+        callbacks.push_update_reference(|_, status| {
+            rejection = status.map(str::to_string);
+            Ok(())
+        });
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+        remote.push(&[refspec], Some(&mut options))?;
+    }
+    match rejection {
+        Some(reason) => Err(Failure::Message(format!("Push rejected: {}", reason))),
+        None => Ok(()),
+    }
+}
+
+fn push_result(
+    pushed: Result<(), Failure>,
+    message: String,
+    cli: impl FnOnce() -> GitResult,
+) -> GitResult {
+    match pushed {
+        Ok(()) => success(message),
+        Err(Failure::Message(error)) => failure(error),
+        Err(Failure::Library(e)) if cli_may_succeed(&e) && cli_available() => cli(),
+        Err(Failure::Library(e)) => failure(push_error(&e)),
     }
 }
 
 /// Push to remote
 pub fn push(path: &Path) -> GitResult {
-    let output = git_cmd()
-        .args(["-c", "http.lowSpeedLimit=1000", "-c", "http.lowSpeedTime=10", "push"])
-        .env("GIT_SSH_COMMAND", "ssh -o ConnectTimeout=10")
-        .current_dir(path)
-        .output();
-
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                GitResult {
-                    success: true,
-                    message: Some("Pushed successfully".to_string()),
-                    error: None,
-                }
-            } else {
-                GitResult {
-                    success: false,
-                    message: None,
-                    error: Some(parse_push_error(&String::from_utf8_lossy(&output.stderr))),
-                }
+    let pushed = Repository::open(path)
+        .map_err(Failure::from)
+        .and_then(|repo| {
+            let branch = current_branch(&repo)
+                .ok_or_else(|| Failure::Message("No current branch found".to_string()))?;
+            let refname = format!("refs/heads/{}", branch);
+            match (
+                repo.branch_upstream_remote(&refname),
+                repo.branch_upstream_merge(&refname),
+            ) {
+                (Ok(remote), Ok(merge)) => push_refspec(
+                    &repo,
+                    remote.as_str().unwrap_or("origin"),
+                    &format!("{}:{}", refname, merge.as_str().unwrap_or(&refname)),
+                ),
+                _ => Err(Failure::Message(format!(
+                    "The current branch {} has no upstream branch.",
+                    branch
+                ))),
             }
-        }
-        Err(e) => GitResult {
-            success: false,
-            message: None,
-            error: Some(format!("Failed to push: {}", e)),
-        },
-    }
+        });
+    push_result(pushed, "Pushed successfully".to_string(), || {
+        git_cli::push(path)
+    })
+}
+
+/// Push to remote and set upstream tracking (like git push -u origin <branch>)
+pub fn push_with_upstream(path: &Path, branch: &str) -> GitResult {
+    let pushed = Repository::open(path)
+        .map_err(Failure::from)
+        .and_then(|repo| {
+            let refname = format!("refs/heads/{}", branch);
+            push_refspec(&repo, "origin", &format!("{}:{}", refname, refname))?;
+            repo.find_branch(branch, BranchType::Local)?
+                .set_upstream(Some(&format!("origin/{}", branch)))?;
+            Ok(())
+        });
+    push_result(
+        pushed,
+        format!("Pushed and tracking origin/{}", branch),
+        || git_cli::push_with_upstream(path, branch),
+    )
 }
 
 /// Fetch from remote to update tracking refs
 pub fn fetch(path: &Path) -> GitResult {
-    let output = git_cmd()
-        .args(["-c", "http.lowSpeedLimit=1000", "-c", "http.lowSpeedTime=10", "fetch", "--quiet"])
-        .env("GIT_SSH_COMMAND", "ssh -o ConnectTimeout=10")
-        .current_dir(path)
-        .output();
-
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                GitResult {
-                    success: true,
-                    message: Some("Fetched successfully".to_string()),
-                    error: None,
-                }
-            } else {
-                GitResult {
-                    success: false,
-                    message: None,
-                    error: Some(parse_pull_error(&String::from_utf8_lossy(&output.stderr))),
-                }
-            }
-        }
-        Err(e) => GitResult {
-            success: false,
-            message: None,
-            error: Some(format!("Failed to fetch: {}", e)),
-        },
+    match Repository::open(path).and_then(|repo| fetch_remote(&repo, &default_remote(&repo))) {
+        Ok(()) => success("Fetched successfully"),
+        Err(e) if cli_may_succeed(&e) && cli_available() => git_cli::fetch(path),
+        Err(e) => failure(remote_error(&e)),
     }
 }
 
-/// Pull from remote
+/// Pull from remote: fetch, then fast-forward or merge (never rebase)
 pub fn pull(path: &Path) -> GitResult {
-    let output = git_cmd()
-        .args(["-c", "http.lowSpeedLimit=1000", "-c", "http.lowSpeedTime=10", "-c", "pull.rebase=false", "pull"])
-        .env("GIT_SSH_COMMAND", "ssh -o ConnectTimeout=10")
-        .current_dir(path)
-        .output();
-
-    match output {
-        Ok(output) => {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            if output.status.success() {
-                let message = if stdout.contains("Already up to date") {
-                    "Already up to date"
-                } else {
-                    "Pulled latest changes"
-                };
-                GitResult {
-                    success: true,
-                    message: Some(message.to_string()),
-                    error: None,
-                }
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                let combined = format!("{}{}", stdout, stderr);
-                GitResult {
-                    success: false,
-                    message: None,
-                    error: Some(parse_pull_error(&combined)),
-                }
-            }
-        }
-        Err(e) => GitResult {
-            success: false,
-            message: None,
-            error: Some(format!("Failed to pull: {}", e)),
-        },
+    match Repository::open(path)
+        .map_err(Failure::from)
+        .and_then(|repo| pull_in(&repo))
+    {
+        Ok(message) => success(message),
+        Err(Failure::Message(error)) => failure(error),
+        Err(Failure::Library(e)) if cli_may_succeed(&e) && cli_available() => git_cli::pull(path),
+        Err(Failure::Library(e)) => failure(remote_error(&e)),
     }
 }
 
-/// Get the URL of the 'origin' remote, if configured
-pub fn get_remote_url(path: &Path) -> Option<String> {
-    if !is_git_repo(path) {
-        return None;
+fn pull_in(repo: &Repository) -> Result<&'static str, Failure> {
+    if repo.state() != RepositoryState::Clean {
+        return Err(Failure::Message(
+            "Finish or abort the merge in progress before syncing with remote.".to_string(),
+        ));
     }
+    let branch = current_branch(repo)
+        .ok_or_else(|| Failure::Message("No current branch found".to_string()))?;
+    let refname = format!("refs/heads/{}", branch);
+    let (Ok(remote), Ok(upstream), Ok(merge)) = (
+        repo.branch_upstream_remote(&refname),
+        repo.branch_upstream_name(&refname),
+        repo.branch_upstream_merge(&refname),
+    ) else {
+        return Err(Failure::Message(
+            "The current branch doesn't track a remote branch.".to_string(),
+        ));
+    };
+    let remote = remote.as_str().unwrap_or("origin").to_string();
+    fetch_remote(repo, &remote)?;
 
-    git_cmd()
-        .args(["remote", "get-url", "origin"])
-        .current_dir(path)
-        .output()
-        .ok()
-        .filter(|o| o.status.success())
-        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    // Anything failing from here on is about local changes, not the remote
+    let merge_message = format!(
+        "Merge branch '{}' of {}",
+        merge
+            .as_str()
+            .unwrap_or_default()
+            .trim_start_matches("refs/heads/"),
+        repo.find_remote(&remote)
+            .ok()
+            .and_then(|r| r.url().map(str::to_string))
+            .unwrap_or(remote)
+    );
+    merge_upstream(
+        repo,
+        &refname,
+        upstream.as_str().unwrap_or_default(),
+        &merge_message,
+    )
+    .map_err(|failure| match failure {
+        Failure::Library(e)
+            if e.code() == ErrorCode::Conflict || e.class() == ErrorClass::Checkout =>
+        {
+            Failure::Message("Commit your changes before syncing with remote.".to_string())
+        }
+        Failure::Library(e) => Failure::Message(e.message().to_string()),
+        failure => failure,
+    })
+}
+
+fn merge_upstream(
+    repo: &Repository,
+    refname: &str,
+    upstream: &str,
+    message: &str,
+) -> Result<&'static str, Failure> {
+    let theirs_id = repo.refname_to_id(upstream)?;
+    let theirs = repo.find_annotated_commit(theirs_id)?;
+    let (analysis, _) = repo.merge_analysis(&[&theirs])?;
+    if analysis.is_up_to_date() {
+        return Ok("Already up to date");
+    }
+
+    if analysis.is_fast_forward() || analysis.is_unborn() {
+        let target = repo.find_commit(theirs_id)?;
+        repo.checkout_tree(target.as_object(), Some(CheckoutBuilder::new().safe()))?;
+        repo.reference(refname, theirs_id, true, "pull: fast-forward")?;
+        return Ok("Pulled latest changes");
+    }
+
+    let ours_id = repo.head()?.peel_to_commit()?.id();
+    if repo.merge_base(ours_id, theirs_id).is_err() {
+        return Err(Failure::Message(
+            "Pull failed: repositories have unrelated histories. Merge them manually or re-run with --allow-unrelated-histories."
+                .to_string(),
+        ));
+    }
+    repo.merge(&[&theirs], None, Some(CheckoutBuilder::new().safe()))?;
+    let mut index = repo.index()?;
+    if index.has_conflicts() {
+        return Err(Failure::Message(
+            "Pull stopped on merge conflicts. Resolve them or abort the merge.".to_string(),
+        ));
+    }
+    let tree = index.write_tree()?;
+    commit_tree(repo, tree, message)?;
+    Ok("Pulled latest changes")
 }
 
 /// Add a remote named 'origin' with the given URL
 pub fn add_remote(path: &Path, url: &str) -> GitResult {
     // Validate URL format (basic check)
     if !is_valid_remote_url(url) {
-        return GitResult {
-            success: false,
-            message: None,
-            error: Some("Invalid remote URL format. URL must start with https://, http://, or git@".to_string()),
-        };
+        return failure(
+            "Invalid remote URL format. URL must start with https://, http://, or git@",
+        );
     }
 
-    let output = git_cmd()
-        .args(["remote", "add", "origin", url])
-        .current_dir(path)
-        .output();
-
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                GitResult {
-                    success: true,
-                    message: Some("Remote added successfully".to_string()),
-                    error: None,
-                }
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                // Handle common case: remote already exists
-                if stderr.contains("already exists") {
-                    GitResult {
-                        success: false,
-                        message: None,
-                        error: Some("Remote 'origin' already exists".to_string()),
-                    }
-                } else {
-                    GitResult {
-                        success: false,
-                        message: None,
-                        error: Some(stderr),
-                    }
-                }
-            }
-        }
-        Err(e) => GitResult {
-            success: false,
-            message: None,
-            error: Some(format!("Failed to add remote: {}", e)),
-        },
+    match Repository::open(path).and_then(|repo| repo.remote("origin", url).map(|_| ())) {
+        Ok(()) => success("Remote added successfully"),
+        // Handle common case: remote already exists
+        Err(e) if e.code() == ErrorCode::Exists => failure("Remote 'origin' already exists"),
+        Err(e) => failure(e.message()),
     }
 }
 
@@ -407,122 +817,354 @@ pub fn add_remote(path: &Path, url: &str) -> GitResult {
 pub fn set_remote_url(path: &Path, url: &str) -> GitResult {
     let normalized = url.trim();
     if !is_valid_remote_url(normalized) {
-        return GitResult {
-            success: false,
-            message: None,
-            error: Some("Invalid remote URL format. URL must start with https://, http://, or git@".to_string()),
-        };
+        return failure(
+            "Invalid remote URL format. URL must start with https://, http://, or git@",
+        );
     }
 
-    let output = git_cmd()
-        .args(["remote", "set-url", "origin", normalized])
-        .current_dir(path)
-        .output();
+    let updated = Repository::open(path).and_then(|repo| {
+        // Setting the URL of a missing remote would create it
+        repo.find_remote("origin")?;
+        repo.remote_set_url("origin", normalized)
+    });
+    match updated {
+        Ok(()) => success("Remote URL updated"),
+        Err(e) if e.code() == ErrorCode::NotFound => failure("No 'origin' remote configured"),
+        Err(e) => failure(e.message()),
+    }
+}
 
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                GitResult {
-                    success: true,
-                    message: Some("Remote URL updated".to_string()),
-                    error: None,
-                }
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                if stderr.contains("No such remote") {
-                    GitResult {
-                        success: false,
-                        message: None,
-                        error: Some("No 'origin' remote configured".to_string()),
-                    }
-                } else {
-                    GitResult {
-                        success: false,
-                        message: None,
-                        error: Some(stderr.trim().to_string()),
-                    }
-                }
-            }
-        }
-        Err(e) => GitResult {
-            success: false,
+/// Remove the 'origin' remote
+pub fn remove_remote(path: &Path) -> GitResult {
+    match Repository::open(path).and_then(|repo| repo.remote_delete("origin")) {
+        Ok(()) => GitResult {
+            success: true,
+            message: Some("Remote removed".to_string()),
+            error: None,
+        },
+        // Removing an already-missing 'origin' is idempotent — converge on "not connected".
+        Err(e) if e.code() == ErrorCode::NotFound => GitResult {
+            success: true,
             message: None,
-            error: Some(format!("Failed to update remote: {}", e)),
+            error: None,
         },
+        Err(e) => failure(e.message()),
     }
 }
 
-/// Remove the 'origin' remote
-pub fn remove_remote(path: &Path) -> GitResult {
-    let output = git_cmd()
-        .args(["remote", "remove", "origin"])
-        .current_dir(path)
-        .output();
-
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                GitResult {
-                    success: true,
-                    message: Some("Remote removed".to_string()),
-                    error: None,
+/// Commits touching a file, newest first, following it across renames.
+/// `file` is relative to the repository root.
+pub fn log_file(path: &Path, file: &str) -> Result<Vec<FileCommit>, String> {
+    match Repository::open(path).and_then(|repo| file_history(&repo, file)) {
+        Ok(commits) => Ok(commits),
+        Err(_) if cli_available() => git_cli::log_file(path, file),
+        Err(e) => Err(e.message().to_string()),
+    }
+}
+
+fn file_history(repo: &Repository, file: &str) -> Result<Vec<FileCommit>, git2::Error> {
+    match repo.head() {
+        Ok(_) => {}
+        // A repository without commits has no history for anything
+        Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e),
+    }
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+    walk.push_head()?;
+
+    let blob_at =
+        |tree: &git2::Tree, path: &str| tree.get_path(Path::new(path)).ok().map(|entry| entry.id());
+    let mut current = file.to_string();
+    let mut commits = Vec::new();
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        let after = blob_at(&commit.tree()?, &current);
+        let parents = commit
+            .parents()
+            .map(|parent| Ok(blob_at(&parent.tree()?, &current)))
+            .collect::<Result<Vec<_>, git2::Error>>()?;
+        // A merge that kept one side's version didn't change the file
+        if parents.len() > 1 && parents.contains(&after) {
+            continue;
+        }
+
+        let (change, old_path) = match (parents.first().copied().flatten(), after) {
+            (Some(before), Some(after)) if before == after => continue,
+            (Some(_), Some(_)) => (FileChange::Modified, None),
+            (None, Some(_)) => match renamed_from(repo, &commit, &current)? {
+                Some(old_path) => (FileChange::Renamed, Some(old_path)),
+                None => (FileChange::Added, None),
+            },
+            (Some(_), None) => (FileChange::Deleted, None),
+            (None, None) => continue,
+        };
+        let author = commit.author();
+        commits.push(FileCommit {
+            hash: commit.id().to_string(),
+            short_hash: commit
+                .as_object()
+                .short_id()?
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            author: lossy(author.name_bytes()),
+            email: lossy(author.email_bytes()),
+            date: author.when().seconds(),
+            message: lossy(commit.message_bytes()).trim_end().to_string(),
+            change,
+            path: current.clone(),
+            old_path: old_path.clone(),
+        });
+        if let Some(old_path) = old_path {
+            current = old_path;
+        }
+    }
+    Ok(commits)
+}
+
+/// The path a file added by `commit` was renamed from, if it was
+fn renamed_from(
+    repo: &Repository,
+    commit: &git2::Commit,
+    file: &str,
+) -> Result<Option<String>, git2::Error> {
+    let Ok(parent) = commit.parent(0) else {
+        return Ok(None);
+    };
+    let mut diff = repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&commit.tree()?), None)?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+    Ok(diff
+        .deltas()
+        .find(|delta| {
+            delta.status() == Delta::Renamed
+                && delta.new_file().path_bytes() == Some(file.as_bytes())
+        })
+        .and_then(|delta| delta.old_file().path_bytes().map(lossy)))
+}
+
+/// Content of a file as of a revision, following renames: `file` is its
+/// current path, which it may not have had back then. `None` if the file
+/// didn't exist at that revision.
+pub fn show_file_at(path: &Path, file: &str, rev: &str) -> Result<Option<String>, String> {
+    let rev = rev.trim();
+    if rev.is_empty() || rev.starts_with('-') {
+        return Err("Invalid revision".to_string());
+    }
+    match Repository::open(path)
+        .map_err(Failure::from)
+        .and_then(|repo| file_at(&repo, file, rev))
+    {
+        Ok(content) => Ok(content),
+        Err(Failure::Message(error)) => Err(error),
+        Err(Failure::Library(_)) if cli_available() => git_cli::show_file_at(path, file, rev),
+        Err(Failure::Library(e)) => Err(e.message().to_string()),
+    }
+}
+
+fn file_at(repo: &Repository, file: &str, rev: &str) -> Result<Option<String>, Failure> {
+    let commit = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|_| Failure::Message(format!("Unknown revision: {}", rev)))?;
+
+    // The last commit touching the file up to `rev` has its path at `rev`
+    let last = file_history(repo, file)?.into_iter().find(|c| {
+        Oid::from_str(&c.hash).is_ok_and(|id| {
+            id == commit.id() || repo.graph_descendant_of(commit.id(), id).unwrap_or(false)
+        })
+    });
+    let Some(last) = last.filter(|c| c.change != FileChange::Deleted) else {
+        return Ok(None);
+    };
+    let entry = commit.tree()?.get_path(Path::new(&last.path))?;
+    let blob = repo.find_blob(entry.id())?;
+    Ok(Some(lossy(blob.content())))
+}
+
+/// Line diff between two versions of a file's content.
+pub fn diff_contents(old: &str, new: &str) -> Result<FileDiff, String> {
+    if old == new {
+        return Ok(FileDiff::default());
+    }
+    let to_string = |e: git2::Error| e.message().to_string();
+    let mut options = DiffOptions::new();
+    options.context_lines(3);
+    let patch = Patch::from_buffers(
+        old.as_bytes(),
+        None,
+        new.as_bytes(),
+        None,
+        Some(&mut options),
+    )
+    .map_err(to_string)?;
+
+    let mut diff = FileDiff::default();
+    for hunk_idx in 0..patch.num_hunks() {
+        let (hunk, line_count) = patch.hunk(hunk_idx).map_err(to_string)?;
+        let mut lines = Vec::with_capacity(line_count);
+        for line_idx in 0..line_count {
+            let line = patch.line_in_hunk(hunk_idx, line_idx).map_err(to_string)?;
+            let kind = match line.origin() {
+                '+' => {
+                    diff.additions += 1;
+                    DiffLineKind::Added
                 }
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                // Removing an already-missing 'origin' is idempotent — converge on "not connected".
-                if stderr.contains("No such remote") {
-                    GitResult {
-                        success: true,
-                        message: None,
-                        error: None,
-                    }
-                } else {
-                    GitResult {
-                        success: false,
-                        message: None,
-                        error: Some(stderr.trim().to_string()),
-                    }
+                '-' => {
+                    diff.deletions += 1;
+                    DiffLineKind::Removed
                 }
-            }
+                ' ' => DiffLineKind::Context,
+                _ => continue, // "No newline at end of file" markers
+            };
+            let content = lossy(line.content());
+            let content = content.strip_suffix('\n').unwrap_or(&content);
+            lines.push(DiffLine {
+                kind,
+                content: content.strip_suffix('\r').unwrap_or(content).to_string(),
+                old_line: line.old_lineno(),
+                new_line: line.new_lineno(),
+            });
         }
-        Err(e) => GitResult {
-            success: false,
-            message: None,
-            error: Some(format!("Failed to remove remote: {}", e)),
-        },
+        diff.hunks.push(DiffHunk {
+            old_start: hunk.old_start(),
+            old_lines: hunk.old_lines(),
+            new_start: hunk.new_start(),
+            new_lines: hunk.new_lines(),
+            lines,
+        });
     }
+    Ok(diff)
 }
 
-/// Push to remote and set upstream tracking (git push -u origin <branch>)
-pub fn push_with_upstream(path: &Path, branch: &str) -> GitResult {
-    let output = git_cmd()
-        .args(["-c", "http.lowSpeedLimit=1000", "-c", "http.lowSpeedTime=10", "push", "-u", "origin", branch])
-        .env("GIT_SSH_COMMAND", "ssh -o ConnectTimeout=10")
-        .current_dir(path)
-        .output();
-
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                GitResult {
-                    success: true,
-                    message: Some(format!("Pushed and tracking origin/{}", branch)),
-                    error: None,
-                }
-            } else {
-                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                GitResult {
-                    success: false,
-                    message: None,
-                    error: Some(parse_push_error(&stderr)),
+/// Paths of files with unresolved conflicts, relative to the repository root.
+pub fn conflicted_paths(path: &Path) -> Result<Vec<String>, String> {
+    let paths = Repository::open(path).and_then(|repo| {
+        repo.index()?
+            .conflicts()?
+            .map(|conflict| {
+                let conflict = conflict?;
+                let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+                Ok(entry.map(|entry| lossy(&entry.path)))
+            })
+            .filter_map(Result::transpose)
+            .collect::<Result<Vec<_>, git2::Error>>()
+    });
+    paths.map_err(|e| e.message().to_string())
+}
+
+/// Files with unresolved conflicts, with their ours, theirs and base content.
+pub fn list_conflicts(path: &Path) -> Result<Vec<ConflictedFile>, String> {
+    let repo = Repository::open(path).map_err(|e| e.message().to_string())?;
+    let index = repo.index().map_err(|e| e.message().to_string())?;
+    conflicted_paths(path)?
+        .into_iter()
+        .map(|file| {
+            Ok(ConflictedFile {
+                ours: stage_content(&repo, &index, &file, ConflictSide::Ours)?,
+                theirs: stage_content(&repo, &index, &file, ConflictSide::Theirs)?,
+                base: stage_content(&repo, &index, &file, ConflictSide::Base)?,
+                path: file,
+            })
+        })
+        .collect()
+}
+
+/// A file's content at an index stage; `None` only if the stage has no entry.
+fn stage_content(
+    repo: &Repository,
+    index: &git2::Index,
+    file: &str,
+    side: ConflictSide,
+) -> Result<Option<String>, String> {
+    let Some(entry) = index.get_path(Path::new(file), side.stage()) else {
+        return Ok(None);
+    };
+    let blob = repo
+        .find_blob(entry.id)
+        .map_err(|e| e.message().to_string())?;
+    Ok(Some(lossy(blob.content())))
+}
+
+/// One side of a conflicted file, from its index stage; `None` if the file
+/// doesn't exist on that side.
+pub fn conflict_side(
+    path: &Path,
+    file: &str,
+    side: ConflictSide,
+) -> Result<Option<String>, String> {
+    let repo = Repository::open(path).map_err(|e| e.message().to_string())?;
+    let index = repo.index().map_err(|e| e.message().to_string())?;
+    stage_content(&repo, &index, file, side)
+}
+
+/// Mark a conflicted file resolved with whatever is now in the working tree
+/// (including its deletion).
+pub fn mark_resolved(path: &Path, file: &str) -> Result<(), String> {
+    let resolved = Repository::open(path).and_then(|repo| {
+        let mut index = repo.index()?;
+        let file = Path::new(file);
+        if repo.workdir().is_some_and(|dir| dir.join(file).exists()) {
+            index.add_path(file)?;
+        } else {
+            index.remove_path(file)?;
+        }
+        index.write()
+    });
+    resolved.map_err(|e| e.message().to_string())
+}
+
+/// Abort a merge stopped on conflicts, restoring the state before the pull.
+/// Like `git merge --abort`, local changes the merge didn't touch are kept,
+/// staged or not.
+pub fn abort_merge(path: &Path) -> GitResult {
+    let aborted = Repository::open(path).and_then(|repo| {
+        if repo.state() != RepositoryState::Merge {
+            return Ok(false);
+        }
+        let head = repo.head()?.peel_to_commit()?;
+        let head_tree = head.tree()?;
+
+        // Only files changed on the merged side can differ from HEAD because of the merge
+        let mut paths: Vec<String> = Vec::new();
+        for id in merge_heads(&repo) {
+            let theirs = repo.find_commit(id)?.tree()?;
+            let base = match repo.merge_base(head.id(), id) {
+                Ok(base) => repo.find_commit(base)?.tree()?,
+                Err(_) => head_tree.clone(),
+            };
+            let diff = repo.diff_tree_to_tree(Some(&base), Some(&theirs), None)?;
+            for delta in diff.deltas() {
+                for file in [delta.old_file(), delta.new_file()] {
+                    paths.extend(file.path_bytes().map(lossy));
                 }
             }
         }
-        Err(e) => GitResult {
-            success: false,
-            message: None,
-            error: Some(format!("Failed to push: {}", e)),
-        },
+        paths.extend(conflicted_paths(path).map_err(|e| git2::Error::from_str(&e))?);
+        paths.sort();
+        paths.dedup();
+
+        if !paths.is_empty() {
+            repo.reset_default(Some(head.as_object()), &paths)?;
+            let mut checkout = CheckoutBuilder::new();
+            checkout
+                .force()
+                .remove_untracked(true)
+                .disable_pathspec_match(true);
+            for path in &paths {
+                checkout.path(path);
+            }
+            repo.checkout_head(Some(&mut checkout))?;
+        }
+        repo.cleanup_state()?;
+        Ok(true)
+    });
+    match aborted {
+        Ok(true) => success("Merge aborted"),
+        Ok(false) => failure("No merge in progress"),
+        Err(e) => failure(e.message()),
     }
 }
 
@@ -534,41 +1176,595 @@ fn is_valid_remote_url(url: &str) -> bool {
     url.starts_with("git@") || url.starts_with("https://") || url.starts_with("http://")
 }
 
-/// Parse common remote errors (auth, network) shared by push/pull/fetch
-fn parse_remote_error(stderr: &str) -> Option<String> {
-    if stderr.contains("Permission denied") || stderr.contains("publickey") {
-        Some("Authentication failed. Check your SSH keys or credentials.".to_string())
-    } else if stderr.contains("Could not resolve host") {
-        Some("Could not connect to remote. Check your internet connection.".to_string())
-    } else {
-        None
-    }
-}
-
-/// Parse git pull errors into user-friendly messages
-fn parse_pull_error(stderr: &str) -> String {
-    if let Some(msg) = parse_remote_error(stderr) {
-        msg
-    } else if stderr.contains("local changes") || stderr.contains("unstaged changes") {
-        "Commit your changes before syncing with remote.".to_string()
-    } else if stderr.contains("CONFLICT") || stderr.contains("Merge conflict") {
-        "Pull failed due to merge conflicts. Resolve conflicts manually.".to_string()
-    } else if stderr.contains("not possible to fast-forward") {
-        "Pull failed: local and remote have diverged. Try pulling with rebase or merging manually.".to_string()
-    } else if stderr.contains("unrelated histories") {
-        "Pull failed: repositories have unrelated histories. Merge them manually or re-run with --allow-unrelated-histories.".to_string()
-    } else {
-        stderr.trim().to_string()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scratch repositories, removed when dropped.
+    struct Repos(PathBuf);
+
+    impl Repos {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("scratch-git-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Repos(root)
+        }
+
+        /// A new repository with an identity to commit as.
+        fn init(&self, name: &str) -> Repository {
+            let path = self.0.join(name);
+            git_init(&path).unwrap();
+            let repo = Repository::open(&path).unwrap();
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test").unwrap();
+            config.set_str("user.email", "test@example.com").unwrap();
+            repo
+        }
     }
-}
 
-/// Parse git push errors into user-friendly messages
-fn parse_push_error(stderr: &str) -> String {
-    if let Some(msg) = parse_remote_error(stderr) {
-        msg
-    } else if stderr.contains("Repository not found") || stderr.contains("does not exist") {
-        "Remote repository not found. Check the URL.".to_string()
-    } else {
-        stderr.trim().to_string()
+    impl Repos {
+        /// A bare "remote" with one commit on its default branch, and a clone
+        /// of it to work in.
+        fn remote_and_clone(&self, clone: &str) -> Repository {
+            let seed = self.init("seed");
+            write(&seed, "shared.md", "# Shared\n");
+            commit(&seed, "Initial");
+            Repository::init_bare(self.0.join("remote.git")).unwrap();
+            // `add_remote` only takes network URLs
+            seed.remote("origin", &self.remote()).unwrap();
+            let branch = current_branch(&seed).unwrap();
+            assert!(push_with_upstream(seed.workdir().unwrap(), &branch).success);
+            self.clone(clone)
+        }
+
+        fn remote(&self) -> String {
+            self.0.join("remote.git").to_string_lossy().into_owned()
+        }
+
+        /// A clone of the remote, tracking its branch.
+        fn clone(&self, name: &str) -> Repository {
+            let repo = Repository::clone(&self.remote(), self.0.join(name)).unwrap();
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test").unwrap();
+            config.set_str("user.email", "test@example.com").unwrap();
+            repo
+        }
+    }
+
+    impl Drop for Repos {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn write(repo: &Repository, file: &str, content: &str) {
+        let path = repo.workdir().unwrap().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// Commit every change, returning the new HEAD.
+    fn commit(repo: &Repository, message: &str) -> Oid {
+        let result = commit_all(repo.workdir().unwrap(), message);
+        assert!(result.success, "{:?}", result.error);
+        head(repo)
+    }
+
+    fn read(repo: &Repository, file: &str) -> Option<String> {
+        std::fs::read_to_string(repo.workdir().unwrap().join(file)).ok()
+    }
+
+    fn push_and_pull(from: &Repository, to: &Repository) -> Result<&'static str, Failure> {
+        assert!(push(from.workdir().unwrap()).success);
+        pull_in(to)
+    }
+
+    /// Paths whose index entry differs from HEAD.
+    fn staged(repo: &Repository) -> Vec<String> {
+        let head = repo.head().unwrap().peel_to_tree().unwrap();
+        repo.diff_tree_to_index(Some(&head), None, None)
+            .unwrap()
+            .deltas()
+            .filter_map(|delta| delta.new_file().path_bytes().map(lossy))
+            .collect()
+    }
+
+    /// Paths in HEAD's tree.
+    fn committed(repo: &Repository) -> Vec<String> {
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        let mut paths = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                paths.push(format!("{}{}", dir, entry.name().unwrap()));
+            }
+            git2::TreeWalkResult::Ok
+        })
+        .unwrap();
+        paths.sort();
+        paths
+    }
+
+    fn head(repo: &Repository) -> Oid {
+        repo.head().unwrap().target().unwrap()
+    }
+
+    fn note(version: u32) -> String {
+        let body: String = (1..=8)
+            .map(|i| format!("Line {} of the note\n", i))
+            .collect();
+        format!("# Plan\n\n{}\nVersion {}\n", body, version)
+    }
+
+    #[test]
+    fn history_follows_a_file_across_renames() {
+        let repos = Repos::new("history");
+        let repo = repos.init("notes");
+        write(&repo, "other.md", "# Other");
+        let before = commit(&repo, "Unrelated");
+        write(&repo, "a.md", &note(1));
+        let added = commit(&repo, "Add plan");
+        write(&repo, "a.md", &note(2));
+        let edited = commit(&repo, "Edit plan");
+        let workdir = repo.workdir().unwrap();
+        std::fs::create_dir_all(workdir.join("work")).unwrap();
+        std::fs::rename(workdir.join("a.md"), workdir.join("work/b.md")).unwrap();
+        let renamed = commit(&repo, "Move plan");
+        write(&repo, "work/b.md", &note(3));
+        write(&repo, "other.md", "# Other, edited");
+        commit(&repo, "Edit both");
+
+        let history = file_history(&repo, "work/b.md").unwrap();
+        let summary: Vec<(&str, FileChange, &str, Option<&str>)> = history
+            .iter()
+            .map(|c| {
+                (
+                    c.message.as_str(),
+                    c.change,
+                    c.path.as_str(),
+                    c.old_path.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Edit both", FileChange::Modified, "work/b.md", None),
+                ("Move plan", FileChange::Renamed, "work/b.md", Some("a.md")),
+                ("Edit plan", FileChange::Modified, "a.md", None),
+                ("Add plan", FileChange::Added, "a.md", None),
+            ]
+        );
+        assert_eq!(history[1].hash, renamed.to_string());
+        assert_eq!(history[0].author, "Test");
+        assert!(history[0].hash.starts_with(&history[0].short_hash));
+
+        // Shown by its current path at revisions from before the rename
+        let at = |rev: &str| match file_at(&repo, "work/b.md", rev) {
+            Ok(content) => content,
+            Err(_) => panic!("no content at {}", rev),
+        };
+        assert_eq!(at("HEAD"), Some(note(3)));
+        assert_eq!(at(&renamed.to_string()), Some(note(2)));
+        assert_eq!(at(&edited.to_string()), Some(note(2)));
+        assert_eq!(at(&added.to_string()), Some(note(1)));
+        assert_eq!(at("HEAD~3"), Some(note(1)));
+        assert_eq!(at(&before.to_string()), None);
+        assert!(matches!(
+            file_at(&repo, "work/b.md", "no-such-branch"),
+            Err(Failure::Message(m)) if m == "Unknown revision: no-such-branch"
+        ));
+
+        // Deleted at HEAD
+        std::fs::remove_file(repo.workdir().unwrap().join("work/b.md")).unwrap();
+        commit(&repo, "Delete plan");
+        assert_eq!(
+            file_history(&repo, "work/b.md").unwrap()[0].change,
+            FileChange::Deleted
+        );
+        assert_eq!(at("HEAD"), None);
+        assert_eq!(at("HEAD~1"), Some(note(3)));
+    }
+
+    #[test]
+    fn history_of_an_unborn_branch_is_empty() {
+        let repos = Repos::new("unborn-history");
+        let repo = repos.init("notes");
+        write(&repo, "a.md", "# A");
+        assert!(file_history(&repo, "a.md").unwrap().is_empty());
+    }
+
+    #[test]
+    fn diff_hunks_carry_line_numbers() {
+        let old: String = (1..=15).map(|i| format!("l{}\n", i)).collect();
+        let new = old.replacen("l1\n", "L1\n", 1).replace("l12\n", "");
+        let diff = diff_contents(&old, &new).unwrap();
+        assert_eq!((diff.additions, diff.deletions), (1, 2));
+
+        let ranges: Vec<(u32, u32, u32, u32)> = diff
+            .hunks
+            .iter()
+            .map(|h| (h.old_start, h.old_lines, h.new_start, h.new_lines))
+            .collect();
+        assert_eq!(ranges, vec![(1, 4, 1, 4), (9, 7, 9, 6)]);
+
+        let lines = |hunk: &DiffHunk| -> Vec<(DiffLineKind, String, Option<u32>, Option<u32>)> {
+            hunk.lines
+                .iter()
+                .map(|l| (l.kind, l.content.clone(), l.old_line, l.new_line))
+                .collect()
+        };
+        use DiffLineKind::*;
+        assert_eq!(
+            lines(&diff.hunks[0])[..3],
+            [
+                (Removed, "l1".to_string(), Some(1), None),
+                (Added, "L1".to_string(), None, Some(1)),
+                (Context, "l2".to_string(), Some(2), Some(2)),
+            ]
+        );
+        assert_eq!(
+            lines(&diff.hunks[1])[2..5],
+            [
+                (Context, "l11".to_string(), Some(11), Some(11)),
+                (Removed, "l12".to_string(), Some(12), None),
+                (Context, "l13".to_string(), Some(13), Some(12)),
+            ]
+        );
+
+        // Line endings and "no newline" markers aren't part of the lines
+        let diff = diff_contents("a\r\nb", "a\r\nc").unwrap();
+        let contents: Vec<&str> = diff.hunks[0]
+            .lines
+            .iter()
+            .map(|l| l.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["a", "b", "c"]);
+
+        assert!(diff_contents("same", "same").unwrap().hunks.is_empty());
+    }
+
+    #[test]
+    fn aborting_a_merge_keeps_local_changes_it_did_not_touch() {
+        let repos = Repos::new("abort");
+        let ours = repos.remote_and_clone("ours");
+        let theirs = repos.clone("theirs");
+        write(&ours, "notes.md", "# Notes\n");
+        commit(&ours, "Add notes");
+        assert!(push(ours.workdir().unwrap()).success);
+        assert!(pull_in(&theirs).is_ok());
+
+        write(&theirs, "shared.md", "# Shared\ntheirs\n");
+        write(&theirs, "new.md", "# From theirs\n");
+        commit(&theirs, "Their edit");
+        write(&ours, "shared.md", "# Shared\nours\n");
+        commit(&ours, "Our edit");
+
+        // Changes the merge has nothing to do with (a merge refuses to start
+        // with staged changes, so one is staged while it's stopped)
+        write(&ours, "notes.md", "# Notes\nunstaged\n");
+        write(&ours, "untracked.md", "# Untracked\n");
+        assert!(matches!(
+            push_and_pull(&theirs, &ours),
+            Err(Failure::Message(m)) if m.starts_with("Pull stopped on merge conflicts")
+        ));
+        assert_eq!(ours.state(), RepositoryState::Merge);
+        assert_eq!(read(&ours, "new.md").as_deref(), Some("# From theirs\n"));
+        write(&ours, "notes.md", "# Notes\nstaged\n");
+        let mut index = ours.index().unwrap();
+        index.add_path(Path::new("notes.md")).unwrap();
+        index.write().unwrap();
+        write(&ours, "notes.md", "# Notes\nstaged\nand unstaged\n");
+
+        assert!(abort_merge(ours.workdir().unwrap()).success);
+        assert_eq!(ours.state(), RepositoryState::Clean);
+        assert_eq!(
+            read(&ours, "shared.md").as_deref(),
+            Some("# Shared\nours\n")
+        );
+        assert_eq!(read(&ours, "new.md"), None);
+        assert_eq!(staged(&ours), vec!["notes.md"]);
+        assert_eq!(
+            read(&ours, "notes.md").as_deref(),
+            Some("# Notes\nstaged\nand unstaged\n")
+        );
+        assert_eq!(
+            read(&ours, "untracked.md").as_deref(),
+            Some("# Untracked\n")
+        );
+
+        assert!(!abort_merge(ours.workdir().unwrap()).success);
+    }
+
+    #[test]
+    fn local_app_data_is_neither_listed_nor_committed() {
+        let repos = Repos::new("local-only");
+        let repo = repos.init("notes");
+        write(&repo, "note.md", "# Note");
+        write(&repo, ".scratch/settings.json", "{}");
+        write(&repo, ".scratch/history/note.md/1-save.md.gz", "gz");
+        write(&repo, ".scratch/replace-undo/token.json", "[]");
+        write(&repo, ".trash/1/old.md", "# Old");
+        write(&repo, ".trashy.md", "# Not the trash");
+
+        let (files, _) = changes(&repo).unwrap();
+        let mut paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![".scratch/settings.json", ".trashy.md", "note.md"]
+        );
+
+        commit(&repo, "Notes");
+        assert_eq!(
+            committed(&repo),
+            vec![".scratch/settings.json", ".trashy.md", "note.md"]
+        );
+        assert!(changes(&repo).unwrap().0.is_empty());
+    }
+
+    fn changed(repo: &Repository, file: &str) -> ChangedFile {
+        let (files, _) = changes(repo).unwrap();
+        match files.into_iter().find(|f| f.path == file) {
+            Some(changed) => changed,
+            None => panic!("{} not changed", file),
+        }
+    }
+
+    #[test]
+    fn committing_selected_files_leaves_other_changes_alone() {
+        let repos = Repos::new("commit-files");
+        let repo = repos.init("notes");
+        for file in ["a.md", "b.md", "c.md"] {
+            write(&repo, file, &format!("# {}\n", file));
+        }
+        commit(&repo, "Initial");
+
+        write(&repo, "a.md", "# a.md\nedited\n");
+        write(&repo, "b.md", "# b.md\nstaged\n");
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("b.md")).unwrap();
+        index.write().unwrap();
+        write(&repo, "c.md", "# c.md\nunstaged\n");
+        write(&repo, "d.md", "# d.md\n");
+
+        let selected = changed(&repo, "a.md");
+        assert!(commit_files_in(&repo, &[selected], "Edit a").is_ok_and(|c| c));
+        let head = repo.head().unwrap().peel_to_tree().unwrap();
+        let blob = |file: &str| {
+            let id = head.get_path(Path::new(file)).unwrap().id();
+            lossy(repo.find_blob(id).unwrap().content())
+        };
+        assert_eq!(blob("a.md"), "# a.md\nedited\n");
+        assert_eq!(blob("b.md"), "# b.md\n");
+        assert_eq!(blob("c.md"), "# c.md\n");
+        assert_eq!(committed(&repo), vec!["a.md", "b.md", "c.md"]);
+
+        // Still staged, unstaged and untracked
+        assert_eq!(staged(&repo), vec!["b.md"]);
+        let mut paths: Vec<String> = changes(&repo)
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["b.md", "c.md", "d.md"]);
+        assert_eq!(read(&repo, "c.md").as_deref(), Some("# c.md\nunstaged\n"));
+    }
+
+    #[test]
+    fn committing_a_renamed_file_drops_its_old_path() {
+        let repos = Repos::new("commit-rename");
+        let repo = repos.init("notes");
+        write(&repo, "plan.md", &note(1));
+        write(&repo, "other.md", "# Other\n");
+        commit(&repo, "Initial");
+
+        let workdir = repo.workdir().unwrap();
+        std::fs::create_dir_all(workdir.join("work")).unwrap();
+        std::fs::rename(workdir.join("plan.md"), workdir.join("work/plan.md")).unwrap();
+        write(&repo, "other.md", "# Other\nedited\n");
+
+        let renamed = changed(&repo, "work/plan.md");
+        assert_eq!(renamed.status, FileChange::Renamed);
+        assert_eq!(renamed.old_path.as_deref(), Some("plan.md"));
+        assert_eq!(changes(&repo).unwrap().0.len(), 2);
+
+        assert!(commit_files_in(&repo, &[renamed], "Move plan").is_ok_and(|c| c));
+        assert_eq!(committed(&repo), vec!["other.md", "work/plan.md"]);
+        let history = file_history(&repo, "work/plan.md").unwrap();
+        assert_eq!(history[0].change, FileChange::Renamed);
+        assert_eq!(changed(&repo, "other.md").status, FileChange::Modified);
+    }
+
+    #[test]
+    fn selected_files_cannot_be_committed_during_a_merge() {
+        let repos = Repos::new("commit-merge");
+        let ours = repos.remote_and_clone("ours");
+        let theirs = repos.clone("theirs");
+        write(&theirs, "shared.md", "# Shared\ntheirs\n");
+        commit(&theirs, "Their edit");
+        write(&ours, "shared.md", "# Shared\nours\n");
+        commit(&ours, "Our edit");
+        assert!(push_and_pull(&theirs, &ours).is_err());
+
+        write(&ours, "other.md", "# Other\n");
+        let other = changed(&ours, "other.md");
+        assert!(matches!(
+            commit_files_in(&ours, &[other], "Other"),
+            Err(Failure::Message(m)) if m.starts_with("Can't commit selected files during a merge")
+        ));
+    }
+
+    fn staged_tree(repo: &Repository, files: &[&str]) -> Oid {
+        let mut index = repo.index().unwrap();
+        for file in files {
+            index.add_path(Path::new(file)).unwrap();
+        }
+        index.write().unwrap();
+        index.write_tree().unwrap()
+    }
+
+    #[test]
+    fn commit_tree_skips_commits_without_changes() {
+        let repos = Repos::new("commit-tree");
+        let repo = repos.init("notes");
+        let empty = repo.treebuilder(None).unwrap().write().unwrap();
+        assert!(!commit_tree(&repo, empty, "Nothing").unwrap());
+        assert!(repo.head().is_err());
+
+        // First commit on an unborn branch
+        write(&repo, "a.md", "# A\n");
+        let tree = staged_tree(&repo, &["a.md"]);
+        assert!(commit_tree(&repo, tree, "Add a").unwrap());
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(first.parent_count(), 0);
+        assert_eq!(first.message(), Some("Add a\n"));
+        assert!(!commit_tree(&repo, tree, "Again").unwrap());
+        assert_eq!(head(&repo), first.id());
+
+        write(&repo, "a.md", "# A\nedited\n");
+        let tree = staged_tree(&repo, &["a.md"]);
+        assert!(commit_tree(&repo, tree, "  \n").is_err());
+        assert_eq!(head(&repo), first.id());
+        assert!(commit_tree(&repo, tree, "Edit a").unwrap());
+        let second = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(second.parent_id(0).unwrap(), first.id());
+    }
+
+    #[test]
+    fn commit_tree_concludes_a_merge() {
+        let repos = Repos::new("commit-tree-merge");
+        let ours = repos.remote_and_clone("ours");
+        let theirs = repos.clone("theirs");
+        write(&theirs, "shared.md", "# Shared\ntheirs\n");
+        let their_commit = commit(&theirs, "Their edit");
+        write(&ours, "shared.md", "# Shared\nours\n");
+        let our_commit = commit(&ours, "Our edit");
+        assert!(push_and_pull(&theirs, &ours).is_err());
+        assert_eq!(ours.state(), RepositoryState::Merge);
+
+        // Keeping our side leaves the tree as it was, but still commits
+        write(&ours, "shared.md", "# Shared\nours\n");
+        let tree = staged_tree(&ours, &["shared.md"]);
+        assert_eq!(tree, ours.find_commit(our_commit).unwrap().tree_id());
+        assert!(commit_tree(&ours, tree, "Merge their edit").unwrap());
+
+        let merge = ours.head().unwrap().peel_to_commit().unwrap();
+        let parents: Vec<Oid> = merge.parent_ids().collect();
+        assert_eq!(parents, vec![our_commit, their_commit]);
+        assert_eq!(ours.state(), RepositoryState::Clean);
+        assert!(merge_heads(&ours).is_empty());
+        assert!(!commit_tree(&ours, tree, "Again").unwrap());
+    }
+
+    #[test]
+    fn pull_fast_forwards_or_merges() {
+        let repos = Repos::new("pull");
+        let ours = repos.remote_and_clone("ours");
+        let theirs = repos.clone("theirs");
+        assert!(matches!(pull_in(&ours), Ok("Already up to date")));
+
+        write(&theirs, "theirs.md", "# Theirs\n");
+        let their_commit = commit(&theirs, "Add theirs");
+        assert!(matches!(
+            push_and_pull(&theirs, &ours),
+            Ok("Pulled latest changes")
+        ));
+        assert_eq!(head(&ours), their_commit);
+        assert_eq!(read(&ours, "theirs.md").as_deref(), Some("# Theirs\n"));
+
+        write(&theirs, "theirs.md", "# Theirs\nedited\n");
+        let their_commit = commit(&theirs, "Edit theirs");
+        write(&ours, "ours.md", "# Ours\n");
+        let our_commit = commit(&ours, "Add ours");
+        assert!(matches!(
+            push_and_pull(&theirs, &ours),
+            Ok("Pulled latest changes")
+        ));
+
+        let merge = ours.head().unwrap().peel_to_commit().unwrap();
+        let parents: Vec<Oid> = merge.parent_ids().collect();
+        assert_eq!(parents, vec![our_commit, their_commit]);
+        let branch = current_branch(&ours).unwrap();
+        assert_eq!(
+            merge.message(),
+            Some(format!("Merge branch '{}' of {}\n", branch, repos.remote()).as_str())
+        );
+        assert_eq!(ours.state(), RepositoryState::Clean);
+        assert_eq!(
+            read(&ours, "theirs.md").as_deref(),
+            Some("# Theirs\nedited\n")
+        );
+        assert_eq!(read(&ours, "ours.md").as_deref(), Some("# Ours\n"));
+        assert!(changes(&ours).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn pull_explains_why_it_stopped() {
+        let repos = Repos::new("pull-errors");
+        let alone = repos.init("alone");
+        write(&alone, "a.md", "# A\n");
+        commit(&alone, "Add a");
+        assert!(matches!(
+            pull_in(&alone),
+            Err(Failure::Message(m)) if m == "The current branch doesn't track a remote branch."
+        ));
+
+        // Local edits the incoming changes would overwrite
+        let ours = repos.remote_and_clone("ours");
+        let theirs = repos.clone("theirs");
+        write(&theirs, "shared.md", "# Shared\ntheirs\n");
+        commit(&theirs, "Their edit");
+        write(&ours, "shared.md", "# Shared\nuncommitted\n");
+        assert!(matches!(
+            push_and_pull(&theirs, &ours),
+            Err(Failure::Message(m)) if m == "Commit your changes before syncing with remote."
+        ));
+        assert_eq!(
+            read(&ours, "shared.md").as_deref(),
+            Some("# Shared\nuncommitted\n")
+        );
+
+        // Conflicting commits stop the merge until it's resolved or aborted
+        commit(&ours, "Our edit");
+        assert!(matches!(
+            pull_in(&ours),
+            Err(Failure::Message(m))
+                if m == "Pull stopped on merge conflicts. Resolve them or abort the merge."
+        ));
+        assert_eq!(ours.state(), RepositoryState::Merge);
+        assert!(matches!(
+            pull_in(&ours),
+            Err(Failure::Message(m)) if m.starts_with("Finish or abort the merge in progress")
+        ));
+    }
+
+    #[test]
+    fn push_needs_an_upstream_branch() {
+        let repos = Repos::new("push");
+        let repo = repos.init("notes");
+        write(&repo, "a.md", "# A\n");
+        commit(&repo, "Add a");
+        let branch = current_branch(&repo).unwrap();
+        let result = push(repo.workdir().unwrap());
+        assert!(!result.success);
+        assert_eq!(
+            result.error,
+            Some(format!(
+                "The current branch {} has no upstream branch.",
+                branch
+            ))
+        );
+
+        let ours = repos.remote_and_clone("ours");
+        write(&ours, "a.md", "# A\n");
+        let pushed = commit(&ours, "Add a");
+        assert!(push(ours.workdir().unwrap()).success);
+        let remote = Repository::open_bare(repos.remote()).unwrap();
+        let refname = format!("refs/heads/{}", current_branch(&ours).unwrap());
+        assert_eq!(remote.refname_to_id(&refname).unwrap(), pushed);
     }
 }
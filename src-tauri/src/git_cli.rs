@@ -0,0 +1,656 @@
+//! The git CLI, used where the in-process library in `git` can't do the job,
+//! e.g. remotes needing credentials only the CLI's helpers or SSH config can
+//! supply, or repository features libgit2 doesn't support. Only used when git
+//! is installed.
+
+use std::path::Path;
+use std::process::Command;
+
+use crate::git::{ChangedFile, FileChange, FileCommit, GitResult, GitStatus};
+
+/// Create a `Command` for git that hides the console window on Windows.
+fn git_cmd() -> Command {
+    let cmd = Command::new("git");
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::process::CommandExt;
+        let mut cmd = cmd;
+        cmd.creation_flags(0x08000000); // CREATE_NO_WINDOW
+        cmd
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        cmd
+    }
+}
+
+/// Check if git CLI is available
+pub fn is_available() -> bool {
+    git_cmd()
+        .arg("--version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Get the current git status
+pub fn get_status(path: &Path) -> GitStatus {
+    if !crate::git::is_git_repo(path) {
+        return GitStatus::default();
+    }
+
+    let mut status = GitStatus {
+        is_repo: true,
+        ahead_count: -1,
+        behind_count: -1,
+        ..Default::default()
+    };
+
+    // Get current branch
+    if let Ok(output) = git_cmd()
+        .args(["branch", "--show-current"])
+        .current_dir(path)
+        .output()
+    {
+        if output.status.success() {
+            let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if !branch.is_empty() {
+                status.current_branch = Some(branch);
+            }
+        }
+    }
+
+    // Check for remote
+    if let Ok(output) = git_cmd().args(["remote"]).current_dir(path).output() {
+        status.has_remote =
+            output.status.success() && !String::from_utf8_lossy(&output.stdout).trim().is_empty();
+
+        // Get remote URL if remote exists
+        if status.has_remote {
+            status.remote_url = get_remote_url(path);
+        }
+    }
+
+    // Get status with porcelain format for easy parsing
+    if let Ok(stdout) = run_git(
+        path,
+        &["status", "--porcelain", "-z", "--untracked-files=all"],
+    ) {
+        let changes: Vec<_> = parse_porcelain(&stdout)
+            .into_iter()
+            .filter(|(_, file)| !crate::git::is_local_only(&file.path))
+            .collect();
+        status.changed_count = changes.len();
+        status.conflict_count = changes.iter().filter(|(code, _)| is_unmerged(code)).count();
+        status.changed_files = changes.into_iter().map(|(_, file)| file).collect();
+    }
+    status.merge_in_progress = path.join(".git").join("MERGE_HEAD").exists();
+
+    // Get ahead/behind count if we have a remote
+    if status.has_remote && status.current_branch.is_some() {
+        match git_cmd()
+            .args(["rev-list", "--left-right", "--count", "@{upstream}...HEAD"])
+            .current_dir(path)
+            .output()
+        {
+            Ok(output) => {
+                if output.status.success() {
+                    status.has_upstream = true;
+                    let stdout = String::from_utf8_lossy(&output.stdout);
+                    let parts: Vec<&str> = stdout.trim().split('\t').collect();
+                    if parts.len() == 2 {
+                        // parts[0] is behind count, parts[1] is ahead count
+                        status.behind_count = parts[0].parse().unwrap_or(0);
+                        status.ahead_count = parts[1].parse().unwrap_or(0);
+                    }
+                } else {
+                    // Command failed - likely no upstream configured
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    if stderr.contains("no upstream") || stderr.contains("unknown revision") {
+                        status.has_upstream = false;
+                        status.ahead_count = -1; // Sentinel value indicating no upstream
+                        status.behind_count = -1;
+                    }
+                }
+            }
+            Err(_) => {
+                status.has_upstream = false;
+                status.ahead_count = -1;
+                status.behind_count = -1;
+            }
+        }
+    }
+
+    status
+}
+
+/// Changed files (including untracked ones) with their status codes, from
+/// `git status --porcelain -z`
+fn parse_porcelain(stdout: &str) -> Vec<(String, ChangedFile)> {
+    let mut files = Vec::new();
+    let mut entries = stdout.split('\0').filter(|entry| !entry.is_empty());
+    while let Some(entry) = entries.next() {
+        let Some((code, file)) = entry.split_at_checked(3) else {
+            continue;
+        };
+        let code = code.trim_end();
+        // Renames and copies are followed by the original path
+        let old_path = if code.contains('R') || code.contains('C') {
+            entries.next().map(str::to_string)
+        } else {
+            None
+        };
+        let status = if is_unmerged(code) {
+            FileChange::Modified
+        } else if old_path.is_some() {
+            FileChange::Renamed
+        } else if code.contains('D') {
+            FileChange::Deleted
+        } else if code.starts_with('A') || code == "??" {
+            FileChange::Added
+        } else {
+            FileChange::Modified
+        };
+        files.push((
+            code.to_string(),
+            ChangedFile {
+                path: file.to_string(),
+                status,
+                old_path,
+                id: None,
+            },
+        ));
+    }
+    files
+}
+
+/// Unmerged status codes in `git status --porcelain`
+fn is_unmerged(code: &str) -> bool {
+    matches!(code, "DD" | "AU" | "UD" | "UA" | "DU" | "AA" | "UU")
+}
+
+/// Stage and commit only the given changes, leaving others (staged or not)
+/// as they are
+pub fn commit_files(path: &Path, files: &[ChangedFile], message: &str) -> GitResult {
+    if files.is_empty() {
+        return GitResult {
+            success: false,
+            message: None,
+            error: Some("Nothing selected to commit".to_string()),
+        };
+    }
+    // Paths are matched literally, not as globs
+    let with_paths = |args: &[&str], paths: &mut dyn Iterator<Item = &String>| -> Vec<String> {
+        let mut full: Vec<String> = std::iter::once("--literal-pathspecs")
+            .chain(args.iter().copied())
+            .chain(std::iter::once("--"))
+            .map(str::to_string)
+            .collect();
+        full.extend(paths.cloned());
+        full
+    };
+
+    // The old path of a staged rename is already gone from the index, so it's
+    // only needed for the commit
+    let stage = with_paths(&["add", "-A"], &mut files.iter().map(|file| &file.path));
+    if let Err(stderr) = run_git(path, &stage.iter().map(String::as_str).collect::<Vec<_>>()) {
+        return GitResult {
+            success: false,
+            message: None,
+            error: Some(format!("Failed to stage changes: {}", stderr)),
+        };
+    }
+
+    let mut paths = files
+        .iter()
+        .flat_map(|file| std::iter::once(&file.path).chain(&file.old_path));
+    let commit = with_paths(&["commit", "-m", message], &mut paths);
+    match run_git(path, &commit.iter().map(String::as_str).collect::<Vec<_>>()) {
+        Ok(_) => GitResult {
+            success: true,
+            message: Some(format!(
+                "Committed {} file{}",
+                files.len(),
+                if files.len() == 1 { "" } else { "s" }
+            )),
+            error: None,
+        },
+        // "nothing to commit" goes to stdout
+        Err(stderr) if stderr.is_empty() => GitResult {
+            success: true,
+            message: Some("Nothing to commit".to_string()),
+            error: None,
+        },
+        Err(stderr) => GitResult {
+            success: false,
+            message: None,
+            error: Some(stderr),
+        },
+    }
+}
+
+/// Stage all changes and commit
+pub fn commit_all(path: &Path, message: &str) -> GitResult {
+    // Stage all changes, except app data that stays local
+    let excludes: Vec<String> = crate::git::LOCAL_ONLY_DIRS
+        .iter()
+        .map(|dir| format!(":(exclude){}", dir))
+        .collect();
+    let stage_output = match git_cmd()
+        .args(["add", "-A", "--", "."])
+        .args(&excludes)
+        .current_dir(path)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            return GitResult {
+                success: false,
+                message: None,
+                error: Some(format!("Failed to run git add: {}", e)),
+            };
+        }
+    };
+
+    // Check if staging succeeded
+    if !stage_output.status.success() {
+        let stderr = String::from_utf8_lossy(&stage_output.stderr).to_string();
+        let stdout = String::from_utf8_lossy(&stage_output.stdout).to_string();
+        return GitResult {
+            success: false,
+            message: None,
+            error: Some(format!(
+                "Failed to stage changes: {}{}",
+                stderr,
+                if stdout.is_empty() {
+                    String::new()
+                } else {
+                    format!("\n{}", stdout)
+                }
+            )),
+        };
+    }
+
+    // Commit
+    let commit_output = git_cmd()
+        .args(["commit", "-m", message])
+        .current_dir(path)
+        .output();
+
+    match commit_output {
+        Ok(output) => {
+            if output.status.success() {
+                GitResult {
+                    success: true,
+                    message: Some("Changes committed".to_string()),
+                    error: None,
+                }
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+                // "nothing to commit" is not really an error
+                if stderr.contains("nothing to commit") {
+                    GitResult {
+                        success: true,
+                        message: Some("Nothing to commit".to_string()),
+                        error: None,
+                    }
+                } else {
+                    GitResult {
+                        success: false,
+                        message: None,
+                        error: Some(stderr),
+                    }
+                }
+            }
+        }
+        Err(e) => GitResult {
+            success: false,
+            message: None,
+            error: Some(format!("Failed to commit: {}", e)),
+        },
+    }
+}
+
+/// Push to remote
+pub fn push(path: &Path) -> GitResult {
+    let output = git_cmd()
+        .args([
+            "-c",
+            "http.lowSpeedLimit=1000",
+            "-c",
+            "http.lowSpeedTime=10",
+            "push",
+        ])
+        .env("GIT_SSH_COMMAND", "ssh -o ConnectTimeout=10")
+        .current_dir(path)
+        .output();
+
+    match output {
+        Ok(output) => {
+            if output.status.success() {
+                GitResult {
+                    success: true,
+                    message: Some("Pushed successfully".to_string()),
+                    error: None,
+                }
+            } else {
+                GitResult {
+                    success: false,
+                    message: None,
+                    error: Some(parse_push_error(&String::from_utf8_lossy(&output.stderr))),
+                }
+            }
+        }
+        Err(e) => GitResult {
+            success: false,
+            message: None,
+            error: Some(format!("Failed to push: {}", e)),
+        },
+    }
+}
+
+/// Fetch from remote to update tracking refs
+pub fn fetch(path: &Path) -> GitResult {
+    let output = git_cmd()
+        .args([
+            "-c",
+            "http.lowSpeedLimit=1000",
+            "-c",
+            "http.lowSpeedTime=10",
+            "fetch",
+            "--quiet",
+        ])
+        .env("GIT_SSH_COMMAND", "ssh -o ConnectTimeout=10")
+        .current_dir(path)
+        .output();
+
+    match output {
+        Ok(output) => {
+            if output.status.success() {
+                GitResult {
+                    success: true,
+                    message: Some("Fetched successfully".to_string()),
+                    error: None,
+                }
+            } else {
+                GitResult {
+                    success: false,
+                    message: None,
+                    error: Some(parse_pull_error(&String::from_utf8_lossy(&output.stderr))),
+                }
+            }
+        }
+        Err(e) => GitResult {
+            success: false,
+            message: None,
+            error: Some(format!("Failed to fetch: {}", e)),
+        },
+    }
+}
+
+/// Pull from remote
+pub fn pull(path: &Path) -> GitResult {
+    let output = git_cmd()
+        .args([
+            "-c",
+            "http.lowSpeedLimit=1000",
+            "-c",
+            "http.lowSpeedTime=10",
+            "-c",
+            "pull.rebase=false",
+            "pull",
+        ])
+        .env("GIT_SSH_COMMAND", "ssh -o ConnectTimeout=10")
+        .current_dir(path)
+        .output();
+
+    match output {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            if output.status.success() {
+                let message = if stdout.contains("Already up to date") {
+                    "Already up to date"
+                } else {
+                    "Pulled latest changes"
+                };
+                GitResult {
+                    success: true,
+                    message: Some(message.to_string()),
+                    error: None,
+                }
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let combined = format!("{}{}", stdout, stderr);
+                GitResult {
+                    success: false,
+                    message: None,
+                    error: Some(parse_pull_error(&combined)),
+                }
+            }
+        }
+        Err(e) => GitResult {
+            success: false,
+            message: None,
+            error: Some(format!("Failed to pull: {}", e)),
+        },
+    }
+}
+
+/// Get the URL of the 'origin' remote, if configured
+fn get_remote_url(path: &Path) -> Option<String> {
+    git_cmd()
+        .args(["remote", "get-url", "origin"])
+        .current_dir(path)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+}
+
+/// Push to remote and set upstream tracking (git push -u origin <branch>)
+pub fn push_with_upstream(path: &Path, branch: &str) -> GitResult {
+    let output = git_cmd()
+        .args([
+            "-c",
+            "http.lowSpeedLimit=1000",
+            "-c",
+            "http.lowSpeedTime=10",
+            "push",
+            "-u",
+            "origin",
+            branch,
+        ])
+        .env("GIT_SSH_COMMAND", "ssh -o ConnectTimeout=10")
+        .current_dir(path)
+        .output();
+
+    match output {
+        Ok(output) => {
+            if output.status.success() {
+                GitResult {
+                    success: true,
+                    message: Some(format!("Pushed and tracking origin/{}", branch)),
+                    error: None,
+                }
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+                GitResult {
+                    success: false,
+                    message: None,
+                    error: Some(parse_push_error(&stderr)),
+                }
+            }
+        }
+        Err(e) => GitResult {
+            success: false,
+            message: None,
+            error: Some(format!("Failed to push: {}", e)),
+        },
+    }
+}
+
+/// Run git and return its stdout, or its stderr as the error.
+fn run_git(path: &Path, args: &[&str]) -> Result<String, String> {
+    let output = git_cmd()
+        .args(args)
+        .current_dir(path)
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Commits touching a file, newest first, following it across renames.
+/// `file` is relative to the repository root.
+pub fn log_file(path: &Path, file: &str) -> Result<Vec<FileCommit>, String> {
+    // Records start with \x1e and fields are split by \x1f; the last field is
+    // followed by the NUL-separated name-status entry for the file
+    let stdout = run_git(
+        path,
+        &[
+            "log",
+            "--follow",
+            "-z",
+            "--name-status",
+            "--format=%x1e%H%x1f%h%x1f%an%x1f%ae%x1f%at%x1f%B%x1f",
+            "--",
+            file,
+        ],
+    )
+    .or_else(|e| {
+        // A repository without commits has no history for anything
+        if e.contains("does not have any commits") {
+            Ok(String::new())
+        } else {
+            Err(e)
+        }
+    })?;
+
+    let mut commits = Vec::new();
+    for record in stdout.split('\u{1e}').filter(|r| !r.is_empty()) {
+        let fields: Vec<&str> = record.split('\u{1f}').collect();
+        let [hash, short_hash, author, email, date, message, names] = fields[..] else {
+            continue;
+        };
+        let names: Vec<&str> = names
+            .split('\0')
+            .map(|s| s.trim_start_matches('\n'))
+            .filter(|s| !s.is_empty())
+            .collect();
+        let (change, old_path, path) = match names[..] {
+            [status, old, new] if status.starts_with('R') || status.starts_with('C') => {
+                (FileChange::Renamed, Some(old), new)
+            }
+            [status, file] => {
+                let change = match status {
+                    "A" => FileChange::Added,
+                    "D" => FileChange::Deleted,
+                    _ => FileChange::Modified,
+                };
+                (change, None, file)
+            }
+            _ => continue,
+        };
+        commits.push(FileCommit {
+            hash: hash.to_string(),
+            short_hash: short_hash.to_string(),
+            author: author.to_string(),
+            email: email.to_string(),
+            date: date.parse().unwrap_or(0),
+            message: message.trim_end().to_string(),
+            change,
+            path: path.to_string(),
+            old_path: old_path.map(str::to_string),
+        });
+    }
+    Ok(commits)
+}
+
+/// Resolve a revision (hash, branch, `HEAD~2`, ...) to a full commit hash.
+fn resolve_commit(path: &Path, rev: &str) -> Result<String, String> {
+    let rev = rev.trim();
+    if rev.is_empty() || rev.starts_with('-') {
+        return Err("Invalid revision".to_string());
+    }
+    run_git(
+        path,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", rev),
+        ],
+    )
+    .map(|hash| hash.trim().to_string())
+    .map_err(|_| format!("Unknown revision: {}", rev))
+}
+
+fn is_ancestor(path: &Path, ancestor: &str, commit: &str) -> bool {
+    git_cmd()
+        .args(["merge-base", "--is-ancestor", ancestor, commit])
+        .current_dir(path)
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Content of a file as of a revision, following renames: `file` is its
+/// current path, which it may not have had back then. `None` if the file
+/// didn't exist at that revision.
+pub fn show_file_at(path: &Path, file: &str, rev: &str) -> Result<Option<String>, String> {
+    let commit = resolve_commit(path, rev)?;
+    // The last commit touching the file up to `rev` has its path at `rev`
+    let Some(last) = log_file(path, file)?
+        .into_iter()
+        .find(|c| c.hash == commit || is_ancestor(path, &c.hash, &commit))
+    else {
+        return Ok(None);
+    };
+    if last.change == FileChange::Deleted {
+        return Ok(None);
+    }
+    run_git(path, &["show", &format!("{}:{}", commit, last.path)]).map(Some)
+}
+
+/// Parse common remote errors (auth, network) shared by push/pull/fetch
+fn parse_remote_error(stderr: &str) -> Option<String> {
+    if stderr.contains("Permission denied") || stderr.contains("publickey") {
+        Some("Authentication failed. Check your SSH keys or credentials.".to_string())
+    } else if stderr.contains("Could not resolve host") {
+        Some("Could not connect to remote. Check your internet connection.".to_string())
+    } else {
+        None
+    }
+}
+
+/// Parse git pull errors into user-friendly messages
+fn parse_pull_error(stderr: &str) -> String {
+    if let Some(msg) = parse_remote_error(stderr) {
+        msg
+    } else if stderr.contains("local changes") || stderr.contains("unstaged changes") {
+        "Commit your changes before syncing with remote.".to_string()
+    } else if stderr.contains("CONFLICT") || stderr.contains("Merge conflict") {
+        "Pull stopped on merge conflicts. Resolve them or abort the merge.".to_string()
+    } else if stderr.contains("not possible to fast-forward") {
+        "Pull failed: local and remote have diverged. Try pulling with rebase or merging manually."
+            .to_string()
+    } else if stderr.contains("unrelated histories") {
+        "Pull failed: repositories have unrelated histories. Merge them manually or re-run with --allow-unrelated-histories.".to_string()
+    } else {
+        stderr.trim().to_string()
+    }
+}
+
+/// Parse git push errors into user-friendly messages
+fn parse_push_error(stderr: &str) -> String {
+    if let Some(msg) = parse_remote_error(stderr) {
+        msg
+    } else if stderr.contains("Repository not found") || stderr.contains("does not exist") {
+        "Remote repository not found. Check the URL.".to_string()
+    } else {
+        stderr.trim().to_string()
+    }
+}
@@ -0,0 +1,762 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::{git, AppState};
+
+/// How often the scheduler checks whether a commit or sync is due.
+const TICK: Duration = Duration::from_secs(15);
+/// Failed commits and syncs are retried after the usual wait doubled per
+/// failure, up to this long (or the sync interval, if longer).
+const BACKOFF_MAX: Duration = Duration::from_secs(60 * 60);
+/// Note titles named in a commit message before "and N more".
+const MAX_TITLES: usize = 5;
+
+pub const DEFAULT_COMMIT_AFTER_MINUTES: u32 = 5;
+pub const DEFAULT_SYNC_INTERVAL_MINUTES: u32 = 15;
+pub const DEFAULT_MESSAGE_TEMPLATE: &str = "Update {titles} ({timestamp})";
+
+/// Automatic commits, pulls and pushes, for notes folders with git enabled.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoSyncSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Commit once notes haven't changed for this many minutes; `None` uses the default.
+    #[serde(default)]
+    pub commit_after_minutes: Option<u32>,
+    /// Minutes between pulls and pushes; `None` uses the default, 0 only commits.
+    #[serde(default)]
+    pub sync_interval_minutes: Option<u32>,
+    /// Fetch when the app starts, so ahead/behind counts are current; `None` means yes.
+    #[serde(default)]
+    pub fetch_on_startup: Option<bool>,
+    /// Commit message; `{titles}`, `{count}` and `{timestamp}` are filled in.
+    #[serde(default)]
+    pub message_template: Option<String>,
+}
+
+impl AutoSyncSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.commit_after_minutes == Some(0) {
+            return Err("Auto-commit delay must be at least a minute".to_string());
+        }
+        if self
+            .message_template
+            .as_deref()
+            .is_some_and(|template| template.trim().is_empty())
+        {
+            return Err("Auto-commit message can't be empty".to_string());
+        }
+        Ok(())
+    }
+
+    fn commit_after(&self) -> Duration {
+        minutes(
+            self.commit_after_minutes
+                .unwrap_or(DEFAULT_COMMIT_AFTER_MINUTES),
+        )
+    }
+
+    /// `None` if only committing.
+    fn sync_interval(&self) -> Option<Duration> {
+        match self
+            .sync_interval_minutes
+            .unwrap_or(DEFAULT_SYNC_INTERVAL_MINUTES)
+        {
+            0 => None,
+            interval => Some(minutes(interval)),
+        }
+    }
+
+    fn message_template(&self) -> &str {
+        self.message_template
+            .as_deref()
+            .map(str::trim)
+            .filter(|template| !template.is_empty())
+            .unwrap_or(DEFAULT_MESSAGE_TEMPLATE)
+    }
+}
+
+fn minutes(minutes: u32) -> Duration {
+    Duration::from_secs(u64::from(minutes) * 60)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncState {
+    /// Auto-sync is off, git is disabled, or the notes folder isn't a repository
+    #[default]
+    Disabled,
+    Idle,
+    Committing,
+    Pulling,
+    Pushing,
+    /// Paused until the merge conflicts from a pull are resolved or aborted
+    Conflicts,
+    /// The last commit or sync failed; it's retried at `next_sync`
+    Failed,
+}
+
+/// Sent as `git-sync-status` whenever it changes.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    pub state: SyncState,
+    pub error: Option<String>,
+    pub last_commit: Option<i64>, // Unix seconds
+    pub last_sync: Option<i64>,   // Unix seconds
+    pub next_sync: Option<i64>,   // Unix seconds; also when a failure is retried
+    pub failures: u32,            // Consecutive failures
+}
+
+enum Wake {
+    SettingsChanged,
+    SyncNow, // Commit, pull and push right away
+}
+
+/// The scheduler's shared state; the scheduler itself runs on its own thread.
+#[derive(Default)]
+pub struct Scheduler {
+    last_change: Mutex<Option<Instant>>,
+    status: Mutex<SyncStatus>,
+    wake: Mutex<Option<Sender<Wake>>>,
+}
+
+impl Scheduler {
+    /// A note changed, so the auto-commit waits for the notes to settle again.
+    pub fn note_changed(&self) {
+        *self.last_change.lock().expect("git sync mutex") = Some(Instant::now());
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status.lock().expect("git sync mutex").clone()
+    }
+
+    /// Re-read the settings now rather than at the next check.
+    pub fn settings_changed(&self) {
+        self.send(Wake::SettingsChanged);
+    }
+
+    pub fn sync_now(&self) {
+        self.send(Wake::SyncNow);
+    }
+
+    fn send(&self, wake: Wake) {
+        if let Some(tx) = self.wake.lock().expect("git sync mutex").as_ref() {
+            let _ = tx.send(wake);
+        }
+    }
+}
+
+/// Start the scheduler thread.
+pub fn start(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let (tx, rx) = mpsc::channel();
+    *state.git_sync.wake.lock().expect("git sync mutex") = Some(tx);
+    let app = app.clone();
+    std::thread::spawn(move || run(app, rx));
+}
+
+/// What the scheduler needs from the settings, when auto-sync applies.
+struct Config {
+    folder: PathBuf,
+    settings: AutoSyncSettings,
+}
+
+fn current_config(state: &AppState) -> Option<Config> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        PathBuf::from(app_config.notes_folder.clone()?)
+    };
+    let settings = {
+        let settings = state.settings.read().expect("settings read lock");
+        if settings.git_enabled != Some(true) {
+            return None;
+        }
+        settings.git_auto_sync.clone().filter(|auto| auto.enabled)?
+    };
+    git::is_git_repo(&folder).then_some(Config { folder, settings })
+}
+
+/// Timers for the notes folder being synced.
+struct Run {
+    folder: PathBuf,
+    fetched: bool,
+    next_sync: Option<Instant>, // Due now if unset
+    retry_at: Option<Instant>,  // Nothing runs before this after a failure
+}
+
+fn run(app: AppHandle, wake: Receiver<Wake>) {
+    let mut current: Option<Run> = None;
+    loop {
+        let sync_now = match wake.recv_timeout(TICK) {
+            Ok(Wake::SyncNow) => true,
+            Ok(Wake::SettingsChanged) | Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        let state = app.state::<AppState>();
+        let Some(config) = current_config(&state) else {
+            current = None;
+            update_status(&app, |status| *status = SyncStatus::default());
+            continue;
+        };
+        let run = match current.as_mut() {
+            Some(run) if run.folder == config.folder => run,
+            _ => {
+                update_status(&app, |status| {
+                    *status = SyncStatus {
+                        state: SyncState::Idle,
+                        ..Default::default()
+                    }
+                });
+                current.insert(Run {
+                    folder: config.folder.clone(),
+                    fetched: false,
+                    next_sync: None,
+                    retry_at: None,
+                })
+            }
+        };
+        tick(&app, &config, run, sync_now);
+    }
+}
+
+fn tick<R: Runtime>(app: &AppHandle<R>, config: &Config, run: &mut Run, sync_now: bool) {
+    let state = app.state::<AppState>();
+    let now = Instant::now();
+    if !sync_now && run.retry_at.is_some_and(|at| now < at) {
+        return;
+    }
+
+    // Manual git commands wait until this tick is done, and the other way round
+    let _git = state.git_lock.lock().expect("git mutex");
+
+    let status = git::get_status(&config.folder);
+    if !run.fetched && config.settings.fetch_on_startup != Some(false) && status.has_remote {
+        run.fetched = true;
+        // Only updates ahead/behind counts, so a failure here isn't retried
+        let _ = git::fetch(&config.folder);
+    }
+    if status.merge_in_progress || status.conflict_count > 0 {
+        update_status(app, |status| {
+            status.state = SyncState::Conflicts;
+            status.error = None;
+            status.next_sync = None;
+        });
+        return;
+    }
+
+    // Commit once notes have settled
+    if status.changed_count == 0 {
+        *state.git_sync.last_change.lock().expect("git sync mutex") = None;
+    } else {
+        let last_change = *state
+            .git_sync
+            .last_change
+            .lock()
+            .expect("git sync mutex")
+            .get_or_insert(now);
+        if sync_now || now.duration_since(last_change) >= config.settings.commit_after() {
+            update_status(app, |status| status.state = SyncState::Committing);
+            let message =
+                commit_message(&state, &config.folder, config.settings.message_template());
+            let result = git::commit_all(&config.folder, &message);
+            if !result.success {
+                fail(app, config, run, result.error);
+                return;
+            }
+            *state.git_sync.last_change.lock().expect("git sync mutex") = None;
+            update_status(app, |status| status.last_commit = Some(unix_now()));
+        }
+    }
+
+    // Pull and push on the interval
+    let Some(interval) = config.settings.sync_interval() else {
+        succeed(app, run, None);
+        return;
+    };
+    if !status.has_remote || !(sync_now || run.next_sync.is_none_or(|at| now >= at)) {
+        succeed(app, run, None);
+        return;
+    }
+    if let Err(error) = sync(app, &config.folder) {
+        fail(app, config, run, Some(error));
+        return;
+    }
+    run.next_sync = Some(Instant::now() + interval);
+    succeed(app, run, Some(unix_now()));
+}
+
+/// Pull, then push whatever is ahead of the remote.
+fn sync<R: Runtime>(app: &AppHandle<R>, folder: &Path) -> Result<(), String> {
+    let status = git::get_status(folder);
+    if status.has_upstream {
+        update_status(app, |status| status.state = SyncState::Pulling);
+        let result = git::pull(folder);
+        if !result.success {
+            return Err(result.error.unwrap_or_default());
+        }
+    }
+
+    let status = git::get_status(folder);
+    let result = if !status.has_upstream {
+        let Some(branch) = status.current_branch else {
+            return Err("No current branch found".to_string());
+        };
+        update_status(app, |status| status.state = SyncState::Pushing);
+        git::push_with_upstream(folder, &branch)
+    } else if status.ahead_count > 0 {
+        update_status(app, |status| status.state = SyncState::Pushing);
+        git::push(folder)
+    } else {
+        return Ok(());
+    };
+    if result.success {
+        Ok(())
+    } else {
+        Err(result.error.unwrap_or_default())
+    }
+}
+
+fn succeed<R: Runtime>(app: &AppHandle<R>, run: &mut Run, synced_at: Option<i64>) {
+    run.retry_at = None;
+    let next_sync = run.next_sync.map(unix_time);
+    update_status(app, |status| {
+        status.state = SyncState::Idle;
+        status.error = None;
+        status.failures = 0;
+        status.next_sync = next_sync;
+        if synced_at.is_some() {
+            status.last_sync = synced_at;
+        }
+    });
+}
+
+fn fail<R: Runtime>(app: &AppHandle<R>, config: &Config, run: &mut Run, error: Option<String>) {
+    // A pull that stopped on conflicts waits for them instead of retrying
+    if git::get_status(&config.folder).merge_in_progress {
+        update_status(app, |status| {
+            status.state = SyncState::Conflicts;
+            status.error = error;
+            status.next_sync = None;
+        });
+        return;
+    }
+
+    let failures = app.state::<AppState>().git_sync.status().failures + 1;
+    let base = config
+        .settings
+        .sync_interval()
+        .unwrap_or_else(|| config.settings.commit_after());
+    let delay = base
+        .saturating_mul(1 << failures.min(16))
+        .min(BACKOFF_MAX.max(base));
+    let retry_at = Instant::now() + delay;
+    run.retry_at = Some(retry_at);
+    run.next_sync = Some(retry_at);
+    update_status(app, |status| {
+        status.state = SyncState::Failed;
+        status.error = Some(error.unwrap_or_else(|| "Unknown error".to_string()));
+        status.failures = failures;
+        status.next_sync = Some(unix_time(retry_at));
+    });
+}
+
+/// Fill in the message template for the current changes.
+fn commit_message(state: &AppState, folder: &Path, template: &str) -> String {
+    let files = git::changed_files(folder).unwrap_or_default();
+    let ignored_dirs = {
+        let settings = state.settings.read().expect("settings read lock");
+        crate::get_effective_ignored_dirs(&settings)
+    };
+    let titles: Vec<String> = {
+        let cache = state.notes_cache.read().expect("cache read lock");
+        files
+            .iter()
+            .filter_map(|file| {
+                crate::id_from_abs_path(folder, &folder.join(&file.path), &ignored_dirs)
+            })
+            .map(|id| match cache.get(&id) {
+                Some(note) => note.title.clone(),
+                // Deleted notes are no longer listed
+                None => id.rsplit('/').next().unwrap_or(&id).to_string(),
+            })
+            .collect()
+    };
+
+    let mut listed = titles
+        .iter()
+        .take(MAX_TITLES)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if titles.len() > MAX_TITLES {
+        listed.push_str(&format!(" and {} more", titles.len() - MAX_TITLES));
+    } else if listed.is_empty() {
+        listed = "notes".to_string();
+    }
+    template
+        .replace("{titles}", &listed)
+        .replace("{count}", &files.len().to_string())
+        .replace(
+            "{timestamp}",
+            &chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+        )
+}
+
+/// Change the status, emitting `git-sync-status` if that changed it.
+fn update_status<R: Runtime>(app: &AppHandle<R>, change: impl FnOnce(&mut SyncStatus)) {
+    let state = app.state::<AppState>();
+    let mut status = state.git_sync.status.lock().expect("git sync mutex");
+    let before = status.clone();
+    change(&mut status);
+    if *status != before {
+        let _ = app.emit("git-sync-status", status.clone());
+    }
+}
+
+fn unix_now() -> i64 {
+    crate::index_store::now_secs()
+}
+
+fn unix_time(at: Instant) -> i64 {
+    unix_now() + at.saturating_duration_since(Instant::now()).as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::test::{mock_app, MockRuntime};
+
+    /// Scratch repositories (a bare "remote" and clones of it), removed when dropped.
+    struct Repos(PathBuf);
+
+    impl Repos {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "scratch-git-sync-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            git2::Repository::init_bare(root.join("remote.git")).unwrap();
+            Repos(root)
+        }
+
+        fn remote(&self) -> String {
+            self.0.join("remote.git").to_string_lossy().into_owned()
+        }
+
+        /// A working copy with `origin` set to the remote.
+        fn working_copy(&self, name: &str) -> PathBuf {
+            let path = self.0.join(name);
+            git::git_init(&path).unwrap();
+            let repo = git2::Repository::open(&path).unwrap();
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test").unwrap();
+            config.set_str("user.email", "test@example.com").unwrap();
+            // `git::add_remote` only takes network URLs
+            repo.remote("origin", &self.remote()).unwrap();
+            path
+        }
+
+        /// A clone of the remote, tracking its branch.
+        fn clone(&self, name: &str) -> PathBuf {
+            let path = self.0.join(name);
+            let repo = git2::Repository::clone(&self.remote(), &path).unwrap();
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Other").unwrap();
+            config.set_str("user.email", "other@example.com").unwrap();
+            path
+        }
+
+        fn set_origin(path: &Path, url: &str) {
+            let repo = git2::Repository::open(path).unwrap();
+            repo.remote_set_url("origin", url).unwrap();
+        }
+    }
+
+    impl Drop for Repos {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn app() -> tauri::App<MockRuntime> {
+        let app = mock_app();
+        app.manage(AppState::default());
+        app
+    }
+
+    fn config(folder: &Path, commit_after: u32, sync_interval: u32) -> Config {
+        Config {
+            folder: folder.to_path_buf(),
+            settings: AutoSyncSettings {
+                enabled: true,
+                commit_after_minutes: Some(commit_after),
+                sync_interval_minutes: Some(sync_interval),
+                fetch_on_startup: Some(false),
+                message_template: Some("{count}: {titles}".to_string()),
+            },
+        }
+    }
+
+    fn new_run(folder: &Path) -> Run {
+        Run {
+            folder: folder.to_path_buf(),
+            fetched: false,
+            next_sync: None,
+            retry_at: None,
+        }
+    }
+
+    fn status(app: &tauri::App<MockRuntime>) -> SyncStatus {
+        app.state::<AppState>().git_sync.status()
+    }
+
+    /// Pretend the last note change was this long ago.
+    fn changed_ago(app: &tauri::App<MockRuntime>, ago: Duration) {
+        *app.state::<AppState>().git_sync.last_change.lock().unwrap() =
+            Instant::now().checked_sub(ago);
+    }
+
+    fn head_message(folder: &Path) -> String {
+        let repo = git2::Repository::open(folder).unwrap();
+        let message = repo
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .message()
+            .unwrap()
+            .to_string();
+        message.trim_end().to_string()
+    }
+
+    fn commit_and_push(folder: &Path, file: &str, content: &str) {
+        std::fs::write(folder.join(file), content).unwrap();
+        assert!(git::commit_all(folder, &format!("Edit {}", file)).success);
+        let status = git::get_status(folder);
+        let pushed = if status.has_upstream {
+            git::push(folder)
+        } else {
+            git::push_with_upstream(folder, status.current_branch.as_deref().unwrap())
+        };
+        assert!(pushed.success, "{:?}", pushed.error);
+    }
+
+    #[test]
+    fn commits_once_notes_settle() {
+        let repos = Repos::new("commit");
+        let notes = repos.working_copy("notes");
+        let app = app();
+        let handle = app.handle();
+        let config = config(&notes, 5, 0);
+        let mut run = new_run(&notes);
+
+        std::fs::write(notes.join("a.md"), "# A").unwrap();
+        app.state::<AppState>().git_sync.note_changed();
+        tick(handle, &config, &mut run, false);
+        assert_eq!(git::get_status(&notes).changed_count, 1);
+        assert_eq!(status(&app).state, SyncState::Idle);
+        assert_eq!(status(&app).last_commit, None);
+
+        changed_ago(&app, Duration::from_secs(6 * 60));
+        tick(handle, &config, &mut run, false);
+        assert_eq!(git::get_status(&notes).changed_count, 0);
+        assert_eq!(head_message(&notes), "1: a");
+        assert!(status(&app).last_commit.is_some());
+
+        // "Sync now" doesn't wait
+        std::fs::write(notes.join("b.md"), "# B").unwrap();
+        app.state::<AppState>().git_sync.note_changed();
+        tick(handle, &config, &mut run, true);
+        assert_eq!(git::get_status(&notes).changed_count, 0);
+        assert_eq!(head_message(&notes), "1: b");
+    }
+
+    #[test]
+    fn waits_for_git_commands_in_progress() {
+        let repos = Repos::new("lock");
+        let notes = repos.working_copy("notes");
+        let app = app();
+        let handle = app.handle().clone();
+        let config = config(&notes, 5, 0);
+        std::fs::write(notes.join("a.md"), "# A").unwrap();
+
+        // A manual command holds the lock
+        let git_lock = app.state::<AppState>().git_lock.clone();
+        let git = git_lock.lock().unwrap();
+        let folder = notes.clone();
+        let ticking = std::thread::spawn(move || {
+            tick(&handle, &config, &mut new_run(&folder), true);
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(git::get_status(&notes).changed_count, 1);
+
+        drop(git);
+        ticking.join().unwrap();
+        assert_eq!(git::get_status(&notes).changed_count, 0);
+    }
+
+    #[test]
+    fn pulls_and_pushes_on_the_interval() {
+        let repos = Repos::new("interval");
+        let notes = repos.working_copy("notes");
+        let app = app();
+        let handle = app.handle();
+        let config = config(&notes, 5, 15);
+        let mut run = new_run(&notes);
+
+        // The first sync is due right away, and sets the upstream
+        std::fs::write(notes.join("a.md"), "# A").unwrap();
+        tick(handle, &config, &mut run, true);
+        let after = git::get_status(&notes);
+        assert!(after.has_upstream);
+        assert_eq!(after.ahead_count, 0);
+        assert!(status(&app).last_sync.is_some());
+        let next = run.next_sync.unwrap();
+        assert!(next > Instant::now() + Duration::from_secs(14 * 60));
+
+        // Commits in between wait for the next sync
+        std::fs::write(notes.join("b.md"), "# B").unwrap();
+        changed_ago(&app, Duration::from_secs(6 * 60));
+        tick(handle, &config, &mut run, false);
+        assert_eq!(git::get_status(&notes).ahead_count, 1);
+        assert_eq!(run.next_sync, Some(next));
+
+        // Once due, pull what was pushed elsewhere, then push
+        let other = repos.clone("other");
+        commit_and_push(&other, "c.md", "# C");
+        run.next_sync = Instant::now().checked_sub(Duration::from_secs(1));
+        tick(handle, &config, &mut run, false);
+        let after = git::get_status(&notes);
+        assert_eq!((after.ahead_count, after.behind_count), (0, 0));
+        assert!(notes.join("c.md").exists());
+        assert_eq!(status(&app).state, SyncState::Idle);
+    }
+
+    #[test]
+    fn pauses_on_conflicts() {
+        let repos = Repos::new("conflicts");
+        let notes = repos.working_copy("notes");
+        let app = app();
+        let handle = app.handle();
+        let config = config(&notes, 5, 15);
+        let mut run = new_run(&notes);
+
+        std::fs::write(notes.join("a.md"), "# A\n").unwrap();
+        tick(handle, &config, &mut run, true);
+        let other = repos.clone("other");
+        commit_and_push(&other, "a.md", "# A\ntheirs\n");
+
+        std::fs::write(notes.join("a.md"), "# A\nours\n").unwrap();
+        tick(handle, &config, &mut run, true);
+        let paused = status(&app);
+        assert_eq!(paused.state, SyncState::Conflicts);
+        assert_eq!((paused.failures, paused.next_sync), (0, None));
+        assert_eq!(git::conflicted_paths(&notes).unwrap(), vec!["a.md"]);
+
+        // Nothing is committed or synced until the conflict is dealt with
+        let head = head_message(&notes);
+        tick(handle, &config, &mut run, true);
+        assert_eq!(status(&app).state, SyncState::Conflicts);
+        assert_eq!(head_message(&notes), head);
+
+        assert!(git::abort_merge(&notes).success);
+        tick(handle, &config, &mut run, false);
+        assert_ne!(status(&app).state, SyncState::Conflicts);
+    }
+
+    #[test]
+    fn failures_back_off_and_recover() {
+        let repos = Repos::new("backoff");
+        let notes = repos.working_copy("notes");
+        let missing = repos.0.join("missing.git").to_string_lossy().into_owned();
+        Repos::set_origin(&notes, &missing);
+        let app = app();
+        let handle = app.handle();
+        let config = config(&notes, 5, 1);
+        let mut run = new_run(&notes);
+        let retry_in = |run: &Run| run.retry_at.unwrap() - Instant::now();
+
+        std::fs::write(notes.join("a.md"), "# A").unwrap();
+        tick(handle, &config, &mut run, true);
+        let failed = status(&app);
+        assert_eq!((failed.state, failed.failures), (SyncState::Failed, 1));
+        assert!(failed.error.is_some());
+        // The interval, doubled per failure
+        assert!(retry_in(&run) > Duration::from_secs(110) && retry_in(&run) <= minutes(2));
+        assert_eq!(run.next_sync, run.retry_at);
+
+        // Nothing is tried before the retry, unless asked
+        tick(handle, &config, &mut run, false);
+        assert_eq!(status(&app).failures, 1);
+        tick(handle, &config, &mut run, true);
+        assert_eq!(status(&app).failures, 2);
+        assert!(retry_in(&run) > Duration::from_secs(230) && retry_in(&run) <= minutes(4));
+
+        // Capped at an hour
+        update_status(handle, |status| status.failures = 20);
+        tick(handle, &config, &mut run, true);
+        assert_eq!(status(&app).failures, 21);
+        assert!(retry_in(&run) > minutes(59) && retry_in(&run) <= minutes(60));
+
+        Repos::set_origin(&notes, &repos.remote());
+        tick(handle, &config, &mut run, true);
+        let recovered = status(&app);
+        assert_eq!(
+            (recovered.state, recovered.failures, recovered.error),
+            (SyncState::Idle, 0, None)
+        );
+        assert_eq!(run.retry_at, None);
+    }
+
+    #[test]
+    fn commit_messages_fill_in_the_template() {
+        let repos = Repos::new("message");
+        let notes = repos.working_copy("notes");
+        let state = AppState::default();
+        state.notes_cache.write().unwrap().insert(
+            "a".to_string(),
+            crate::NoteMetadata {
+                id: "a".to_string(),
+                title: "Alpha Plan".to_string(),
+                preview: String::new(),
+                modified: 0,
+                created: 0,
+                frontmatter: Default::default(),
+            },
+        );
+
+        assert_eq!(
+            commit_message(&state, &notes, "{titles} ({count})"),
+            "notes (0)"
+        );
+
+        std::fs::create_dir_all(notes.join("dir")).unwrap();
+        std::fs::write(notes.join("a.md"), "# Alpha Plan").unwrap();
+        std::fs::write(notes.join("dir/b.md"), "# B").unwrap();
+        assert_eq!(
+            commit_message(&state, &notes, "{count} notes: {titles}"),
+            "2 notes: Alpha Plan, b"
+        );
+
+        for name in ["c", "d", "e", "f", "g"] {
+            std::fs::write(notes.join(format!("{}.md", name)), "text").unwrap();
+        }
+        assert_eq!(
+            commit_message(&state, &notes, "{titles}"),
+            "Alpha Plan, c, d, b, e and 2 more"
+        );
+
+        let stamped = commit_message(&state, &notes, "at {timestamp}");
+        let stamp = stamped.strip_prefix("at ").unwrap();
+        assert!(chrono::NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M").is_ok());
+    }
+}
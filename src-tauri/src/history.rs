@@ -0,0 +1,383 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Snapshots for note `a/b` live in `.scratch/history/a/b.md/`, mirroring the
+/// note's own path so a note and a folder with the same name never share a directory.
+const HISTORY_DIR: &str = "history";
+const SNAPSHOT_EXT: &str = ".md.gz";
+
+pub const DEFAULT_INTERVAL_MINUTES: u32 = 10;
+pub const DEFAULT_MAX_VERSIONS: u32 = 50;
+pub const DEFAULT_RETENTION_DAYS: u32 = 90;
+
+/// Snapshot throttling and retention, resolved from settings.
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryPolicy {
+    pub interval_minutes: u32,
+    pub max_versions: u32,   // 0 = unlimited
+    pub retention_days: u32, // 0 = unlimited
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotSource {
+    Save,
+    Ai,
+    Restore,
+    Replace,
+}
+
+impl SnapshotSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            SnapshotSource::Save => "save",
+            SnapshotSource::Ai => "ai",
+            SnapshotSource::Restore => "restore",
+            SnapshotSource::Replace => "replace",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "save" => Some(SnapshotSource::Save),
+            "ai" => Some(SnapshotSource::Ai),
+            "restore" => Some(SnapshotSource::Restore),
+            "replace" => Some(SnapshotSource::Replace),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteVersion {
+    pub id: String,   // Snapshot file stem: "<unix millis>-<source>"
+    pub created: i64, // Unix seconds
+    pub source: SnapshotSource,
+    pub size: u64, // Compressed size in bytes
+}
+
+fn history_root(notes_root: &Path) -> PathBuf {
+    notes_root.join(".scratch").join(HISTORY_DIR)
+}
+
+fn note_history_dir(notes_root: &Path, id: &str) -> PathBuf {
+    let mut os = history_root(notes_root)
+        .join(id.replace('/', std::path::MAIN_SEPARATOR_STR))
+        .into_os_string();
+    os.push(".md");
+    PathBuf::from(os)
+}
+
+fn folder_history_dir(notes_root: &Path, folder: &str) -> PathBuf {
+    history_root(notes_root).join(folder.replace('/', std::path::MAIN_SEPARATOR_STR))
+}
+
+fn now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Parse "<millis>-<source>" into its parts.
+fn parse_version_id(version_id: &str) -> Option<(i64, SnapshotSource)> {
+    let (millis, source) = version_id.split_once('-')?;
+    Some((millis.parse().ok()?, SnapshotSource::parse(source)?))
+}
+
+/// List snapshots for a note, newest first.
+pub fn list_versions(notes_root: &Path, id: &str) -> Vec<NoteVersion> {
+    let Ok(entries) = std::fs::read_dir(note_history_dir(notes_root, id)) else {
+        return Vec::new();
+    };
+
+    let mut versions: Vec<(i64, NoteVersion)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let version_id = name.strip_suffix(SNAPSHOT_EXT)?.to_string();
+            let (millis, source) = parse_version_id(&version_id)?;
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            Some((
+                millis,
+                NoteVersion {
+                    id: version_id,
+                    created: millis / 1000,
+                    source,
+                    size,
+                },
+            ))
+        })
+        .collect();
+
+    versions.sort_by_key(|(millis, _)| std::cmp::Reverse(*millis));
+    versions.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Read and decompress a snapshot.
+pub fn read_version(notes_root: &Path, id: &str, version_id: &str) -> Result<String, String> {
+    if parse_version_id(version_id).is_none() {
+        return Err("Invalid version ID".to_string());
+    }
+
+    let path = note_history_dir(notes_root, id).join(format!("{}{}", version_id, SNAPSHOT_EXT));
+    let file = std::fs::File::open(&path).map_err(|_| "Version not found".to_string())?;
+    let mut content = String::new();
+    GzDecoder::new(file)
+        .read_to_string(&mut content)
+        .map_err(|e| format!("Failed to read version: {}", e))?;
+    Ok(content)
+}
+
+/// Store a snapshot of `content` for a note. When `throttle` is set, no snapshot
+/// is taken if the previous one is newer than the policy interval. Identical
+/// consecutive snapshots are skipped. Returns the new version, if one was written.
+pub fn snapshot(
+    notes_root: &Path,
+    id: &str,
+    content: &str,
+    source: SnapshotSource,
+    policy: &HistoryPolicy,
+    throttle: bool,
+) -> Result<Option<NoteVersion>, String> {
+    let now = now_millis();
+    let existing = list_versions(notes_root, id);
+
+    if let Some(latest) = existing.first() {
+        let latest_millis = parse_version_id(&latest.id).map(|(m, _)| m).unwrap_or(0);
+        let interval_millis = i64::from(policy.interval_minutes) * 60 * 1000;
+        if throttle && now - latest_millis < interval_millis {
+            return Ok(None);
+        }
+        if read_version(notes_root, id, &latest.id).is_ok_and(|previous| previous == content) {
+            return Ok(None);
+        }
+    }
+
+    let dir = note_history_dir(notes_root, id);
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let version_id = format!("{}-{}", now, source.as_str());
+    let path = dir.join(format!("{}{}", version_id, SNAPSHOT_EXT));
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(content.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let compressed = encoder.finish().map_err(|e| e.to_string())?;
+    crate::fsutil::write_atomic(&path, &compressed)
+        .map_err(|e| format!("Failed to write snapshot: {}", e))?;
+
+    prune(notes_root, id, policy);
+
+    Ok(Some(NoteVersion {
+        id: version_id,
+        created: now / 1000,
+        source,
+        size: compressed.len() as u64,
+    }))
+}
+
+/// Drop snapshots beyond the version limit or older than the retention period.
+fn prune(notes_root: &Path, id: &str, policy: &HistoryPolicy) {
+    let cutoff = now_millis() / 1000 - i64::from(policy.retention_days) * 24 * 60 * 60;
+    let dir = note_history_dir(notes_root, id);
+
+    for (i, version) in list_versions(notes_root, id).iter().enumerate() {
+        let over_limit = policy.max_versions > 0 && i >= policy.max_versions as usize;
+        let expired = policy.retention_days > 0 && version.created < cutoff;
+        if over_limit || expired {
+            let _ = std::fs::remove_file(dir.join(format!("{}{}", version.id, SNAPSHOT_EXT)));
+        }
+    }
+}
+
+/// Move a note's snapshots along with a rename or move.
+pub fn rename_note(notes_root: &Path, old_id: &str, new_id: &str) {
+    move_dir(
+        &note_history_dir(notes_root, old_id),
+        &note_history_dir(notes_root, new_id),
+    );
+}
+
+/// Move snapshots for every note in a folder along with a folder rename or move.
+pub fn rename_folder(notes_root: &Path, old_path: &str, new_path: &str) {
+    move_dir(
+        &folder_history_dir(notes_root, old_path),
+        &folder_history_dir(notes_root, new_path),
+    );
+}
+
+/// Move a directory, merging into the destination if it already exists
+/// (e.g. a note took over the ID of an earlier, deleted note).
+fn move_dir(from: &Path, to: &Path) {
+    if !from.is_dir() {
+        return;
+    }
+    if !to.exists() {
+        if let Some(parent) = to.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = std::fs::rename(from, to);
+        return;
+    }
+
+    if let Ok(entries) = std::fs::read_dir(from) {
+        for entry in entries.flatten() {
+            let target = to.join(entry.file_name());
+            if entry.path().is_dir() {
+                move_dir(&entry.path(), &target);
+            } else if !target.exists() {
+                let _ = std::fs::rename(entry.path(), target);
+            }
+        }
+    }
+    let _ = std::fs::remove_dir(from);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: HistoryPolicy = HistoryPolicy {
+        interval_minutes: 10,
+        max_versions: 0,
+        retention_days: 0,
+    };
+
+    /// A scratch notes folder, removed when dropped.
+    struct Vault(PathBuf);
+
+    impl Vault {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "scratch-history-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Vault(root)
+        }
+
+        /// Take an unthrottled snapshot, keeping version IDs (millis) distinct.
+        fn snapshot(&self, id: &str, content: &str, policy: &HistoryPolicy) -> Option<NoteVersion> {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            snapshot(&self.0, id, content, SnapshotSource::Save, policy, false).unwrap()
+        }
+
+        fn contents(&self, id: &str) -> Vec<String> {
+            list_versions(&self.0, id)
+                .iter()
+                .map(|v| read_version(&self.0, id, &v.id).unwrap())
+                .collect()
+        }
+    }
+
+    impl Drop for Vault {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn snapshots_round_trip_through_gzip() {
+        let vault = Vault::new("roundtrip");
+        let content = "# Café\n\n- [ ] 日本語\n".repeat(50);
+        let version = snapshot(
+            &vault.0,
+            "a/b",
+            &content,
+            SnapshotSource::Ai,
+            &POLICY,
+            false,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(version.source, SnapshotSource::Ai);
+        assert!(version.size < content.len() as u64);
+        assert!(vault
+            .0
+            .join(".scratch/history/a/b.md")
+            .join(format!("{}.md.gz", version.id))
+            .is_file());
+        assert_eq!(read_version(&vault.0, "a/b", &version.id).unwrap(), content);
+
+        assert!(read_version(&vault.0, "a/b", "nonsense").is_err());
+        assert!(read_version(&vault.0, "a/b", "1-save").is_err());
+    }
+
+    #[test]
+    fn throttles_and_skips_identical_snapshots() {
+        let vault = Vault::new("throttle");
+        let first = snapshot(&vault.0, "n", "one", SnapshotSource::Save, &POLICY, true).unwrap();
+        assert!(first.is_some());
+        // Within the interval
+        let throttled = snapshot(&vault.0, "n", "two", SnapshotSource::Save, &POLICY, true);
+        assert!(throttled.unwrap().is_none());
+        // Same content as the latest snapshot
+        assert!(vault.snapshot("n", "one", &POLICY).is_none());
+
+        // Unthrottled snapshots (e.g. before a restore) ignore the interval
+        assert!(vault.snapshot("n", "two", &POLICY).is_some());
+        assert_eq!(vault.contents("n"), vec!["two", "one"]);
+    }
+
+    #[test]
+    fn prunes_by_count_and_age() {
+        let vault = Vault::new("prune");
+        let by_count = HistoryPolicy {
+            max_versions: 2,
+            ..POLICY
+        };
+        for content in ["one", "two", "three"] {
+            vault.snapshot("n", content, &by_count);
+        }
+        assert_eq!(vault.contents("n"), vec!["three", "two"]);
+
+        // Backdate a snapshot past the retention period
+        let dir = note_history_dir(&vault.0, "n");
+        let old_millis = now_millis() - 31 * 24 * 60 * 60 * 1000;
+        std::fs::copy(
+            dir.join(format!(
+                "{}{}",
+                list_versions(&vault.0, "n")[1].id,
+                SNAPSHOT_EXT
+            )),
+            dir.join(format!("{}-save{}", old_millis, SNAPSHOT_EXT)),
+        )
+        .unwrap();
+        assert_eq!(list_versions(&vault.0, "n").len(), 3);
+
+        let by_age = HistoryPolicy {
+            retention_days: 30,
+            ..POLICY
+        };
+        vault.snapshot("n", "four", &by_age);
+        assert_eq!(vault.contents("n"), vec!["four", "three", "two"]);
+    }
+
+    #[test]
+    fn moves_history_with_renamed_notes_and_folders() {
+        let vault = Vault::new("rename");
+        vault.snapshot("old", "note", &POLICY);
+        vault.snapshot("dir/a", "a", &POLICY);
+        vault.snapshot("dir/sub/b", "b", &POLICY);
+        // Stale history left behind by a deleted note with the new ID
+        vault.snapshot("new", "earlier", &POLICY);
+
+        rename_note(&vault.0, "old", "new");
+        assert!(list_versions(&vault.0, "old").is_empty());
+        assert_eq!(vault.contents("new"), vec!["earlier", "note"]);
+
+        rename_folder(&vault.0, "dir", "moved/dir");
+        assert!(list_versions(&vault.0, "dir/a").is_empty());
+        assert_eq!(vault.contents("moved/dir/a"), vec!["a"]);
+        assert_eq!(vault.contents("moved/dir/sub/b"), vec!["b"]);
+    }
+}
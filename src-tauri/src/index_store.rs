@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Search indexes live in app data, one directory per notes folder and analyzer:
+/// `search_indexes/<hash of the canonical folder path>-<analyzer>/` holding the
+/// tantivy index (`index/`) and a manifest of what was indexed.
+const INDEXES_DIR: &str = "search_indexes";
+const INDEX_SUBDIR: &str = "index";
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Single shared index used before indexes were kept per notes folder.
+const LEGACY_INDEX_DIR: &str = "search_index";
+
+/// Indexes of notes folders not opened for this many days are deleted.
+pub const RETENTION_DAYS: i64 = 90;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub notes_folder: String,               // Canonical path
+    pub last_opened: i64,                   // Unix seconds
+    pub files: HashMap<String, (u64, u64)>, // Note ID -> file (mtime ns, size) when it was indexed
+}
+
+/// FNV-1a, so directory names stay the same across Rust versions
+/// (`DefaultHasher` output isn't guaranteed to be stable).
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Identifies a notes folder in file names under app data: a hash of its
+/// canonical path.
+pub fn folder_key(notes_folder: &Path) -> String {
+    let canonical =
+        std::fs::canonicalize(notes_folder).unwrap_or_else(|_| notes_folder.to_path_buf());
+    format!(
+        "{:016x}",
+        stable_hash(canonical.to_string_lossy().as_bytes())
+    )
+}
+
+/// Directory holding the index and manifest for a notes folder, analyzed with
+/// the analyzer identified by `analyzer_key`.
+pub fn vault_dir(app_data: &Path, notes_folder: &Path, analyzer_key: &str) -> PathBuf {
+    app_data
+        .join(INDEXES_DIR)
+        .join(format!("{}-{}", folder_key(notes_folder), analyzer_key))
+}
+
+pub fn index_dir(vault_dir: &Path) -> PathBuf {
+    vault_dir.join(INDEX_SUBDIR)
+}
+
+pub fn load_manifest(vault_dir: &Path) -> Manifest {
+    read_manifest(vault_dir).unwrap_or_default()
+}
+
+/// The manifest, or `None` if it's missing or in an older format.
+pub fn read_manifest(vault_dir: &Path) -> Option<Manifest> {
+    std::fs::read_to_string(vault_dir.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+/// The directory isn't created, so an index deleted while still open stays deleted.
+pub fn save_manifest(vault_dir: &Path, manifest: &Manifest) -> Result<(), String> {
+    let content = serde_json::to_string(manifest).map_err(|e| e.to_string())?;
+    crate::fsutil::write_atomic(&vault_dir.join(MANIFEST_FILE), content.as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+pub fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Delete indexes of notes folders that haven't been opened within
+/// `RETENTION_DAYS`, indexes of `keep`'s folder built with other analyzers,
+/// and the legacy shared index. `keep` is never removed.
+/// Returns the number of indexes deleted.
+pub fn collect_garbage(app_data: &Path, keep: &Path) -> usize {
+    let _ = std::fs::remove_dir_all(app_data.join(LEGACY_INDEX_DIR));
+    let current_folder = load_manifest(keep).notes_folder;
+
+    let Ok(entries) = std::fs::read_dir(app_data.join(INDEXES_DIR)) else {
+        return 0;
+    };
+    let cutoff = now_secs() - RETENTION_DAYS * 24 * 60 * 60;
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if dir == keep || !dir.is_dir() {
+            continue;
+        }
+        let manifest = load_manifest(&dir);
+        let superseded = !current_folder.is_empty() && manifest.notes_folder == current_folder;
+
+        // Fall back to the directory mtime for indexes without a readable manifest
+        let last_opened = match manifest.last_opened {
+            0 => entry
+                .metadata()
+                .ok()
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0),
+            secs => secs,
+        };
+        if (superseded || last_opened < cutoff) && std::fs::remove_dir_all(&dir).is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
@@ -0,0 +1,220 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use crate::{AppState, SearchIndex};
+
+/// Send an `index-progress` event at most every this many files.
+const PROGRESS_INTERVAL: usize = 100;
+/// How long note updates from file events are collected before committing.
+const BATCH_WINDOW: Duration = Duration::from_millis(250);
+
+pub enum IndexJob {
+    /// Bring an index up to date with its notes folder. Searches keep using
+    /// the last committed generation of the index while this runs.
+    Sync {
+        index: Arc<SearchIndex>,
+        notes_folder: PathBuf,
+        ignored_dirs: Vec<String>,
+        rebuild: bool, // Reindex every note instead of only changed files
+    },
+    /// Re-read notes from disk, dropping those that are gone (file events).
+    Notes {
+        index: Arc<SearchIndex>,
+        notes_root: PathBuf,
+        ids: Vec<String>,
+    },
+}
+
+#[derive(Clone, Serialize)]
+pub struct IndexProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Queue a job on the indexing worker, starting the worker on first use.
+pub fn queue(app: &AppHandle, state: &AppState, mut job: IndexJob) {
+    let mut sender = state.index_jobs.lock().expect("index jobs mutex");
+    if let Some(tx) = sender.as_ref() {
+        match tx.send(job) {
+            Ok(()) => return,
+            // The worker is gone (it panicked); start a new one
+            Err(mpsc::SendError(returned)) => job = returned,
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let worker_app = app.clone();
+    std::thread::spawn(move || run(worker_app, rx));
+    let _ = tx.send(job);
+    *sender = Some(tx);
+}
+
+fn run(app: AppHandle, jobs: Receiver<IndexJob>) {
+    while let Ok(first) = jobs.recv() {
+        let mut pending = vec![first];
+
+        // Let a burst of file events (git pull, sync clients) arrive so it's
+        // committed once rather than note by note
+        if matches!(pending[0], IndexJob::Notes { .. }) {
+            let deadline = Instant::now() + BATCH_WINDOW;
+            while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                match jobs.recv_timeout(wait) {
+                    Ok(next) => pending.push(next),
+                    Err(_) => break,
+                }
+            }
+        }
+        pending.extend(jobs.try_iter());
+
+        for job in coalesce(pending) {
+            run_job(&app, job);
+        }
+    }
+}
+
+/// Merge neighbouring jobs: note updates for the same index share a commit, and
+/// only the latest sync matters (keeping a requested rebuild of the same index).
+fn coalesce(jobs: Vec<IndexJob>) -> Vec<IndexJob> {
+    let mut merged: Vec<IndexJob> = Vec::new();
+    for job in jobs {
+        match (merged.last_mut(), job) {
+            (
+                Some(IndexJob::Notes { index, ids, .. }),
+                IndexJob::Notes {
+                    index: next_index,
+                    ids: next_ids,
+                    ..
+                },
+            ) if Arc::ptr_eq(index, &next_index) => ids.extend(next_ids),
+            (
+                Some(IndexJob::Sync { index, rebuild, .. }),
+                IndexJob::Sync {
+                    index: next_index,
+                    notes_folder,
+                    ignored_dirs,
+                    rebuild: next_rebuild,
+                },
+            ) => {
+                let rebuild = next_rebuild || (*rebuild && Arc::ptr_eq(index, &next_index));
+                *merged.last_mut().expect("last job") = IndexJob::Sync {
+                    index: next_index,
+                    notes_folder,
+                    ignored_dirs,
+                    rebuild,
+                };
+            }
+            (_, job) => merged.push(job),
+        }
+    }
+    merged
+}
+
+fn run_job(app: &AppHandle, job: IndexJob) {
+    // Replaced while the job was queued; dropping it lets the index close
+    let (IndexJob::Sync { index, .. } | IndexJob::Notes { index, .. }) = &job;
+    if index.is_closed() {
+        return;
+    }
+
+    match job {
+        IndexJob::Sync {
+            index,
+            notes_folder,
+            ignored_dirs,
+            rebuild,
+        } => {
+            let mut last_sent = None;
+            let mut report = |done: usize, total: usize| {
+                let due =
+                    last_sent.is_none_or(|sent| done == total || done - sent >= PROGRESS_INTERVAL);
+                if due {
+                    last_sent = Some(done);
+                    let _ = app.emit("index-progress", IndexProgress { done, total });
+                }
+            };
+
+            let result = if rebuild {
+                index.rebuild_index(&notes_folder, &ignored_dirs, &mut report)
+            } else {
+                index
+                    .sync_index(&notes_folder, &ignored_dirs, &mut report)
+                    .map(|_| ())
+            };
+            if let Err(e) = result {
+                eprintln!("Search indexing failed for {:?}: {}", notes_folder, e);
+            }
+        }
+        IndexJob::Notes {
+            index,
+            notes_root,
+            ids,
+        } => {
+            let ids: BTreeSet<String> = ids.into_iter().collect();
+            let mut batch = index.batch();
+            for id in &ids {
+                if let Err(e) = batch.reindex_from_disk(&notes_root, id) {
+                    eprintln!("Failed to index {}: {}", id, e);
+                }
+            }
+            if let Err(e) = batch.commit() {
+                eprintln!("Search index commit failed: {}", e);
+            }
+            crate::saved_searches::refresh_live(app, &index, &ids);
+        }
+    }
+}
+
+/// Queue re-reading notes changed on disk into the current search index.
+pub fn queue_notes(app: &AppHandle, state: &AppState, notes_root: &Path, ids: Vec<String>) {
+    if let Some(index) = state.current_search_index() {
+        queue(
+            app,
+            state,
+            IndexJob::Notes {
+                index,
+                notes_root: notes_root.to_path_buf(),
+                ids,
+            },
+        );
+    }
+}
+
+/// Convenience for callers holding the app state: index the current notes
+/// folder with the current ignore settings.
+pub fn queue_for_current_folder(
+    app: &AppHandle,
+    state: &AppState,
+    rebuild: bool,
+) -> Result<(), String> {
+    let notes_folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let ignored_dirs = {
+        let settings = state.settings.read().expect("settings read lock");
+        crate::get_effective_ignored_dirs(&settings)
+    };
+    let index = state
+        .current_search_index()
+        .ok_or("Search index not initialized")?;
+
+    queue(
+        app,
+        state,
+        IndexJob::Sync {
+            index,
+            notes_folder: PathBuf::from(notes_folder),
+            ignored_dirs,
+            rebuild,
+        },
+    );
+    Ok(())
+}
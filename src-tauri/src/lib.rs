@@ -4,19 +4,43 @@ use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
-use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
+use tantivy::collector::{DocSetCollector, TopDocs};
+use tantivy::query::{
+    AllQuery, BooleanQuery, EmptyQuery, FuzzyTermQuery, Occur, PhrasePrefixQuery, PhraseQuery,
+    Query, RangeQuery, TermQuery,
+};
 use tantivy::schema::*;
-use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy};
-use tauri::{AppHandle, Emitter, Manager, State, WebviewUrl};
+use tantivy::{
+    doc, DocAddress, Index, IndexReader, IndexWriter, ReloadPolicy, Searcher, SnippetGenerator,
+};
 use tauri::webview::WebviewWindowBuilder;
+use tauri::{AppHandle, Emitter, Manager, State, WebviewUrl};
 use tauri_plugin_clipboard_manager::ClipboardExt;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 
+mod analyzer;
+mod embeddings;
+mod find_replace;
+mod frontmatter;
+mod fsutil;
 mod git;
+mod git_cli;
+mod git_sync;
+mod history;
+mod index_store;
+mod indexer;
+mod links;
+mod metadata_cache;
+mod note_order;
+mod saved_searches;
+mod search_query;
+mod snippets;
+mod tags;
+mod trash;
 
 // Note metadata for list display
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +49,10 @@ pub struct NoteMetadata {
     pub title: String,
     pub preview: String,
     pub modified: i64,
+    #[serde(default)]
+    pub created: i64, // From frontmatter `created`/`date`, else the file's birth time
+    #[serde(default)]
+    pub frontmatter: frontmatter::Frontmatter,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,12 +64,45 @@ pub struct CliStatus {
 
 // Full note content
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Note {
     pub id: String,
     pub title: String,
     pub content: String,
     pub path: String,
     pub modified: i64,
+    pub modified_ns: String, // Exact mtime for save conflict checks; a string since JS numbers can't hold it
+}
+
+// Returned by save commands when the file changed on disk since the editor read it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveConflict {
+    pub kind: &'static str, // Always "conflict"; distinguishes it from plain string errors
+    pub disk_content: String,
+    pub disk_modified: i64,
+    pub disk_modified_ns: String,
+}
+
+/// Error type for save commands. Plain errors serialize as strings (like every
+/// other command), conflicts as a `SaveConflict` object.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum SaveError {
+    Conflict(SaveConflict),
+    Message(String),
+}
+
+impl From<String> for SaveError {
+    fn from(message: String) -> Self {
+        SaveError::Message(message)
+    }
+}
+
+impl From<&str> for SaveError {
+    fn from(message: &str) -> Self {
+        SaveError::Message(message.to_string())
+    }
 }
 
 // Theme color customization
@@ -110,8 +171,15 @@ pub struct Settings {
     pub editor_font: Option<EditorFontSettings>,
     #[serde(rename = "gitEnabled")]
     pub git_enabled: Option<bool>,
+    /// Pinned notes, listed first in this order.
     #[serde(rename = "pinnedNoteIds")]
     pub pinned_note_ids: Option<Vec<String>>,
+    /// Order of the note list; `None` is newest modified first.
+    #[serde(rename = "noteSort")]
+    pub note_sort: Option<note_order::NoteSort>,
+    /// Per-folder note order for the manual sort; kept when notes move.
+    #[serde(rename = "manualNoteOrder")]
+    pub manual_note_order: Option<note_order::ManualOrder>,
     #[serde(rename = "textDirection")]
     pub text_direction: Option<TextDirection>,
     #[serde(rename = "editorWidth")]
@@ -135,16 +203,43 @@ pub struct Settings {
     pub custom_colors_light: Option<std::collections::HashMap<String, String>>,
     #[serde(rename = "customColorsDark")]
     pub custom_colors_dark: Option<std::collections::HashMap<String, String>>,
+    /// Days to keep deleted notes in `.trash/`; `None` uses the default, 0 keeps them forever.
+    #[serde(rename = "trashRetentionDays")]
+    pub trash_retention_days: Option<u32>,
+    /// Minimum minutes between automatic version snapshots of the same note.
+    #[serde(rename = "historyIntervalMinutes")]
+    pub history_interval_minutes: Option<u32>,
+    /// Maximum snapshots kept per note; 0 means unlimited.
+    #[serde(rename = "historyMaxVersions")]
+    pub history_max_versions: Option<u32>,
+    /// Days to keep snapshots; 0 means unlimited.
+    #[serde(rename = "historyRetentionDays")]
+    pub history_retention_days: Option<u32>,
+    /// Stemming, CJK and accent handling for search; `None` uses plain word matching.
+    #[serde(rename = "searchAnalyzer")]
+    pub search_analyzer: Option<analyzer::AnalyzerSettings>,
+    /// Named queries, shown as smart folders.
+    #[serde(rename = "savedSearches")]
+    pub saved_searches: Option<Vec<saved_searches::SavedSearch>>,
+    /// Ollama model used to embed notes for semantic search.
+    #[serde(rename = "embeddingModel")]
+    pub embedding_model: Option<String>,
+    /// Automatic commit, pull and push; only applies while git is enabled.
+    #[serde(rename = "gitAutoSync")]
+    pub git_auto_sync: Option<git_sync::AutoSyncSettings>,
 }
 
 // Search result
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SearchResult {
     pub id: String,
     pub title: String,
     pub preview: String,
     pub modified: i64,
     pub score: f32,
+    pub title_highlights: Vec<snippets::HighlightRange>,
+    pub snippets: Vec<snippets::SearchSnippet>, // Where the query matched the content, in document order
 }
 
 // AI execution result
@@ -173,29 +268,108 @@ pub struct SearchIndex {
     title_field: Field,
     content_field: Field,
     modified_field: Field,
+    folder_field: Field, // Facet of the note's folder path, for folder:
+    tags_field: Field,   // Lowercased tags plus their parent tags, for tag:
+    pinned_field: Field,
+    has_field: Field, // Content features ("task"), for has:
+    // Pinning lives in settings; kept here so documents can carry the flag
+    pinned_ids: RwLock<HashSet<String>>,
+    notes_folder: PathBuf,
+    vault_dir: PathBuf,
+    // Which files are indexed at which mtime and size, so reopening only reindexes changes
+    manifest: Mutex<index_store::Manifest>,
+    // Set once another index replaces this one; indexing work for it stops early
+    closed: AtomicBool,
 }
 
 impl SearchIndex {
-    fn new(index_path: &PathBuf) -> Result<Self> {
+    /// Open (or create) the index for a notes folder in its directory under
+    /// `app_data`. Call `sync_index` afterwards to bring it up to date.
+    fn new(
+        app_data: &Path,
+        notes_folder: &Path,
+        analyzer: &analyzer::AnalyzerSettings,
+    ) -> Result<Self> {
+        let vault_dir = index_store::vault_dir(app_data, notes_folder, &analyzer.key());
+        let index_path = index_store::index_dir(&vault_dir);
+
         // Build schema
         let mut schema_builder = Schema::builder();
         let id_field = schema_builder.add_text_field("id", STRING | STORED);
-        let title_field = schema_builder.add_text_field("title", TEXT | STORED);
-        let content_field = schema_builder.add_text_field("content", TEXT | STORED);
+        let text_options = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(analyzer::TOKENIZER_NAME)
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            )
+            .set_stored();
+        let title_field = schema_builder.add_text_field("title", text_options.clone());
+        let content_field = schema_builder.add_text_field("content", text_options);
         let modified_field = schema_builder.add_i64_field("modified", INDEXED | STORED);
+        let folder_field = schema_builder.add_facet_field("folder", FacetOptions::default());
+        let tags_field = schema_builder.add_text_field("tags", STRING);
+        let pinned_field = schema_builder.add_bool_field("pinned", INDEXED);
+        let has_field = schema_builder.add_text_field("has", STRING);
         let schema = schema_builder.build();
 
-        // Create or open index
-        std::fs::create_dir_all(index_path)?;
-        let index = Index::create_in_dir(index_path, schema.clone())
-            .or_else(|_| Index::open_in_dir(index_path))?;
+        // Open the index, recreating it if it was built with an older schema
+        std::fs::create_dir_all(&index_path)?;
+        let (index, created) = match Index::open_in_dir(&index_path) {
+            Ok(index) if index.schema() == schema => (index, false),
+            Ok(_) => {
+                std::fs::remove_dir_all(&index_path)?;
+                std::fs::create_dir_all(&index_path)?;
+                (Index::create_in_dir(&index_path, schema.clone())?, true)
+            }
+            Err(_) => (Index::create_in_dir(&index_path, schema.clone())?, true),
+        };
+        index
+            .tokenizers()
+            .register(analyzer::TOKENIZER_NAME, analyzer.build());
 
         let reader = index
             .reader_builder()
             .reload_policy(ReloadPolicy::OnCommitWithDelay)
             .try_into()?;
 
-        let writer = index.writer(50_000_000)?; // 50MB buffer
+        let mut writer: IndexWriter = index.writer(50_000_000)?; // 50MB buffer
+
+        // A fresh index has nothing in it, whatever an old manifest says
+        let mut manifest = if created {
+            index_store::Manifest::default()
+        } else if let Some(manifest) = index_store::read_manifest(&vault_dir) {
+            manifest
+        } else {
+            // Without a readable manifest there's no telling which documents are
+            // stale, so start over and let the sync reindex every note
+            writer.delete_all_documents()?;
+            writer.commit()?;
+            index_store::Manifest::default()
+        };
+        manifest.notes_folder = std::fs::canonicalize(notes_folder)
+            .unwrap_or_else(|_| notes_folder.to_path_buf())
+            .to_string_lossy()
+            .into_owned();
+        manifest.last_opened = index_store::now_secs();
+        let _ = index_store::save_manifest(&vault_dir, &manifest);
+
+        // Start from the pinned flags already in the index, so `set_pinned`
+        // also clears notes unpinned since it was last open
+        let pinned_ids = {
+            let searcher = reader.searcher();
+            let query = TermQuery::new(
+                tantivy::Term::from_field_bool(pinned_field, true),
+                IndexRecordOption::Basic,
+            );
+            let mut ids = HashSet::new();
+            for address in searcher.search(&query, &DocSetCollector)? {
+                let stored: TantivyDocument = searcher.doc(address)?;
+                if let Some(id) = stored.get_first(id_field).and_then(|v| v.as_str()) {
+                    ids.insert(id.to_string());
+                }
+            }
+            ids
+        };
 
         Ok(Self {
             index,
@@ -206,48 +380,383 @@ impl SearchIndex {
             title_field,
             content_field,
             modified_field,
+            folder_field,
+            tags_field,
+            pinned_field,
+            has_field,
+            pinned_ids: RwLock::new(pinned_ids),
+            notes_folder: notes_folder.to_path_buf(),
+            vault_dir,
+            manifest: Mutex::new(manifest),
+            closed: AtomicBool::new(false),
         })
     }
 
-    fn index_note(&self, id: &str, title: &str, content: &str, modified: i64) -> Result<()> {
-        let mut writer = self.writer.lock().expect("search writer mutex");
-
-        // Delete existing document with this ID
-        let id_term = tantivy::Term::from_field_text(self.id_field, id);
-        writer.delete_term(id_term);
-
-        // Add new document
-        writer.add_document(doc!(
+    fn save_manifest(&self) -> Result<()> {
+        let manifest = self.manifest.lock().expect("search manifest mutex");
+        index_store::save_manifest(&self.vault_dir, &manifest).map_err(anyhow::Error::msg)
+    }
+
+    fn build_document(
+        &self,
+        id: &str,
+        title: &str,
+        content: &str,
+        modified: i64,
+    ) -> TantivyDocument {
+        let pinned = self
+            .pinned_ids
+            .read()
+            .expect("pinned ids read lock")
+            .contains(id);
+        let mut document = doc!(
             self.id_field => id,
             self.title_field => title,
             self.content_field => content,
             self.modified_field => modified,
-        ))?;
+            self.pinned_field => pinned,
+        );
 
-        writer.commit()?;
-        Ok(())
+        if let Some((folder, _)) = id.rsplit_once('/') {
+            document.add_facet(self.folder_field, Facet::from_path(folder.split('/')));
+        }
+
+        // Index parent tags too, so tag:project matches #project/alpha
+        let mut tag_terms = HashSet::new();
+        for tag in tags::extract_tags(content) {
+            let tag = tag.to_lowercase();
+            for (i, _) in tag.match_indices('/').chain([(tag.len(), "")]) {
+                tag_terms.insert(tag[..i].to_string());
+            }
+        }
+        for tag in tag_terms {
+            document.add_text(self.tags_field, tag);
+        }
+
+        if search_query::has_task(content) {
+            document.add_text(self.has_field, "task");
+        }
+
+        document
+    }
+
+    /// Mark the index as replaced, so queued and running indexing jobs for it
+    /// stop instead of holding on to it (and its writer lock).
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Start a set of changes that are committed together.
+    fn batch(&self) -> IndexBatch<'_> {
+        IndexBatch {
+            index: self,
+            writer: self.writer.lock().expect("search writer mutex"),
+            files: Vec::new(),
+        }
+    }
+
+    fn index_note(&self, id: &str, title: &str, content: &str, modified: i64) -> Result<()> {
+        let mut batch = self.batch();
+        batch.index_note(id, title, content, modified)?;
+        batch.commit()
     }
 
     fn delete_note(&self, id: &str) -> Result<()> {
-        let mut writer = self.writer.lock().expect("search writer mutex");
-        let id_term = tantivy::Term::from_field_text(self.id_field, id);
-        writer.delete_term(id_term);
-        writer.commit()?;
-        Ok(())
+        let mut batch = self.batch();
+        batch.delete_note(id);
+        batch.commit()
+    }
+
+    /// Title, content and modified time of an indexed note, from its stored fields.
+    fn stored_note(
+        &self,
+        searcher: &Searcher,
+        address: DocAddress,
+    ) -> Result<(String, String, String, i64)> {
+        let stored: TantivyDocument = searcher.doc(address)?;
+        let text = |field| {
+            stored
+                .get_first(field)
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string()
+        };
+        let modified = stored
+            .get_first(self.modified_field)
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0);
+        Ok((
+            text(self.id_field),
+            text(self.title_field),
+            text(self.content_field),
+            modified,
+        ))
     }
 
-    fn search(&self, query_str: &str, limit: usize) -> Result<Vec<SearchResult>> {
-        let searcher = self.reader.searcher();
-        let query_parser =
-            QueryParser::for_index(&self.index, vec![self.title_field, self.content_field]);
+    /// Update the set of pinned note IDs and re-index the notes whose pinned
+    /// state changed, from their stored fields.
+    fn set_pinned(&self, ids: HashSet<String>) -> Result<()> {
+        let changed: Vec<String> = {
+            let mut pinned_ids = self.pinned_ids.write().expect("pinned ids write lock");
+            let changed = pinned_ids.symmetric_difference(&ids).cloned().collect();
+            *pinned_ids = ids;
+            changed
+        };
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let mut batch = self.batch();
+        for id in changed {
+            batch.rekey(&id, &id)?;
+        }
+        batch.commit()
+    }
+
+    /// Tokens of `text` as indexed in `field`, using the field's tokenizer.
+    fn field_tokens(&self, field: Field, text: &str) -> Result<Vec<String>> {
+        let mut analyzer = self.index.tokenizer_for_field(field)?;
+        let mut stream = analyzer.token_stream(text);
+        let mut tokens = Vec::new();
+        while stream.advance() {
+            tokens.push(stream.token().text.clone());
+        }
+        Ok(tokens)
+    }
+
+    /// Term or phrase query for `text` in one field, using the field's tokenizer.
+    /// `None` if the text has no searchable tokens (e.g. only punctuation).
+    fn text_query(&self, field: Field, text: &str) -> Result<Option<Box<dyn Query>>> {
+        let mut terms: Vec<tantivy::Term> = self
+            .field_tokens(field, text)?
+            .iter()
+            .map(|token| tantivy::Term::from_field_text(field, token))
+            .collect();
+
+        Ok(match terms.len() {
+            0 => None,
+            1 => Some(Box::new(TermQuery::new(
+                terms.remove(0),
+                IndexRecordOption::WithFreqsAndPositions,
+            ))),
+            _ => Some(Box::new(PhraseQuery::new(terms))),
+        })
+    }
+
+    /// Query for unquoted text in one field. Exact matches score highest; words
+    /// within a typo or two, and with `WordMatch::FuzzyPrefix` words starting
+    /// with the text, match too. Text spanning several tokens is matched as a phrase.
+    fn word_query(
+        &self,
+        field: Field,
+        text: &str,
+        mode: WordMatch,
+    ) -> Result<Option<Box<dyn Query>>> {
+        let tokens = self.field_tokens(field, text)?;
+        let term = |token: &str| tantivy::Term::from_field_text(field, token);
+
+        if tokens.len() > 1 {
+            let terms: Vec<tantivy::Term> = tokens.iter().map(|t| term(t)).collect();
+            return Ok(Some(match mode {
+                WordMatch::FuzzyPrefix => Box::new(PhrasePrefixQuery::new(terms)),
+                _ => Box::new(PhraseQuery::new(terms)),
+            }));
+        }
+        let Some(token) = tokens.first() else {
+            return Ok(None);
+        };
+
+        let exact: Box<dyn Query> = Box::new(TermQuery::new(
+            term(token),
+            IndexRecordOption::WithFreqsAndPositions,
+        ));
+        let distance = search_query::fuzzy_distance(token);
+        let near: Box<dyn Query> = match mode {
+            WordMatch::FuzzyPrefix => {
+                Box::new(FuzzyTermQuery::new_prefix(term(token), distance, true))
+            }
+            WordMatch::Fuzzy if distance > 0 => {
+                Box::new(FuzzyTermQuery::new(term(token), distance, true))
+            }
+            _ => return Ok(Some(exact)),
+        };
+        Ok(Some(Box::new(BooleanQuery::new(vec![
+            (Occur::Should, exact),
+            (Occur::Should, near),
+        ]))))
+    }
+
+    fn clause_query(
+        &self,
+        filter: &search_query::Filter,
+        mode: WordMatch,
+    ) -> Result<Option<Box<dyn Query>>> {
+        use search_query::Filter;
+
+        // Title or content
+        let either_field = |query: &dyn Fn(Field) -> Result<Option<Box<dyn Query>>>| -> Result<Option<Box<dyn Query>>> {
+            let subqueries: Vec<(Occur, Box<dyn Query>)> = [self.title_field, self.content_field]
+                .into_iter()
+                .filter_map(|field| query(field).transpose())
+                .map(|query| query.map(|q| (Occur::Should, q)))
+                .collect::<Result<_>>()?;
+            Ok((!subqueries.is_empty()).then(|| Box::new(BooleanQuery::new(subqueries)) as Box<dyn Query>))
+        };
+
+        let term_query = |term: tantivy::Term| -> Option<Box<dyn Query>> {
+            Some(Box::new(TermQuery::new(term, IndexRecordOption::Basic)))
+        };
+
+        Ok(match filter {
+            Filter::Text(text) => either_field(&|field| self.word_query(field, text, mode))?,
+            Filter::Phrase(text) => either_field(&|field| self.text_query(field, text))?,
+            Filter::Title(text) => self.text_query(self.title_field, text)?,
+            Filter::Tag(tag) => term_query(tantivy::Term::from_field_text(self.tags_field, tag)),
+            Filter::Folder(folder) => term_query(tantivy::Term::from_facet(
+                self.folder_field,
+                &Facet::from_path(folder.split('/')),
+            )),
+            Filter::Modified { start, end } => {
+                let lower = start.map_or(std::ops::Bound::Unbounded, std::ops::Bound::Included);
+                let upper = end.map_or(std::ops::Bound::Unbounded, std::ops::Bound::Excluded);
+                Some(Box::new(RangeQuery::new_i64_bounds(
+                    "modified".to_string(),
+                    lower,
+                    upper,
+                )))
+            }
+            Filter::Pinned => term_query(tantivy::Term::from_field_bool(self.pinned_field, true)),
+            Filter::HasTask => term_query(tantivy::Term::from_field_text(self.has_field, "task")),
+        })
+    }
+
+    /// Combine parsed clauses into one query; every clause must match. With
+    /// `prefix_last`, the last word is matched as a prefix (search as you type).
+    fn build_query(
+        &self,
+        clauses: &[search_query::Clause],
+        prefix_last: bool,
+    ) -> Result<Box<dyn Query>> {
+        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for (i, clause) in clauses.iter().enumerate() {
+            // Exclusions stay exact so a typo allowance doesn't hide unrelated notes
+            let mode = if clause.negated {
+                WordMatch::Exact
+            } else if prefix_last && i + 1 == clauses.len() {
+                WordMatch::FuzzyPrefix
+            } else {
+                WordMatch::Fuzzy
+            };
+            if let Some(query) = self.clause_query(&clause.filter, mode)? {
+                let occur = if clause.negated {
+                    Occur::MustNot
+                } else {
+                    Occur::Must
+                };
+                subqueries.push((occur, query));
+            }
+        }
+
+        if subqueries.is_empty() {
+            return Ok(Box::new(EmptyQuery));
+        }
+        // Exclusions alone match nothing, so start from all notes
+        if subqueries.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+            subqueries.push((Occur::Must, Box::new(AllQuery)));
+        }
+        Ok(Box::new(BooleanQuery::new(subqueries)))
+    }
+
+    /// Words of the query that match approximately: (token, allowed typos, prefix).
+    fn fuzzy_words(
+        &self,
+        clauses: &[search_query::Clause],
+        prefix_last: bool,
+    ) -> Result<Vec<(String, u8, bool)>> {
+        let mut words = Vec::new();
+        for (i, clause) in clauses.iter().enumerate() {
+            let search_query::Filter::Text(text) = &clause.filter else {
+                continue;
+            };
+            let tokens = self.field_tokens(self.content_field, text)?;
+            let prefix = prefix_last && i + 1 == clauses.len();
+            if let [token] = tokens.as_slice() {
+                let distance = search_query::fuzzy_distance(token);
+                if !clause.negated && (prefix || distance > 0) {
+                    words.push((token.clone(), distance, prefix));
+                }
+            }
+        }
+        Ok(words)
+    }
+
+    /// Title and content snippet generators for a query. Fuzzy and prefix
+    /// matches don't expose their terms to tantivy's snippets, so the words in
+    /// `text` they matched are added explicitly. Without fuzzy words the
+    /// generators don't depend on `text` and can be shared by every hit.
+    fn snippet_generators(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        fuzzy_words: &[(String, u8, bool)],
+        text: &str,
+    ) -> Result<(SnippetGenerator, SnippetGenerator)> {
+        let mut matched: HashSet<String> = HashSet::new();
+        if !fuzzy_words.is_empty() {
+            for token in self.field_tokens(self.content_field, text)? {
+                if fuzzy_words.iter().any(|(word, distance, prefix)| {
+                    search_query::fuzzy_matches(&token, word, *distance, *prefix)
+                }) {
+                    matched.insert(token);
+                }
+            }
+        }
 
-        // Parse query, fall back to prefix query if parsing fails
-        let query = query_parser
-            .parse_query(query_str)
-            .or_else(|_| query_parser.parse_query(&format!("{}*", query_str)))?;
+        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Should, query.box_clone())];
+        for token in &matched {
+            for field in [self.title_field, self.content_field] {
+                subqueries.push((
+                    Occur::Should,
+                    Box::new(TermQuery::new(
+                        tantivy::Term::from_field_text(field, token),
+                        IndexRecordOption::Basic,
+                    )),
+                ));
+            }
+        }
+        let query = BooleanQuery::new(subqueries);
+        Ok((
+            SnippetGenerator::create(searcher, &query, self.title_field)?,
+            SnippetGenerator::create(searcher, &query, self.content_field)?,
+        ))
+    }
+
+    fn search(
+        &self,
+        clauses: &[search_query::Clause],
+        prefix_last: bool,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let searcher = self.reader.searcher();
+        let query = self.build_query(clauses, prefix_last)?;
 
         let top_docs = searcher.search(&query, &TopDocs::with_limit(limit))?;
 
+        // Highlight only what the note matched on, not excluded terms
+        let positive: Vec<search_query::Clause> =
+            clauses.iter().filter(|c| !c.negated).cloned().collect();
+        let highlight_query = self.build_query(&positive, prefix_last)?;
+        let fuzzy_words = self.fuzzy_words(clauses, prefix_last)?;
+        let shared_generators = if fuzzy_words.is_empty() && !top_docs.is_empty() {
+            Some(self.snippet_generators(&searcher, &*highlight_query, &[], "")?)
+        } else {
+            None
+        };
+
         let mut results = Vec::with_capacity(top_docs.len());
         for (score, doc_address) in top_docs {
             let doc: TantivyDocument = searcher.doc(doc_address)?;
@@ -275,9 +784,24 @@ impl SearchIndex {
                 .unwrap_or(0);
 
             let preview = generate_preview(content);
+            let per_hit_generators;
+            let (title_snippets, content_snippets) = match &shared_generators {
+                Some((title_snippets, content_snippets)) => (title_snippets, content_snippets),
+                None => {
+                    per_hit_generators = self.snippet_generators(
+                        &searcher,
+                        &*highlight_query,
+                        &fuzzy_words,
+                        &format!("{}\n{}", title, content),
+                    )?;
+                    (&per_hit_generators.0, &per_hit_generators.1)
+                }
+            };
 
             results.push(SearchResult {
                 id,
+                title_highlights: snippets::title_highlights(title_snippets, &title),
+                snippets: snippets::from_generator(content_snippets, content),
                 title,
                 preview,
                 modified,
@@ -288,68 +812,326 @@ impl SearchIndex {
         Ok(results)
     }
 
-    fn rebuild_index(&self, notes_folder: &PathBuf, ignored_dirs: &[String]) -> Result<()> {
+    /// IDs of the notes a query matches, best first, without building snippets.
+    fn matching_ids(&self, clauses: &[search_query::Clause], limit: usize) -> Result<Vec<String>> {
+        let searcher = self.reader.searcher();
+        let query = self.build_query(clauses, false)?;
+
+        let mut ids = Vec::new();
+        for (_, doc_address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            if let Some(id) = doc.get_first(self.id_field).and_then(|v| v.as_str()) {
+                ids.push(id.to_string());
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Note files under the notes folder: (ID, path, (mtime ns, size)).
+    fn note_files(
+        notes_folder: &Path,
+        ignored_dirs: &[String],
+    ) -> Vec<(String, PathBuf, (u64, u64))> {
+        if !notes_folder.exists() {
+            return Vec::new();
+        }
+
+        use walkdir::WalkDir;
+        WalkDir::new(notes_folder)
+            .max_depth(10)
+            .into_iter()
+            .filter_entry(|e| is_visible_notes_entry(e, ignored_dirs))
+            .flatten()
+            .filter(|entry| entry.path().is_file())
+            .filter_map(|entry| {
+                let id = id_from_abs_path(notes_folder, entry.path(), ignored_dirs)?;
+                let stamp = entry
+                    .metadata()
+                    .map(|m| metadata_cache::file_stamp(&m))
+                    .unwrap_or((0, 0));
+                Some((id, entry.into_path(), stamp))
+            })
+            .collect()
+    }
+
+    fn rebuild_index(
+        &self,
+        notes_folder: &Path,
+        ignored_dirs: &[String],
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<()> {
+        let note_files = Self::note_files(notes_folder, ignored_dirs);
+        let total = note_files.len();
+        progress(0, total);
+
+        // Nothing is committed until the end, so searches see the previous
+        // contents until the rebuild is complete
         let mut writer = self.writer.lock().expect("search writer mutex");
         writer.delete_all_documents()?;
 
-        if notes_folder.exists() {
-            use walkdir::WalkDir;
-            for entry in WalkDir::new(notes_folder)
-                .max_depth(10)
-                .into_iter()
-                .filter_entry(|e| is_visible_notes_entry(e, ignored_dirs))
-                .flatten()
-            {
-                let file_path = entry.path();
-                if !file_path.is_file() {
-                    continue;
-                }
-                if let Some(id) = id_from_abs_path(notes_folder, file_path, ignored_dirs) {
-                    if let Ok(content) = std::fs::read_to_string(file_path) {
-                        let modified = entry
-                            .metadata()
-                            .ok()
-                            .and_then(|m| m.modified().ok())
-                            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                            .map(|d| d.as_secs() as i64)
-                            .unwrap_or(0);
-
-                        let title = extract_title(&content);
-
-                        writer.add_document(doc!(
-                            self.id_field => id.as_str(),
-                            self.title_field => title,
-                            self.content_field => content.as_str(),
-                            self.modified_field => modified,
-                        ))?;
-                    }
-                }
+        let mut files = HashMap::new();
+        for (done, (id, file_path, stamp)) in note_files.into_iter().enumerate() {
+            if self.is_closed() {
+                writer.rollback()?;
+                return Ok(());
+            }
+            progress(done + 1, total);
+            if let Ok(content) = std::fs::read_to_string(&file_path) {
+                let title = extract_title(&content);
+                let modified = (stamp.0 / 1_000_000_000) as i64;
+                writer.add_document(self.build_document(&id, &title, &content, modified))?;
+                files.insert(id, stamp);
             }
         }
 
         writer.commit()?;
+        self.manifest.lock().expect("search manifest mutex").files = files;
+        drop(writer);
+        self.save_manifest()
+    }
+
+    /// Bring the index up to date with the notes folder, reindexing only files
+    /// whose mtime or size differs from the manifest and dropping notes that are gone
+    /// (or now ignored). Returns the number of notes added, updated or removed.
+    fn sync_index(
+        &self,
+        notes_folder: &Path,
+        ignored_dirs: &[String],
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<usize> {
+        let note_files = Self::note_files(notes_folder, ignored_dirs);
+        let total = note_files.len();
+        progress(0, total);
+
+        let mut writer = self.writer.lock().expect("search writer mutex");
+        let mut manifest = self.manifest.lock().expect("search manifest mutex");
+
+        let mut seen = HashSet::new();
+        let mut changed = 0;
+        let mut stopped = false;
+        for (done, (id, file_path, stamp)) in note_files.into_iter().enumerate() {
+            // Keep what's indexed so far, but don't take unvisited notes for deleted ones
+            if self.is_closed() {
+                stopped = true;
+                break;
+            }
+            progress(done + 1, total);
+            seen.insert(id.clone());
+            if manifest.files.get(&id) == Some(&stamp) {
+                continue;
+            }
+            let Ok(content) = std::fs::read_to_string(&file_path) else {
+                continue;
+            };
+            let title = extract_title(&content);
+            let modified = (stamp.0 / 1_000_000_000) as i64;
+            writer.delete_term(tantivy::Term::from_field_text(self.id_field, &id));
+            writer.add_document(self.build_document(&id, &title, &content, modified))?;
+            manifest.files.insert(id, stamp);
+            changed += 1;
+        }
+
+        let removed: Vec<String> = manifest
+            .files
+            .keys()
+            .filter(|id| !stopped && !seen.contains(*id))
+            .cloned()
+            .collect();
+        for id in removed {
+            writer.delete_term(tantivy::Term::from_field_text(self.id_field, &id));
+            manifest.files.remove(&id);
+            changed += 1;
+        }
+
+        if changed > 0 {
+            writer.commit()?;
+        }
+        index_store::save_manifest(&self.vault_dir, &manifest).map_err(anyhow::Error::msg)?;
+        Ok(changed)
+    }
+}
+
+/// Index changes applied with a single commit, for bulk operations and bursts of
+/// file events. Holds the index writer until committed, so keep it short-lived.
+struct IndexBatch<'a> {
+    index: &'a SearchIndex,
+    writer: std::sync::MutexGuard<'a, IndexWriter>,
+    files: Vec<(String, Option<(u64, u64)>)>, // Manifest changes, applied on commit
+}
+
+impl IndexBatch<'_> {
+    fn index_note(&mut self, id: &str, title: &str, content: &str, modified: i64) -> Result<()> {
+        self.writer
+            .delete_term(tantivy::Term::from_field_text(self.index.id_field, id));
+        self.writer
+            .add_document(self.index.build_document(id, title, content, modified))?;
+        // Without a stamp the next sync reads the file again, which is only
+        // wasted work if nothing changed
+        let stamp = abs_path_from_id(&self.index.notes_folder, id)
+            .ok()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata_cache::file_stamp(&metadata));
+        self.files.push((id.to_string(), stamp));
+        Ok(())
+    }
+
+    fn delete_note(&mut self, id: &str) {
+        self.writer
+            .delete_term(tantivy::Term::from_field_text(self.index.id_field, id));
+        self.files.push((id.to_string(), None));
+    }
+
+    /// Re-read a note from disk, dropping it from the index if the file is gone.
+    fn reindex_from_disk(&mut self, notes_root: &Path, id: &str) -> Result<()> {
+        let Ok(file_path) = abs_path_from_id(notes_root, id) else {
+            return Ok(());
+        };
+        match std::fs::read_to_string(&file_path) {
+            Ok(content) => {
+                let modified = std::fs::metadata(&file_path)
+                    .ok()
+                    .and_then(|m| m.modified().ok())
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
+                self.index_note(id, &extract_title(&content), &content, modified)
+            }
+            Err(_) if !file_path.exists() => {
+                self.delete_note(id);
+                Ok(())
+            }
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Move a note's document to a new ID, reusing its stored fields instead of
+    /// re-reading the file. Also refreshes derived fields (folder, pinned).
+    /// A note that isn't in the index yet is read from disk instead.
+    fn rekey(&mut self, old_id: &str, new_id: &str) -> Result<()> {
+        // The reader reloads on its own shortly after a commit; the stored
+        // fields have to be the ones just committed
+        self.index.reader.reload()?;
+        let searcher = self.index.reader.searcher();
+        let query = TermQuery::new(
+            tantivy::Term::from_field_text(self.index.id_field, old_id),
+            IndexRecordOption::Basic,
+        );
+        match searcher.search(&query, &TopDocs::with_limit(1))?.pop() {
+            Some((_, address)) => {
+                let (_, title, content, modified) = self.index.stored_note(&searcher, address)?;
+                self.delete_note(old_id);
+                self.index_note(new_id, &title, &content, modified)
+            }
+            None => {
+                let notes_folder = self.index.notes_folder.clone();
+                self.reindex_from_disk(&notes_folder, new_id)
+            }
+        }
+    }
+
+    /// Re-key every note in `old_folder` (and below) to the same place under `new_folder`.
+    fn rekey_folder(&mut self, old_folder: &str, new_folder: &str) -> Result<()> {
+        self.index.reader.reload()?;
+        let searcher = self.index.reader.searcher();
+        let query = TermQuery::new(
+            tantivy::Term::from_facet(
+                self.index.folder_field,
+                &Facet::from_path(old_folder.split('/')),
+            ),
+            IndexRecordOption::Basic,
+        );
+        let old_prefix = format!("{}/", old_folder);
+        for address in searcher.search(&query, &DocSetCollector)? {
+            let (old_id, title, content, modified) = self.index.stored_note(&searcher, address)?;
+            let Some(rest) = old_id.strip_prefix(&old_prefix) else {
+                continue;
+            };
+            self.delete_note(&old_id);
+            self.index_note(
+                &format!("{}/{}", new_folder, rest),
+                &title,
+                &content,
+                modified,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn commit(mut self) -> Result<()> {
+        self.writer.commit()?;
+        let mut manifest = self.index.manifest.lock().expect("search manifest mutex");
+        for (id, stamp) in self.files.drain(..) {
+            match stamp {
+                Some(stamp) => manifest.files.insert(id, stamp),
+                None => manifest.files.remove(&id),
+            };
+        }
         Ok(())
     }
 }
 
+impl Drop for SearchIndex {
+    // Persist mtimes recorded since the last sync, so the next open skips those notes
+    fn drop(&mut self) {
+        let _ = self.save_manifest();
+    }
+}
+
+/// How unquoted words in a search query match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WordMatch {
+    Exact,
+    Fuzzy,       // Allow typos
+    FuzzyPrefix, // Allow typos, and match words starting with the text
+}
+
 // App state with improved structure
 pub struct AppState {
-    pub app_config: RwLock<AppConfig>,  // notes_folder path (stored in app data)
-    pub settings: RwLock<Settings>,      // per-folder settings (stored in .scratch/)
+    pub app_config: RwLock<AppConfig>, // notes_folder path (stored in app data)
+    pub settings: RwLock<Settings>,    // per-folder settings (stored in .scratch/)
     pub notes_cache: RwLock<HashMap<String, NoteMetadata>>,
+    pub tag_index: RwLock<tags::TagIndex>,
     pub file_watcher: Mutex<Option<FileWatcherState>>,
-    pub search_index: Mutex<Option<SearchIndex>>,
+    pub search_index: Mutex<Option<Arc<SearchIndex>>>,
+    pub index_jobs: Mutex<Option<std::sync::mpsc::Sender<indexer::IndexJob>>>, // Background indexing worker
+    pub link_index: RwLock<links::LinkIndex>,
+    pub saved_search_ids: Mutex<HashMap<String, Vec<String>>>, // Saved search ID -> note IDs it last returned
+    pub embeddings: Mutex<Option<embeddings::EmbeddingStore>>, // Loaded on first semantic search
+    pub embedding_jobs: Mutex<Option<std::sync::mpsc::Sender<embeddings::Context>>>, // Background embedding worker
+    pub metadata_cache: Mutex<metadata_cache::MetadataCache>, // Persisted note metadata, tags and links
+    pub git_sync: git_sync::Scheduler,
+    pub git_lock: Arc<Mutex<()>>, // Held by each git operation on the notes folder, manual or automatic
     pub debounce_map: Arc<Mutex<HashMap<PathBuf, Instant>>>,
 }
 
+impl AppState {
+    /// The current search index, without holding the `search_index` lock while
+    /// it's used (so a folder switch or long rebuild doesn't block callers).
+    fn current_search_index(&self) -> Option<Arc<SearchIndex>> {
+        self.search_index
+            .lock()
+            .expect("search index mutex")
+            .clone()
+    }
+}
+
 impl Default for AppState {
     fn default() -> Self {
         Self {
             app_config: RwLock::new(AppConfig::default()),
             settings: RwLock::new(Settings::default()),
             notes_cache: RwLock::new(HashMap::new()),
+            tag_index: RwLock::new(tags::TagIndex::default()),
             file_watcher: Mutex::new(None),
             search_index: Mutex::new(None),
+            index_jobs: Mutex::new(None),
+            link_index: RwLock::new(links::LinkIndex::default()),
+            saved_search_ids: Mutex::new(HashMap::new()),
+            embeddings: Mutex::new(None),
+            embedding_jobs: Mutex::new(None),
+            metadata_cache: Mutex::new(metadata_cache::MetadataCache::default()),
+            git_sync: git_sync::Scheduler::default(),
+            git_lock: Arc::new(Mutex::new(())),
             debounce_map: Arc::new(Mutex::new(HashMap::new())),
         }
     }
@@ -451,25 +1233,14 @@ fn is_effectively_empty(s: &str) -> bool {
 
 /// Strip YAML frontmatter (leading `---` ... `---` block) from content.
 fn strip_frontmatter(content: &str) -> &str {
-    let trimmed = content.trim_start();
-    if trimmed.starts_with("---") {
-        // Find the closing --- (skip the opening line)
-        if let Some(rest) = trimmed.strip_prefix("---") {
-            if let Some(end) = rest.find("\n---") {
-                // Skip past closing --- and the newline after it (handle CRLF)
-                let after_close = &rest[end + 4..];
-                return after_close
-                    .strip_prefix("\r\n")
-                    .or_else(|| after_close.strip_prefix('\n'))
-                    .unwrap_or(after_close);
-            }
-        }
-    }
-    content
+    frontmatter::body(content)
 }
 
-// Utility: Extract title from markdown content
+// Utility: Extract title from markdown content (a frontmatter `title:` wins)
 fn extract_title(content: &str) -> String {
+    if let Some(title) = frontmatter::title(&frontmatter::parse(content)) {
+        return title;
+    }
     let body = strip_frontmatter(content);
     for line in body.lines() {
         let trimmed = line.trim();
@@ -516,7 +1287,12 @@ fn strip_markdown(text: &str) -> String {
     while let Some(start) = result.find("~~") {
         if let Some(end) = result[start + 2..].find("~~") {
             let inner = &result[start + 2..start + 2 + end];
-            result = format!("{}{}{}", &result[..start], inner, &result[start + 4 + end..]);
+            result = format!(
+                "{}{}{}",
+                &result[..start],
+                inner,
+                &result[start + 4 + end..]
+            );
         } else {
             break;
         }
@@ -526,7 +1302,12 @@ fn strip_markdown(text: &str) -> String {
     while let Some(start) = result.find("**") {
         if let Some(end) = result[start + 2..].find("**") {
             let inner = &result[start + 2..start + 2 + end];
-            result = format!("{}{}{}", &result[..start], inner, &result[start + 4 + end..]);
+            result = format!(
+                "{}{}{}",
+                &result[..start],
+                inner,
+                &result[start + 4 + end..]
+            );
         } else {
             break;
         }
@@ -534,7 +1315,12 @@ fn strip_markdown(text: &str) -> String {
     while let Some(start) = result.find("__") {
         if let Some(end) = result[start + 2..].find("__") {
             let inner = &result[start + 2..start + 2 + end];
-            result = format!("{}{}{}", &result[..start], inner, &result[start + 4 + end..]);
+            result = format!(
+                "{}{}{}",
+                &result[..start],
+                inner,
+                &result[start + 4 + end..]
+            );
         } else {
             break;
         }
@@ -544,7 +1330,12 @@ fn strip_markdown(text: &str) -> String {
     while let Some(start) = result.find('`') {
         if let Some(end) = result[start + 1..].find('`') {
             let inner = &result[start + 1..start + 1 + end];
-            result = format!("{}{}{}", &result[..start], inner, &result[start + 2 + end..]);
+            result = format!(
+                "{}{}{}",
+                &result[..start],
+                inner,
+                &result[start + 2 + end..]
+            );
         } else {
             break;
         }
@@ -564,7 +1355,12 @@ fn strip_markdown(text: &str) -> String {
         if let Some(end) = result[start + 1..].find('*') {
             if end > 0 {
                 let inner = &result[start + 1..start + 1 + end];
-                result = format!("{}{}{}", &result[..start], inner, &result[start + 2 + end..]);
+                result = format!(
+                    "{}{}{}",
+                    &result[..start],
+                    inner,
+                    &result[start + 2 + end..]
+                );
             } else {
                 break;
             }
@@ -577,7 +1373,12 @@ fn strip_markdown(text: &str) -> String {
         if let Some(end) = result[start + 1..].find('_') {
             if end > 0 {
                 let inner = &result[start + 1..start + 1 + end];
-                result = format!("{}{}{}", &result[..start], inner, &result[start + 2 + end..]);
+                result = format!(
+                    "{}{}{}",
+                    &result[..start],
+                    inner,
+                    &result[start + 2 + end..]
+                );
             } else {
                 break;
             }
@@ -626,9 +1427,42 @@ const DEFAULT_IGNORED_DIRS: &[&str] = &[
 
 /// Get the effective ignored directories from settings (or defaults if not customized).
 fn get_effective_ignored_dirs(settings: &Settings) -> Vec<String> {
-    settings.ignored_patterns.clone().unwrap_or_else(|| {
-        DEFAULT_IGNORED_DIRS.iter().map(|s| s.to_string()).collect()
-    })
+    settings
+        .ignored_patterns
+        .clone()
+        .unwrap_or_else(|| DEFAULT_IGNORED_DIRS.iter().map(|s| s.to_string()).collect())
+}
+
+fn get_pinned_note_ids(settings: &Settings) -> HashSet<String> {
+    settings
+        .pinned_note_ids
+        .as_ref()
+        .map(|ids| ids.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Apply a change to the manual note order, saving settings if there is one.
+fn update_manual_order(
+    state: &AppState,
+    notes_folder: &str,
+    change: impl FnOnce(&mut note_order::ManualOrder),
+) {
+    let mut settings = state.settings.write().expect("settings write lock");
+    if let Some(ref mut order) = settings.manual_note_order {
+        change(order);
+        let _ = save_settings(notes_folder, &settings);
+    }
+}
+
+/// Push the pinned note IDs from settings to the search index (for is:pinned).
+fn sync_pinned_to_search_index(state: &AppState) {
+    let pinned_ids = {
+        let settings = state.settings.read().expect("settings read lock");
+        get_pinned_note_ids(&settings)
+    };
+    if let Some(search_index) = state.current_search_index() {
+        let _ = search_index.set_pinned(pinned_ids);
+    }
 }
 
 /// Filter for WalkDir: skips excluded and user-ignored directories.
@@ -642,7 +1476,11 @@ fn is_visible_notes_entry(entry: &walkdir::DirEntry, ignored_dirs: &[String]) ->
 
 /// Convert an absolute file path to a note ID (relative path from notes root, no .md extension, POSIX separators).
 /// Returns None if the path is outside the root, not a .md file, or in an excluded/ignored directory.
-fn id_from_abs_path(notes_root: &Path, file_path: &Path, ignored_dirs: &[String]) -> Option<String> {
+fn id_from_abs_path(
+    notes_root: &Path,
+    file_path: &Path,
+    ignored_dirs: &[String],
+) -> Option<String> {
     let rel = file_path.strip_prefix(notes_root).ok()?;
 
     // Skip files inside excluded or ignored directories.
@@ -665,7 +1503,9 @@ fn id_from_abs_path(notes_root: &Path, file_path: &Path, ignored_dirs: &[String]
     // Strip .md by converting to string and trimming (avoids with_extension
     // which breaks on stems containing dots like "meeting.2024-01-15.md").
     let rel_str = rel.to_str()?;
-    let id = rel_str.strip_suffix(".md")?.replace(std::path::MAIN_SEPARATOR, "/");
+    let id = rel_str
+        .strip_suffix(".md")?
+        .replace(std::path::MAIN_SEPARATOR, "/");
 
     if id.is_empty() {
         None
@@ -725,11 +1565,95 @@ fn get_settings_path(notes_folder: &str) -> PathBuf {
     scratch_dir.join("settings.json")
 }
 
-// Get search index path
-fn get_search_index_path(app: &AppHandle) -> Result<PathBuf> {
+// Open the search index for a notes folder and its analyzer settings, deleting
+// indexes that are no longer used. Queue an indexing job to bring it up to date.
+fn open_search_index(
+    app: &AppHandle,
+    notes_folder: &Path,
+    settings: &Settings,
+) -> Result<Arc<SearchIndex>> {
     let app_data = app.path().app_data_dir()?;
     std::fs::create_dir_all(&app_data)?;
-    Ok(app_data.join("search_index"))
+
+    let search_index = SearchIndex::new(&app_data, notes_folder, &get_search_analyzer(settings))?;
+    index_store::collect_garbage(&app_data, &search_index.vault_dir);
+
+    search_index.set_pinned(get_pinned_note_ids(settings))?;
+    Ok(Arc::new(search_index))
+}
+
+// Close a search index that's been taken out of the app state, waiting for the
+// indexing worker and running commands to let go of it. Dropping the last
+// reference releases its writer lock and saves its manifest.
+fn release_search_index(mut index: Arc<SearchIndex>) -> Result<(), String> {
+    const RELEASE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    index.close();
+    let deadline = Instant::now() + RELEASE_TIMEOUT;
+    loop {
+        match Arc::try_unwrap(index) {
+            Ok(index) => {
+                drop(index);
+                return Ok(());
+            }
+            Err(shared) if Instant::now() < deadline => {
+                index = shared;
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(_) => return Err("The previous search index is still in use".to_string()),
+        }
+    }
+}
+
+// Switch to the search index for the current folder and settings, and index
+// whatever it's missing in the background
+fn reopen_search_index(
+    app: &AppHandle,
+    state: &AppState,
+    notes_folder: &Path,
+) -> Result<(), String> {
+    state
+        .saved_search_ids
+        .lock()
+        .expect("saved search ids mutex")
+        .clear();
+    let app_data = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let settings = state.settings.read().expect("settings read lock").clone();
+    let vault_dir = index_store::vault_dir(
+        &app_data,
+        notes_folder,
+        &get_search_analyzer(&settings).key(),
+    );
+
+    // The indexing worker and its queued jobs may still hold the open index, and
+    // with it the writer lock, so an index for the same folder and analyzer is kept
+    let search_index = match state
+        .current_search_index()
+        .filter(|index| index.vault_dir == vault_dir)
+    {
+        Some(search_index) => {
+            search_index
+                .set_pinned(get_pinned_note_ids(&settings))
+                .map_err(|e| e.to_string())?;
+            search_index
+        }
+        None => {
+            let previous = state
+                .search_index
+                .lock()
+                .expect("search index mutex")
+                .take();
+            // Opening the same index again, or collecting a superseded one, has
+            // to wait until the previous index is closed for good
+            if let Some(previous) = previous {
+                release_search_index(previous)?;
+            }
+            open_search_index(app, notes_folder, &settings)
+                .map_err(|e| format!("Failed to open the search index: {}", e))?
+        }
+    };
+    *state.search_index.lock().expect("search index mutex") = Some(search_index);
+    indexer::queue_for_current_folder(app, state, false)
 }
 
 // Load app config from disk (notes folder path)
@@ -753,32 +1677,111 @@ fn load_app_config(app: &AppHandle) -> AppConfig {
 fn save_app_config(app: &AppHandle, config: &AppConfig) -> Result<()> {
     let path = get_app_config_path(app)?;
     let content = serde_json::to_string_pretty(config)?;
-    std::fs::write(path, content)?;
+    fsutil::write_atomic(&path, content.as_bytes())?;
     Ok(())
 }
 
-// Load per-folder settings from disk
+// Last known-good copy of settings.json, kept next to it
+fn get_settings_backup_path(settings_path: &Path) -> PathBuf {
+    settings_path.with_file_name("settings.json.bak")
+}
+
+fn read_settings_file(path: &Path) -> Option<Settings> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+// Load per-folder settings from disk, restoring from the backup if the file is corrupt
 fn load_settings(notes_folder: &str) -> Settings {
     let path = get_settings_path(notes_folder);
 
-    if path.exists() {
-        std::fs::read_to_string(&path)
-            .ok()
-            .and_then(|content| serde_json::from_str(&content).ok())
-            .unwrap_or_default()
-    } else {
-        Settings::default()
+    if !path.exists() {
+        return Settings::default();
+    }
+    if let Some(settings) = read_settings_file(&path) {
+        return settings;
+    }
+
+    let backup_path = get_settings_backup_path(&path);
+    match std::fs::read(&backup_path) {
+        Ok(backup) if serde_json::from_slice::<Settings>(&backup).is_ok() => {
+            eprintln!("settings.json is unreadable, restoring from backup");
+            let _ = fsutil::write_atomic(&path, &backup);
+            serde_json::from_slice(&backup).unwrap_or_default()
+        }
+        _ => Settings::default(),
     }
 }
 
-// Save per-folder settings to disk
+// Save per-folder settings to disk, backing up the previous version first
 fn save_settings(notes_folder: &str, settings: &Settings) -> Result<()> {
     let path = get_settings_path(notes_folder);
     let content = serde_json::to_string_pretty(settings)?;
-    std::fs::write(path, content)?;
+
+    // Only back up a file that parses, so a corrupt file never replaces a good backup
+    if let Ok(previous) = std::fs::read(&path) {
+        if serde_json::from_slice::<Settings>(&previous).is_ok() {
+            let _ = fsutil::write_atomic(&get_settings_backup_path(&path), &previous);
+        }
+    }
+
+    fsutil::write_atomic(&path, content.as_bytes())?;
     Ok(())
 }
 
+/// A file's modification time in unix seconds, and in nanoseconds as a string
+/// for `Note::modified_ns`. Zero if the file can't be read.
+async fn modified_times(file_path: &Path) -> (i64, String) {
+    let modified_ns = fs::metadata(file_path)
+        .await
+        .map(|metadata| metadata_cache::file_stamp(&metadata).0)
+        .unwrap_or(0);
+    (
+        (modified_ns / 1_000_000_000) as i64,
+        modified_ns.to_string(),
+    )
+}
+
+/// Optimistic concurrency check for saves: fails with a conflict if the file's
+/// modification time (to the nanosecond) differs from the one the editor last
+/// saw, unless the disk already holds exactly the content being saved.
+async fn check_save_conflict(
+    file_path: &Path,
+    expected_modified_ns: Option<&str>,
+    new_content: &str,
+) -> Result<(), SaveError> {
+    let Some(expected) = expected_modified_ns else {
+        return Ok(());
+    };
+    let expected: u64 = expected
+        .parse()
+        .map_err(|_| "Invalid modification time".to_string())?;
+    let Ok(metadata) = fs::metadata(file_path).await else {
+        // File is gone; writing recreates it
+        return Ok(());
+    };
+
+    let (disk_modified_ns, _) = metadata_cache::file_stamp(&metadata);
+    if disk_modified_ns == expected {
+        return Ok(());
+    }
+
+    let disk_content = fs::read_to_string(file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    if disk_content == new_content {
+        return Ok(());
+    }
+
+    Err(SaveError::Conflict(SaveConflict {
+        kind: "conflict",
+        disk_content,
+        disk_modified: (disk_modified_ns / 1_000_000_000) as i64,
+        disk_modified_ns: disk_modified_ns.to_string(),
+    }))
+}
+
 // Clean up old entries from debounce map (entries older than 5 seconds)
 fn cleanup_debounce_map(map: &Mutex<HashMap<PathBuf, Instant>>) {
     let mut map = map.lock().expect("debounce map mutex");
@@ -804,10 +1807,93 @@ fn normalize_notes_folder_path(path: &str) -> Result<PathBuf, String> {
     Ok(PathBuf::from(trimmed))
 }
 
+/// Switch to the metadata cache of a notes folder and bring it up to date
+/// (reading only notes changed since it was saved), then rebuild the notes
+/// cache and the link and tag indexes from it.
+fn reload_note_metadata(
+    app: &AppHandle,
+    state: &AppState,
+    notes_folder: &Path,
+    ignored_dirs: &[String],
+) {
+    let app_data = app
+        .path()
+        .app_data_dir()
+        .unwrap_or_else(|_| notes_folder.join(".scratch"));
+
+    let mut metadata_cache = state.metadata_cache.lock().expect("metadata cache mutex");
+    let _ = metadata_cache.save(true);
+    *metadata_cache = metadata_cache::MetadataCache::open(&app_data, notes_folder);
+    metadata_cache.refresh(ignored_dirs);
+    let _ = metadata_cache.save(true);
+
+    let (link_index, tag_index) = metadata_cache.build_indexes();
+    *state.link_index.write().expect("link index write lock") = link_index;
+    *state.tag_index.write().expect("tag index write lock") = tag_index;
+    *state.notes_cache.write().expect("cache write lock") = metadata_cache
+        .notes()
+        .map(|note| (note.meta.id.clone(), note.meta.clone()))
+        .collect();
+}
+
+/// `reload_note_metadata` on a background thread, since reading every changed
+/// note can take a while. Emits `notes-metadata-loaded` with the folder when
+/// done. A `list_notes` that gets there first loads the cache itself.
+fn spawn_note_metadata_reload(app: &AppHandle, notes_folder: PathBuf, ignored_dirs: Vec<String>) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let state = app.state::<AppState>();
+        let folder = notes_folder.to_string_lossy().into_owned();
+        // Another folder was opened before this thread got going
+        let opened = state
+            .app_config
+            .read()
+            .expect("app_config read lock")
+            .notes_folder
+            .as_deref()
+            == Some(folder.as_str());
+        let loaded = state
+            .metadata_cache
+            .lock()
+            .expect("metadata cache mutex")
+            .notes_folder()
+            == folder;
+        if !opened || loaded {
+            return;
+        }
+        reload_note_metadata(&app, &state, &notes_folder, &ignored_dirs);
+        let _ = app.emit("notes-metadata-loaded", folder);
+    });
+}
+
+/// Copy refreshed metadata cache entries into the notes cache and the link and
+/// tag indexes; IDs no longer in the metadata cache are removed.
+fn apply_note_metadata<'a>(
+    state: &AppState,
+    metadata_cache: &metadata_cache::MetadataCache,
+    ids: impl IntoIterator<Item = &'a String>,
+) {
+    let mut link_index = state.link_index.write().expect("link index write lock");
+    let mut tag_index = state.tag_index.write().expect("tag index write lock");
+    let mut cache = state.notes_cache.write().expect("cache write lock");
+    for id in ids {
+        let note = metadata_cache.get(id);
+        metadata_cache::apply_note(&mut link_index, &mut tag_index, id, note);
+        match note {
+            Some(note) => cache.insert(id.clone(), note.meta.clone()),
+            None => cache.remove(id),
+        };
+    }
+}
+
 /// Shared initialization logic for setting a notes folder.
 /// Creates required directories, verifies write access, updates config/settings,
-/// adds asset protocol scope, and rebuilds the search index.
-fn initialize_notes_folder(app: &AppHandle, path_buf: &PathBuf, state: &AppState) -> Result<String, String> {
+/// adds asset protocol scope, and queues search indexing in the background.
+fn initialize_notes_folder(
+    app: &AppHandle,
+    path_buf: &PathBuf,
+    state: &AppState,
+) -> Result<String, String> {
     let normalized_path = path_buf.to_string_lossy().into_owned();
 
     // Verify it's a valid directory
@@ -853,19 +1939,26 @@ fn initialize_notes_folder(app: &AppHandle, path_buf: &PathBuf, state: &AppState
     // Add notes folder to asset protocol scope so images can be served
     let _ = app.asset_protocol_scope().allow_directory(path_buf, true);
 
-    // Initialize search index
-    if let Ok(index_path) = get_search_index_path(app) {
-        if let Ok(search_index) = SearchIndex::new(&index_path) {
-            let ignored_dirs = {
-                let settings = state.settings.read().expect("settings read lock");
-                get_effective_ignored_dirs(&settings)
-            };
-            let _ = search_index.rebuild_index(path_buf, &ignored_dirs);
-            let mut index = state.search_index.lock().expect("search index mutex");
-            *index = Some(search_index);
-        }
+    let (ignored_dirs, retention_days) = {
+        let settings = state.settings.read().expect("settings read lock");
+        (
+            get_effective_ignored_dirs(&settings),
+            get_trash_retention_days(&settings),
+        )
+    };
+
+    // Purge trashed items past their retention period
+    trash::purge_expired(path_buf, retention_days);
+
+    // Initialize search index; the worker reindexes what changed since it was last open.
+    // Notes still work without search, so a failure doesn't stop the folder opening
+    if let Err(e) = reopen_search_index(app, state, path_buf) {
+        eprintln!("{}", e);
     }
 
+    // Load note metadata, and the link and tag indexes, from the cache (reading changed notes)
+    spawn_note_metadata_reload(app, path_buf.clone(), ignored_dirs);
+
     Ok(normalized_path)
 }
 
@@ -889,7 +1982,10 @@ fn set_notes_folder(app: AppHandle, path: String, state: State<AppState>) -> Res
 }
 
 #[tauri::command]
-async fn list_notes(state: State<'_, AppState>) -> Result<Vec<NoteMetadata>, String> {
+async fn list_notes(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<NoteMetadata>, String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
         app_config
@@ -908,82 +2004,108 @@ async fn list_notes(state: State<'_, AppState>) -> Result<Vec<NoteMetadata>, Str
         get_effective_ignored_dirs(&settings)
     };
 
-    let path_clone = path.clone();
-    let discovered = tokio::task::spawn_blocking(move || {
-        use walkdir::WalkDir;
-        let mut results: Vec<(String, String, String, i64)> = Vec::new();
-        for entry in WalkDir::new(&path_clone)
-            .max_depth(10)
-            .into_iter()
-            .filter_entry(|e| is_visible_notes_entry(e, &ignored_dirs))
-            .flatten()
-        {
-            let file_path = entry.path();
-            if !file_path.is_file() {
-                continue;
-            }
-            if let Some(id) = id_from_abs_path(&path_clone, file_path, &ignored_dirs) {
-                if let Ok(content) = std::fs::read_to_string(file_path) {
-                    let modified = entry
-                        .metadata()
-                        .ok()
-                        .and_then(|m| m.modified().ok())
-                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                        .map(|d| d.as_secs() as i64)
-                        .unwrap_or(0);
-                    let title = extract_title(&content);
-                    let preview = generate_preview(&content);
-                    results.push((id, title, preview, modified));
-                }
-            }
+    // Only notes changed since the last listing are read from disk
+    let mut notes = tokio::task::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let current = state
+            .metadata_cache
+            .lock()
+            .expect("metadata cache mutex")
+            .notes_folder()
+            == folder;
+        if !current {
+            reload_note_metadata(&app, &state, &path, &ignored_dirs);
+        }
+
+        let mut metadata_cache = state.metadata_cache.lock().expect("metadata cache mutex");
+        let changes = metadata_cache.refresh(&ignored_dirs);
+        if !changes.is_empty() {
+            apply_note_metadata(
+                &state,
+                &metadata_cache,
+                changes.updated.iter().chain(&changes.removed),
+            );
+            let _ = metadata_cache.save(false);
         }
-        results
+        metadata_cache
+            .notes()
+            .map(|note| note.meta.clone())
+            .collect::<Vec<NoteMetadata>>()
     })
     .await
     .map_err(|e| e.to_string())?;
 
-    let mut notes: Vec<NoteMetadata> = discovered
-        .into_iter()
-        .map(|(id, title, preview, modified)| NoteMetadata {
-            id,
-            title,
-            preview,
-            modified,
-        })
-        .collect();
-
-    // Load pinned note IDs from settings
-    let pinned_ids: HashSet<String> = {
+    // Sort: pinned notes first (in pinned order), then the rest by the chosen order
+    {
         let settings = state.settings.read().expect("settings read lock");
-        settings
-            .pinned_note_ids
-            .as_ref()
-            .map(|ids| ids.iter().cloned().collect())
-            .unwrap_or_default()
+        note_order::sort(
+            &mut notes,
+            settings.note_sort.unwrap_or_default(),
+            settings.pinned_note_ids.as_deref().unwrap_or_default(),
+            settings
+                .manual_note_order
+                .as_ref()
+                .unwrap_or(&HashMap::new()),
+        );
+    }
+
+    Ok(notes)
+}
+
+/// Set the manual order of the notes in a folder ("" for the root), used by the
+/// manual sort. Notes left out go after these.
+#[tauri::command]
+fn reorder_notes(folder: String, ids: Vec<String>, state: State<AppState>) -> Result<(), String> {
+    let notes_folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
     };
+    let folder = folder.trim_matches('/').to_string();
+    if !folder.is_empty() {
+        validate_folder_path(&folder)?;
+    }
+    let prefix = format!("{}/", folder);
+    if let Some(id) = ids.iter().find(|id| {
+        let rest = if folder.is_empty() {
+            Some(id.as_str())
+        } else {
+            id.strip_prefix(&prefix)
+        };
+        rest.is_none_or(|name| name.contains('/'))
+    }) {
+        return Err(format!("Note \"{}\" is not in this folder", id));
+    }
 
-    // Sort: pinned notes first (by date), then unpinned notes (by date)
-    notes.sort_by(|a, b| {
-        let a_pinned = pinned_ids.contains(&a.id);
-        let b_pinned = pinned_ids.contains(&b.id);
+    let mut settings = state.settings.write().expect("settings write lock");
+    settings
+        .manual_note_order
+        .get_or_insert_with(HashMap::new)
+        .insert(folder, ids);
+    save_settings(&notes_folder, &settings).map_err(|e| e.to_string())
+}
 
-        match (a_pinned, b_pinned) {
-            (true, false) => std::cmp::Ordering::Less,    // a pinned, b not -> a first
-            (false, true) => std::cmp::Ordering::Greater, // b pinned, a not -> b first
-            _ => b.modified.cmp(&a.modified),             // both same status -> sort by date (newest first)
-        }
-    });
+/// Rearrange pinned notes. `ids` must be the pinned notes, in their new order.
+#[tauri::command]
+fn reorder_pinned_notes(ids: Vec<String>, state: State<AppState>) -> Result<(), String> {
+    let notes_folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
 
-    // Update cache efficiently
+    let mut settings = state.settings.write().expect("settings write lock");
+    let reordered: HashSet<&String> = ids.iter().collect();
+    if reordered.len() != ids.len() || reordered != get_pinned_note_ids(&settings).iter().collect()
     {
-        let mut cache = state.notes_cache.write().expect("cache write lock");
-        cache.clear();
-        for note in &notes {
-            cache.insert(note.id.clone(), note.clone());
-        }
+        return Err("Pinned notes changed; reload and try again".to_string());
     }
-
-    Ok(notes)
+    settings.pinned_note_ids = Some(ids);
+    save_settings(&notes_folder, &settings).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -1005,32 +2127,107 @@ async fn read_note(id: String, state: State<'_, AppState>) -> Result<Note, Strin
     let content = fs::read_to_string(&file_path)
         .await
         .map_err(|e| e.to_string())?;
-    let metadata = fs::metadata(&file_path)
+    let (modified, modified_ns) = modified_times(&file_path).await;
+
+    Ok(Note {
+        id,
+        title: extract_title(&content),
+        content,
+        path: file_path.to_string_lossy().into_owned(),
+        modified,
+        modified_ns,
+    })
+}
+
+/// Set or remove individual frontmatter fields of a note (`null` removes a
+/// field). The rest of the note, including other fields, is left untouched.
+/// Changing `title:` renames the note, so the returned ID may differ.
+#[tauri::command]
+async fn update_note_frontmatter(
+    app: AppHandle,
+    id: String,
+    patch: frontmatter::Frontmatter,
+    state: State<'_, AppState>,
+) -> Result<Note, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+
+    let folder_path = PathBuf::from(&folder);
+    let file_path = abs_path_from_id(&folder_path, &id)?;
+    if !file_path.exists() {
+        return Err("Note not found".to_string());
+    }
+
+    let current = fs::read_to_string(&file_path)
         .await
         .map_err(|e| e.to_string())?;
+    let content = frontmatter::apply_patch(&current, &patch)?;
 
-    let modified = metadata
-        .modified()
-        .ok()
-        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
+    // A new title renames the file, rewrites links to it and keeps history,
+    // exactly like retitling the note in the editor
+    if extract_title(&content) != extract_title(&current) {
+        return save_note(app, Some(id), content, None, state)
+            .await
+            .map_err(|e| match e {
+                SaveError::Message(message) => message,
+                SaveError::Conflict(_) => "The note changed on disk".to_string(),
+            });
+    }
+
+    if content != current {
+        fsutil::write_atomic_async(&file_path, content.as_str())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let (modified, modified_ns) = modified_times(&file_path).await;
+    let title = extract_title(&content);
+
+    {
+        if let Some(search_index) = state.current_search_index() {
+            let _ = search_index.index_note(&id, &title, &content, modified);
+        }
+    }
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        link_index.update_note(&id, &content);
+    }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.update_note(&id, &content);
+    }
+    {
+        let mut cache = state.notes_cache.write().expect("cache write lock");
+        if let Some(meta) = cache.get_mut(&id) {
+            meta.title = title.clone();
+            meta.modified = modified;
+            meta.frontmatter = frontmatter::parse(&content);
+        }
+    }
 
     Ok(Note {
         id,
-        title: extract_title(&content),
+        title,
         content,
         path: file_path.to_string_lossy().into_owned(),
         modified,
+        modified_ns,
     })
 }
 
 #[tauri::command]
 async fn save_note(
+    app: AppHandle,
     id: Option<String>,
     content: String,
+    expected_modified_ns: Option<String>,
     state: State<'_, AppState>,
-) -> Result<Note, String> {
+) -> Result<Note, SaveError> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
         app_config
@@ -1042,13 +2239,17 @@ async fn save_note(
 
     let title = extract_title(&content);
     let sanitized_leaf = sanitize_filename(&title);
+    let previous_id = id.clone();
 
     // Determine the file ID and path, handling renames
     let (final_id, file_path, old_id) = if let Some(existing_id) = id {
         // Preserve directory prefix for notes in subfolders
         let (dir_prefix, desired_id) = if let Some(pos) = existing_id.rfind('/') {
             let prefix = &existing_id[..pos];
-            (Some(prefix.to_string()), format!("{}/{}", prefix, sanitized_leaf))
+            (
+                Some(prefix.to_string()),
+                format!("{}/{}", prefix, sanitized_leaf),
+            )
         } else {
             (None, sanitized_leaf.clone())
         };
@@ -1094,32 +2295,54 @@ async fn save_note(
         (new_id, new_file_path, None)
     };
 
+    // Refuse to overwrite changes made on disk since the editor read the note
+    if let Some(ref previous_id) = previous_id {
+        let previous_path = abs_path_from_id(&folder_path, previous_id)?;
+        check_save_conflict(&previous_path, expected_modified_ns.as_deref(), &content).await?;
+    }
+
+    // Snapshot the version being replaced (throttled per note)
+    if let Some(ref previous_id) = previous_id {
+        let previous_path = abs_path_from_id(&folder_path, previous_id)?;
+        if let Ok(previous_content) = fs::read_to_string(&previous_path).await {
+            if previous_content != content {
+                let policy = {
+                    let settings = state.settings.read().expect("settings read lock");
+                    get_history_policy(&settings)
+                };
+                let _ = history::snapshot(
+                    &folder_path,
+                    previous_id,
+                    &previous_content,
+                    history::SnapshotSource::Save,
+                    &policy,
+                    true,
+                );
+            }
+        }
+    }
+
     // Write the file to the new path
-    fs::write(&file_path, &content)
+    fsutil::write_atomic_async(&file_path, content.as_str())
         .await
         .map_err(|e| e.to_string())?;
 
     // Delete old file AFTER successful write (to prevent data loss)
-    if let Some((_, ref old_file_path)) = old_id {
+    if let Some((ref old_id_str, ref old_file_path)) = old_id {
         if old_file_path.exists() && *old_file_path != file_path {
             let _ = fs::remove_file(old_file_path).await;
         }
+        history::rename_note(&folder_path, old_id_str, &final_id);
+        update_manual_order(&state, &folder, |order| {
+            note_order::rename_note(order, old_id_str, &final_id)
+        });
     }
 
-    let metadata = fs::metadata(&file_path)
-        .await
-        .map_err(|e| e.to_string())?;
-    let modified = metadata
-        .modified()
-        .ok()
-        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
+    let (modified, modified_ns) = modified_times(&file_path).await;
 
     // Update search index (delete old entry if renamed, then add new)
     {
-        let index = state.search_index.lock().expect("search index mutex");
-        if let Some(ref search_index) = *index {
+        if let Some(search_index) = state.current_search_index() {
             if let Some((ref old_id_str, _)) = old_id {
                 let _ = search_index.delete_note(old_id_str);
             }
@@ -1127,6 +2350,41 @@ async fn save_note(
         }
     }
 
+    // Rewrite links in other notes when the note was renamed or retitled
+    let mut renames = Vec::new();
+    if let Some(previous_id) = previous_id {
+        let old_title = {
+            let link_index = state.link_index.read().expect("link index read lock");
+            link_index.title(&previous_id).map(str::to_string)
+        };
+        let title_changed = old_title.is_some_and(|old| old != title);
+        if previous_id != final_id || title_changed {
+            renames.push(links::NoteRename {
+                old_id: previous_id,
+                new_id: final_id.clone(),
+                new_title: title_changed.then(|| title.clone()),
+            });
+        }
+    }
+    let relinked_ids = rewrite_links_for_renames(&state, &folder_path, &renames, Some(&final_id));
+
+    // Update link index
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        if let Some((ref old_id_str, _)) = old_id {
+            link_index.remove_note(old_id_str);
+        }
+        link_index.update_note(&final_id, &content);
+    }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        if let Some((ref old_id_str, _)) = old_id {
+            tag_index.remove_note(old_id_str);
+        }
+        tag_index.update_note(&final_id, &content);
+    }
+    emit_links_updated(&app, relinked_ids);
+
     // Update cache (remove old entry if renamed)
     if let Some((ref old_id_str, _)) = old_id {
         let mut cache = state.notes_cache.write().expect("cache write lock");
@@ -1139,6 +2397,7 @@ async fn save_note(
         content,
         path: file_path.to_string_lossy().into_owned(),
         modified,
+        modified_ns,
     })
 }
 
@@ -1155,19 +2414,27 @@ async fn delete_note(id: String, state: State<'_, AppState>) -> Result<(), Strin
     let folder_path = PathBuf::from(&folder);
     let file_path = abs_path_from_id(&folder_path, &id)?;
     if file_path.exists() {
-        fs::remove_file(&file_path)
-            .await
-            .map_err(|e| e.to_string())?;
+        trash::move_to_trash(&folder_path, &id, trash::TrashItemKind::Note)?;
     }
+    update_manual_order(&state, &folder, |order| note_order::remove_note(order, &id));
 
     // Update search index
     {
-        let index = state.search_index.lock().expect("search index mutex");
-        if let Some(ref search_index) = *index {
+        if let Some(search_index) = state.current_search_index() {
             let _ = search_index.delete_note(&id);
         }
     }
 
+    // Update link and tag indexes
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        link_index.remove_note(&id);
+    }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.remove_note(&id);
+    }
+
     // Remove from cache
     {
         let mut cache = state.notes_cache.write().expect("cache write lock");
@@ -1178,7 +2445,10 @@ async fn delete_note(id: String, state: State<'_, AppState>) -> Result<(), Strin
 }
 
 #[tauri::command]
-async fn create_note(target_folder: Option<String>, state: State<'_, AppState>) -> Result<Note, String> {
+async fn create_note(
+    target_folder: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Note, String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
         app_config
@@ -1251,29 +2521,36 @@ async fn create_note(target_folder: Option<String>, state: State<'_, AppState>)
             .map_err(|e| e.to_string())?;
     }
 
-    fs::write(&file_path, &content)
+    fsutil::write_atomic_async(&file_path, content.as_str())
         .await
         .map_err(|e| e.to_string())?;
 
-    let modified = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
+    let (modified, modified_ns) = modified_times(&file_path).await;
 
     // Update search index
     {
-        let index = state.search_index.lock().expect("search index mutex");
-        if let Some(ref search_index) = *index {
+        if let Some(search_index) = state.current_search_index() {
             let _ = search_index.index_note(&final_id, &display_title, &content, modified);
         }
     }
 
+    // Update link and tag indexes
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        link_index.update_note(&final_id, &content);
+    }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.update_note(&final_id, &content);
+    }
+
     Ok(Note {
         id: final_id,
         title: display_title,
         content,
         path: file_path.to_string_lossy().into_owned(),
         modified,
+        modified_ns,
     })
 }
 
@@ -1402,15 +2679,16 @@ async fn delete_folder(path: String, state: State<'_, AppState>) -> Result<(), S
 
     // Remove notes from search index
     {
-        let index = state.search_index.lock().expect("search index mutex");
-        if let Some(ref search_index) = *index {
+        if let Some(search_index) = state.current_search_index() {
             let cache = state.notes_cache.read().expect("cache read lock");
             let prefix = format!("{}/", path);
+            let mut batch = search_index.batch();
             for note_id in cache.keys() {
                 if note_id.starts_with(&prefix) {
-                    let _ = search_index.delete_note(note_id);
+                    batch.delete_note(note_id);
                 }
             }
+            let _ = batch.commit();
         }
     }
 
@@ -1421,15 +2699,27 @@ async fn delete_folder(path: String, state: State<'_, AppState>) -> Result<(), S
         cache.retain(|id, _| !id.starts_with(&prefix));
     }
 
-    fs::remove_dir_all(&target)
-        .await
-        .map_err(|e| e.to_string())?;
+    // Update link and tag indexes
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        link_index.remove_prefix(&format!("{}/", path));
+    }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.remove_prefix(&format!("{}/", path));
+    }
+
+    trash::move_to_trash(&PathBuf::from(&folder), &path, trash::TrashItemKind::Folder)?;
+    update_manual_order(&state, &folder, |order| {
+        note_order::remove_folder(order, &path)
+    });
 
     Ok(())
 }
 
 #[tauri::command]
 async fn rename_folder(
+    app: AppHandle,
     old_path: String,
     new_name: String,
     state: State<'_, AppState>,
@@ -1487,6 +2777,7 @@ async fn rename_folder(
     tokio::fs::rename(&old_target, &new_target)
         .await
         .map_err(|e| e.to_string())?;
+    history::rename_folder(&folder_root, &old_path, &new_path);
 
     // Update pinned note IDs in settings
     {
@@ -1500,9 +2791,13 @@ async fn rename_folder(
                 }
             }
         }
+        if let Some(ref mut order) = settings.manual_note_order {
+            note_order::rename_folder(order, &old_path, &new_path);
+        }
         // Save settings
         let _ = save_settings(&folder, &settings);
     }
+    sync_pinned_to_search_index(&state);
 
     // Update cache
     {
@@ -1523,23 +2818,38 @@ async fn rename_folder(
         }
     }
 
-    // Rebuild search index for affected notes
+    // Re-key moved notes in the search index (before links are rewritten, which re-indexes some of them)
+    if let Some(search_index) = state.current_search_index() {
+        let mut batch = search_index.batch();
+        let _ = batch
+            .rekey_folder(&old_path, &new_path)
+            .and_then(|_| batch.commit());
+    }
+
+    // Rewrite links that pointed into the folder, and relative links out of it
+    let renames = folder_renames(&state, &old_prefix, &new_prefix);
+    let relinked_ids = rewrite_links_for_renames(&state, &folder_root, &renames, None);
+
+    // Re-key moved notes in the link and tag indexes (relative links resolve from the new location)
     {
-        let index = state.search_index.lock().expect("search index mutex");
-        if let Some(ref search_index) = *index {
-            let ignored_dirs = {
-                let settings = state.settings.read().expect("settings read lock");
-                get_effective_ignored_dirs(&settings)
-            };
-            let _ = search_index.rebuild_index(&folder_root, &ignored_dirs);
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.remove_prefix(&old_prefix);
+        for old_id in link_index.remove_prefix(&old_prefix) {
+            let new_id = format!("{}{}", new_prefix, &old_id[old_prefix.len()..]);
+            link_index.reindex_from_disk(&folder_root, &new_id);
+            tag_index.reindex_from_disk(&folder_root, &new_id);
         }
     }
 
+    emit_links_updated(&app, relinked_ids);
+
     Ok(())
 }
 
 #[tauri::command]
 async fn move_note(
+    app: AppHandle,
     id: String,
     target_folder: String,
     state: State<'_, AppState>,
@@ -1577,7 +2887,9 @@ async fn move_note(
 
     // Ensure target directory exists
     if let Some(parent) = dest_path.parent() {
-        fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| e.to_string())?;
     }
 
     // Handle collision
@@ -1588,6 +2900,7 @@ async fn move_note(
     tokio::fs::rename(&source_path, &dest_path)
         .await
         .map_err(|e| e.to_string())?;
+    history::rename_note(&folder_root, &id, &new_id);
 
     // Update pinned note IDs
     {
@@ -1599,8 +2912,12 @@ async fn move_note(
                 }
             }
         }
+        if let Some(ref mut order) = settings.manual_note_order {
+            note_order::rename_note(order, &id, &new_id);
+        }
         let _ = save_settings(&folder, &settings);
     }
+    sync_pinned_to_search_index(&state);
 
     // Update cache
     {
@@ -1611,23 +2928,40 @@ async fn move_note(
         }
     }
 
-    // Rebuild search index
+    // Re-key the note in the search index (before links are rewritten, which may re-index it)
+    if let Some(search_index) = state.current_search_index() {
+        let mut batch = search_index.batch();
+        let _ = batch.rekey(&id, &new_id).and_then(|_| batch.commit());
+    }
+
+    // Rewrite links that pointed at the old location
+    let renames = [links::NoteRename {
+        old_id: id.clone(),
+        new_id: new_id.clone(),
+        new_title: None,
+    }];
+    let relinked_ids = rewrite_links_for_renames(&state, &folder_root, &renames, None);
+
+    // Update link and tag indexes
     {
-        let index = state.search_index.lock().expect("search index mutex");
-        if let Some(ref search_index) = *index {
-            let ignored_dirs = {
-                let settings = state.settings.read().expect("settings read lock");
-                get_effective_ignored_dirs(&settings)
-            };
-            let _ = search_index.rebuild_index(&folder_root, &ignored_dirs);
-        }
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        link_index.remove_note(&id);
+        link_index.reindex_from_disk(&folder_root, &new_id);
+    }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.remove_note(&id);
+        tag_index.reindex_from_disk(&folder_root, &new_id);
     }
 
+    emit_links_updated(&app, relinked_ids);
+
     Ok(new_id)
 }
 
 #[tauri::command]
 async fn move_folder(
+    app: AppHandle,
     path: String,
     target_parent: String,
     state: State<'_, AppState>,
@@ -1678,7 +3012,9 @@ async fn move_folder(
 
     // Ensure target parent exists
     if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent).await.map_err(|e| e.to_string())?;
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| e.to_string())?;
     }
 
     // Compute old and new path prefixes for updating IDs
@@ -1693,6 +3029,7 @@ async fn move_folder(
     tokio::fs::rename(&source, &dest)
         .await
         .map_err(|e| e.to_string())?;
+    history::rename_folder(&folder_root, &path, &new_path);
 
     // Update pinned note IDs
     {
@@ -1704,8 +3041,12 @@ async fn move_folder(
                 }
             }
         }
+        if let Some(ref mut order) = settings.manual_note_order {
+            note_order::rename_folder(order, &path, &new_path);
+        }
         let _ = save_settings(&folder, &settings);
     }
+    sync_pinned_to_search_index(&state);
 
     // Update cache
     {
@@ -1726,47 +3067,458 @@ async fn move_folder(
         }
     }
 
-    // Rebuild search index
+    // Re-key moved notes in the search index (before links are rewritten, which re-indexes some of them)
+    if let Some(search_index) = state.current_search_index() {
+        let mut batch = search_index.batch();
+        let _ = batch
+            .rekey_folder(&path, &new_path)
+            .and_then(|_| batch.commit());
+    }
+
+    // Rewrite links that pointed into the folder, and relative links out of it
+    let renames = folder_renames(&state, &old_prefix, &new_prefix);
+    let relinked_ids = rewrite_links_for_renames(&state, &folder_root, &renames, None);
+
+    // Re-key moved notes in the link and tag indexes (relative links resolve from the new location)
     {
-        let index = state.search_index.lock().expect("search index mutex");
-        if let Some(ref search_index) = *index {
-            let ignored_dirs = {
-                let settings = state.settings.read().expect("settings read lock");
-                get_effective_ignored_dirs(&settings)
-            };
-            let _ = search_index.rebuild_index(&folder_root, &ignored_dirs);
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.remove_prefix(&old_prefix);
+        for old_id in link_index.remove_prefix(&old_prefix) {
+            let new_id = format!("{}{}", new_prefix, &old_id[old_prefix.len()..]);
+            link_index.reindex_from_disk(&folder_root, &new_id);
+            tag_index.reindex_from_disk(&folder_root, &new_id);
         }
     }
 
+    emit_links_updated(&app, relinked_ids);
+
     Ok(())
 }
 
-#[tauri::command]
-fn get_settings(state: State<AppState>) -> Settings {
-    state.settings.read().expect("settings read lock").clone()
+/// Get the effective version history policy from settings (or defaults if not customized).
+fn get_history_policy(settings: &Settings) -> history::HistoryPolicy {
+    history::HistoryPolicy {
+        interval_minutes: settings
+            .history_interval_minutes
+            .unwrap_or(history::DEFAULT_INTERVAL_MINUTES),
+        max_versions: settings
+            .history_max_versions
+            .unwrap_or(history::DEFAULT_MAX_VERSIONS),
+        retention_days: settings
+            .history_retention_days
+            .unwrap_or(history::DEFAULT_RETENTION_DAYS),
+    }
+}
+
+/// Get the search analyzer settings (plain word matching if not customized).
+fn get_search_analyzer(settings: &Settings) -> analyzer::AnalyzerSettings {
+    settings.search_analyzer.clone().unwrap_or_default()
+}
+
+/// Get the embedding model for semantic search (or the default if not customized).
+fn get_embedding_model(settings: &Settings) -> String {
+    settings
+        .embedding_model
+        .as_deref()
+        .map(str::trim)
+        .filter(|model| !model.is_empty())
+        .unwrap_or(embeddings::DEFAULT_MODEL)
+        .to_string()
+}
+
+/// Get the effective trash retention from settings (or the default if not customized).
+fn get_trash_retention_days(settings: &Settings) -> u32 {
+    settings
+        .trash_retention_days
+        .unwrap_or(trash::DEFAULT_RETENTION_DAYS)
+}
+
+#[tauri::command]
+fn list_note_versions(
+    id: String,
+    state: State<AppState>,
+) -> Result<Vec<history::NoteVersion>, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let folder_path = PathBuf::from(&folder);
+    abs_path_from_id(&folder_path, &id)?;
+
+    Ok(history::list_versions(&folder_path, &id))
+}
+
+#[tauri::command]
+fn read_note_version(
+    id: String,
+    version_id: String,
+    state: State<AppState>,
+) -> Result<String, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let folder_path = PathBuf::from(&folder);
+    abs_path_from_id(&folder_path, &id)?;
+
+    history::read_version(&folder_path, &id, &version_id)
+}
+
+#[tauri::command]
+async fn restore_note_version(
+    id: String,
+    version_id: String,
+    state: State<'_, AppState>,
+) -> Result<Note, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let folder_path = PathBuf::from(&folder);
+    let file_path = abs_path_from_id(&folder_path, &id)?;
+
+    let content = history::read_version(&folder_path, &id, &version_id)?;
+
+    // Snapshot the current content first so the restore itself can be undone
+    if let Ok(current) = fs::read_to_string(&file_path).await {
+        let policy = {
+            let settings = state.settings.read().expect("settings read lock");
+            get_history_policy(&settings)
+        };
+        let _ = history::snapshot(
+            &folder_path,
+            &id,
+            &current,
+            history::SnapshotSource::Restore,
+            &policy,
+            false,
+        );
+    }
+
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    fsutil::write_atomic_async(&file_path, content.as_str())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let (modified, modified_ns) = modified_times(&file_path).await;
+    let title = extract_title(&content);
+
+    {
+        if let Some(search_index) = state.current_search_index() {
+            let _ = search_index.index_note(&id, &title, &content, modified);
+        }
+    }
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        link_index.update_note(&id, &content);
+    }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.update_note(&id, &content);
+    }
+
+    Ok(Note {
+        id,
+        title,
+        content,
+        path: file_path.to_string_lossy().into_owned(),
+        modified,
+        modified_ns,
+    })
+}
+
+/// Snapshot a note before an AI CLI edits it in place, so the edit can be undone
+/// from version history. Files outside the notes folder are ignored.
+fn snapshot_before_ai_edit(state: &AppState, file_path: &Path) {
+    let Some(folder) = state
+        .app_config
+        .read()
+        .expect("app_config read lock")
+        .notes_folder
+        .clone()
+    else {
+        return;
+    };
+    let (Ok(notes_root), Ok(canonical)) = (
+        PathBuf::from(&folder).canonicalize(),
+        file_path.canonicalize(),
+    ) else {
+        return;
+    };
+
+    let (ignored_dirs, policy) = {
+        let settings = state.settings.read().expect("settings read lock");
+        (
+            get_effective_ignored_dirs(&settings),
+            get_history_policy(&settings),
+        )
+    };
+    let Some(id) = id_from_abs_path(&notes_root, &canonical, &ignored_dirs) else {
+        return;
+    };
+
+    if let Ok(content) = std::fs::read_to_string(&canonical) {
+        let _ = history::snapshot(
+            &notes_root,
+            &id,
+            &content,
+            history::SnapshotSource::Ai,
+            &policy,
+            false,
+        );
+    }
+}
+
+#[tauri::command]
+async fn list_trash(state: State<'_, AppState>) -> Result<Vec<trash::TrashEntry>, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let retention_days = {
+        let settings = state.settings.read().expect("settings read lock");
+        get_trash_retention_days(&settings)
+    };
+
+    let folder_path = PathBuf::from(&folder);
+    tokio::task::spawn_blocking(move || {
+        trash::purge_expired(&folder_path, retention_days);
+        trash::list(&folder_path)
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn restore_from_trash(
+    app: AppHandle,
+    entry_id: String,
+    state: State<'_, AppState>,
+) -> Result<trash::TrashEntry, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let folder_path = PathBuf::from(&folder);
+
+    let restored = trash::restore(&folder_path, &entry_id)?;
+
+    match restored.kind {
+        trash::TrashItemKind::Note => {
+            let id = &restored.original_path;
+            let file_path = abs_path_from_id(&folder_path, id)?;
+            let content = fs::read_to_string(&file_path)
+                .await
+                .map_err(|e| e.to_string())?;
+            let modified = fs::metadata(&file_path)
+                .await
+                .ok()
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+
+            {
+                if let Some(search_index) = state.current_search_index() {
+                    let _ =
+                        search_index.index_note(id, &extract_title(&content), &content, modified);
+                }
+            }
+            {
+                let mut link_index = state.link_index.write().expect("link index write lock");
+                link_index.update_note(id, &content);
+            }
+            {
+                let mut tag_index = state.tag_index.write().expect("tag index write lock");
+                tag_index.update_note(id, &content);
+            }
+        }
+        trash::TrashItemKind::Folder => {
+            let ignored_dirs = {
+                let settings = state.settings.read().expect("settings read lock");
+                get_effective_ignored_dirs(&settings)
+            };
+            // Index the restored notes in the background
+            let _ = indexer::queue_for_current_folder(&app, &state, false);
+            {
+                let mut link_index = state.link_index.write().expect("link index write lock");
+                link_index.rebuild(&folder_path, &ignored_dirs);
+            }
+            {
+                let mut tag_index = state.tag_index.write().expect("tag index write lock");
+                tag_index.rebuild(&folder_path, &ignored_dirs);
+            }
+        }
+    }
+
+    Ok(restored)
+}
+
+#[tauri::command]
+async fn empty_trash(state: State<'_, AppState>) -> Result<(), String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+
+    let folder_path = PathBuf::from(&folder);
+    tokio::task::spawn_blocking(move || trash::empty(&folder_path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn get_settings(state: State<AppState>) -> Settings {
+    state.settings.read().expect("settings read lock").clone()
 }
 
 #[tauri::command]
 fn update_settings(
+    app: AppHandle,
     new_settings: Settings,
     state: State<AppState>,
 ) -> Result<(), String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
-        app_config.notes_folder.clone().ok_or("Notes folder not set")?
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
     };
+    get_search_analyzer(&new_settings).validate()?;
+    saved_searches::validate(new_settings.saved_searches.as_deref().unwrap_or_default())?;
+    if let Some(auto_sync) = &new_settings.git_auto_sync {
+        auto_sync.validate()?;
+    }
 
-    {
+    let analyzer_changed = {
         let mut settings = state.settings.write().expect("settings write lock");
+        let changed = get_search_analyzer(&settings) != get_search_analyzer(&new_settings);
         *settings = new_settings;
+        changed
+    };
+    sync_pinned_to_search_index(&state);
+
+    {
+        let settings = state.settings.read().expect("settings read lock");
+        save_settings(&folder, &settings).map_err(|e| e.to_string())?;
     }
 
-    let settings = state.settings.read().expect("settings read lock");
-    save_settings(&folder, &settings).map_err(|e| e.to_string())?;
+    state.git_sync.settings_changed();
+
+    // Words are split differently now, so search needs an index built with the new analyzer
+    if analyzer_changed {
+        reopen_search_index(&app, &state, Path::new(&folder))?;
+    }
 
     Ok(())
 }
 
+#[tauri::command]
+fn list_saved_searches(state: State<AppState>) -> Vec<saved_searches::SavedSearch> {
+    let settings = state.settings.read().expect("settings read lock");
+    settings.saved_searches.clone().unwrap_or_default()
+}
+
+#[tauri::command]
+async fn run_saved_search(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchResult>, String> {
+    let search = {
+        let settings = state.settings.read().expect("settings read lock");
+        settings
+            .saved_searches
+            .iter()
+            .flatten()
+            .find(|search| search.id == id)
+            .cloned()
+            .ok_or("Saved search not found")?
+    };
+    let search_index = state
+        .current_search_index()
+        .ok_or("Search index not initialized")?;
+    saved_searches::run(&state, &search_index, &search)
+}
+
+/// Where and with what to embed notes in the current folder.
+fn embedding_context(app: &AppHandle, state: &AppState) -> Result<embeddings::Context, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let (ignored_dirs, model) = {
+        let settings = state.settings.read().expect("settings read lock");
+        (
+            get_effective_ignored_dirs(&settings),
+            get_embedding_model(&settings),
+        )
+    };
+    Ok(embeddings::Context {
+        app_data: app.path().app_data_dir().map_err(|e| e.to_string())?,
+        notes_folder: PathBuf::from(folder),
+        ignored_dirs,
+        embedder: Box::new(embeddings::OllamaEmbedder::new(&model)),
+    })
+}
+
+/// Notes related in meaning to the query, using local embeddings. Notes changed
+/// since they were embedded are caught up in the background.
+#[tauri::command]
+async fn semantic_search(
+    app: AppHandle,
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<embeddings::SemanticResult>, String> {
+    if query.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let ctx = embedding_context(&app, &state)?;
+    tokio::task::spawn_blocking(move || embeddings::semantic_search(&app, ctx, query.trim(), 20))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Notes closest in meaning to a note. Works from stored embeddings when
+/// Ollama isn't available.
+#[tauri::command]
+async fn related_notes(
+    app: AppHandle,
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<embeddings::SemanticResult>, String> {
+    let ctx = embedding_context(&app, &state)?;
+    tokio::task::spawn_blocking(move || embeddings::related_notes(&app, ctx, &id, 10))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 fn update_git_enabled(
     enabled: Option<bool>,
@@ -1775,7 +3527,10 @@ fn update_git_enabled(
 ) -> Result<(), String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
-        let folder = app_config.notes_folder.clone().ok_or("Notes folder not set")?;
+        let folder = app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?;
 
         if folder != expected_folder {
             return Err("Notes folder changed".to_string());
@@ -1789,15 +3544,18 @@ fn update_git_enabled(
         settings.git_enabled = enabled;
     }
 
-    let settings = state.settings.read().expect("settings read lock");
-    save_settings(&folder, &settings).map_err(|e| e.to_string())?;
+    {
+        let settings = state.settings.read().expect("settings read lock");
+        save_settings(&folder, &settings).map_err(|e| e.to_string())?;
+    }
+    state.git_sync.settings_changed();
 
     Ok(())
 }
 
 #[tauri::command]
 async fn write_file(path: String, contents: Vec<u8>) -> Result<(), String> {
-    fs::write(&path, contents)
+    fsutil::write_atomic_async(Path::new(&path), contents)
         .await
         .map_err(|_| "Failed to write file".to_string())
 }
@@ -1819,11 +3577,13 @@ fn preview_note_name(template: String) -> Result<String, String> {
 
 // Preview mode: file content returned by read_file_direct / save_file_direct
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FileContent {
     pub path: String,
     pub content: String,
     pub title: String,
     pub modified: i64,
+    pub modified_ns: String,
 }
 
 /// Validate a file path for preview mode direct file operations.
@@ -1859,13 +3619,7 @@ async fn read_file_direct(path: String) -> Result<FileContent, String> {
     let metadata = fs::metadata(&canonical)
         .await
         .map_err(|_| "Failed to read metadata".to_string())?;
-
-    let modified = metadata
-        .modified()
-        .ok()
-        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
+    let (modified_ns, _) = metadata_cache::file_stamp(&metadata);
 
     let title = extract_title(&content);
 
@@ -1873,32 +3627,34 @@ async fn read_file_direct(path: String) -> Result<FileContent, String> {
         path,
         content,
         title,
-        modified,
+        modified: (modified_ns / 1_000_000_000) as i64,
+        modified_ns: modified_ns.to_string(),
     })
 }
 
 #[tauri::command]
-async fn save_file_direct(path: String, content: String) -> Result<FileContent, String> {
+async fn save_file_direct(
+    path: String,
+    content: String,
+    expected_modified_ns: Option<String>,
+) -> Result<FileContent, SaveError> {
     // For save, the file must already exist (we validate extension + path security)
     let canonical = validate_preview_path(&path)?;
 
     if !canonical.is_file() {
-        return Err(format!("Not a file: {}", path));
+        return Err(format!("Not a file: {}", path).into());
     }
 
-    fs::write(&canonical, &content)
+    check_save_conflict(&canonical, expected_modified_ns.as_deref(), &content).await?;
+
+    fsutil::write_atomic_async(&canonical, content.as_str())
         .await
         .map_err(|_| "Failed to write file".to_string())?;
 
     let metadata = fs::metadata(&canonical)
         .await
         .map_err(|_| "Failed to read metadata".to_string())?;
-    let modified = metadata
-        .modified()
-        .ok()
-        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
+    let (modified_ns, _) = metadata_cache::file_stamp(&metadata);
 
     let title = extract_title(&content);
 
@@ -1906,7 +3662,8 @@ async fn save_file_direct(path: String, content: String) -> Result<FileContent,
         path,
         content,
         title,
-        modified,
+        modified: (modified_ns / 1_000_000_000) as i64,
+        modified_ns: modified_ns.to_string(),
     })
 }
 
@@ -1973,7 +3730,7 @@ async fn import_file_to_folder(
             }
             Err(_) => return Err("Failed to create file".to_string()),
         }
-    };
+    }
 
     let modified = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -1982,12 +3739,21 @@ async fn import_file_to_folder(
 
     // Update search index
     {
-        let index = state.search_index.lock().expect("search index mutex");
-        if let Some(ref search_index) = *index {
+        if let Some(search_index) = state.current_search_index() {
             let _ = search_index.index_note(&final_id, &extracted_title, &content, modified);
         }
     }
 
+    // Update link and tag indexes
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        link_index.update_note(&final_id, &content);
+    }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.update_note(&final_id, &content);
+    }
+
     let preview = content
         .lines()
         .skip(1)
@@ -1996,11 +3762,14 @@ async fn import_file_to_folder(
         .collect::<Vec<_>>()
         .join(" ");
 
+    let frontmatter = frontmatter::parse(&content);
     let metadata = NoteMetadata {
         id: final_id,
         title: extracted_title,
         preview,
         modified,
+        created: note_order::created(&frontmatter, None, modified),
+        frontmatter,
     };
 
     // Update notes cache so fallback search sees the imported note immediately
@@ -2019,39 +3788,58 @@ async fn import_file_to_folder(
 }
 
 #[tauri::command]
-async fn search_notes(query: String, state: State<'_, AppState>) -> Result<Vec<SearchResult>, String> {
+async fn search_notes(
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchResult>, String> {
     let trimmed_query = query.trim().to_string();
     if trimmed_query.is_empty() {
         return Ok(vec![]);
     }
 
+    // Syntax errors are reported to the user rather than guessed around
+    let clauses = search_query::parse(&trimmed_query)?;
+    if clauses.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // Until the query ends with a space, the last word is still being typed
+    let prefix_last = !query.ends_with(char::is_whitespace);
+
     // Check if search index is available and use it (scoped to drop lock before await)
     let indexed_result = {
-        let index = state.search_index.lock().expect("search index mutex");
-        (*index).as_ref().map(|search_index| {
-            search_index.search(&trimmed_query, 20).map_err(|e| e.to_string())
+        state.current_search_index().map(|search_index| {
+            search_index
+                .search(&clauses, prefix_last, 20)
+                .map_err(|e| e.to_string())
         })
     };
 
+    // Substring search only understands plain text, so filtered queries never fall back
+    let plain_text = search_query::is_plain_text(&clauses);
     match indexed_result {
-        Some(Ok(results)) if !results.is_empty() => Ok(results),
-        Some(Ok(_)) => {
-            // Tantivy can miss partial/fuzzy matches; fall back to substring search.
-            fallback_search(&trimmed_query, &state).await
-        }
-        Some(Err(e)) => {
-            eprintln!("Tantivy search error, falling back to substring search: {}", e);
-            fallback_search(&trimmed_query, &state).await
+        Some(Ok(results)) => Ok(results),
+        Some(Err(e)) if plain_text => {
+            eprintln!(
+                "Tantivy search error, falling back to substring search: {}",
+                e
+            );
+            fallback_search(&search_query::plain_text(&clauses), &state).await
         }
-        None => {
+        Some(Err(e)) => Err(e),
+        None if plain_text => {
             // Fallback to simple search if index not available
-            fallback_search(&trimmed_query, &state).await
+            fallback_search(&search_query::plain_text(&clauses), &state).await
         }
+        None => Err("Search filters need the search index, which isn't available".to_string()),
     }
 }
 
 // Fallback search when Tantivy index isn't available - searches title and full content
-async fn fallback_search(query: &str, state: &State<'_, AppState>) -> Result<Vec<SearchResult>, String> {
+async fn fallback_search(
+    query: &str,
+    state: &State<'_, AppState>,
+) -> Result<Vec<SearchResult>, String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
         app_config.notes_folder.clone()
@@ -2095,6 +3883,7 @@ async fn fallback_search(query: &str, state: &State<'_, AppState>) -> Result<Vec
             Ok(p) => p,
             Err(_) => continue,
         };
+        let mut content_snippets = Vec::new();
         if let Ok(content) = tokio::fs::read_to_string(&file_path).await {
             let content_lower = content.to_lowercase();
             if content_lower.contains(&query_lower) {
@@ -2104,12 +3893,15 @@ async fn fallback_search(query: &str, state: &State<'_, AppState>) -> Result<Vec
                 } else {
                     score += 5.0;
                 }
+                content_snippets = snippets::from_substring(&content, query);
             }
         }
 
         if score > 0.0 {
             results.push(SearchResult {
                 id,
+                title_highlights: snippets::substring_title_highlights(&title, query),
+                snippets: content_snippets,
                 title,
                 preview,
                 modified,
@@ -2118,10 +3910,418 @@ async fn fallback_search(query: &str, state: &State<'_, AppState>) -> Result<Vec
         }
     }
 
-    results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
-    results.truncate(20);
+    results.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    results.truncate(20);
+
+    Ok(results)
+}
+
+// Links-updated event payload: notes whose files were rewritten to follow a rename
+#[derive(Clone, Serialize)]
+struct LinksUpdatedEvent {
+    changed_ids: Vec<String>,
+}
+
+/// Rewrite wikilinks and relative markdown links in every note affected by `renames`,
+/// then re-index the rewritten notes. Must run after the files were moved on disk but
+/// before the link index is updated for the renames, since links are resolved against
+/// the pre-rename index. Returns the (post-rename) IDs of notes that were rewritten.
+/// `skip_id` excludes a note whose content is owned by the editor (the note being saved).
+fn rewrite_links_for_renames(
+    state: &AppState,
+    notes_root: &Path,
+    renames: &[links::NoteRename],
+    skip_id: Option<&str>,
+) -> Vec<String> {
+    if renames.is_empty() {
+        return Vec::new();
+    }
+
+    let rewritten: Vec<(String, String)> = {
+        let link_index = state.link_index.read().expect("link index read lock");
+        let mut rewritten = Vec::new();
+        for source_id in link_index.rewrite_candidates(renames) {
+            let current_id = renames
+                .iter()
+                .find(|r| r.old_id == source_id)
+                .map(|r| r.new_id.clone())
+                .unwrap_or_else(|| source_id.clone());
+            if skip_id == Some(current_id.as_str()) {
+                continue;
+            }
+            let Ok(file_path) = abs_path_from_id(notes_root, &current_id) else {
+                continue;
+            };
+            let Ok(content) = std::fs::read_to_string(&file_path) else {
+                continue;
+            };
+            if let Some(updated) = link_index.rewrite_links(&source_id, &content, renames) {
+                if fsutil::write_atomic(&file_path, updated.as_bytes()).is_ok() {
+                    rewritten.push((current_id, updated));
+                }
+            }
+        }
+        rewritten
+    };
+
+    if rewritten.is_empty() {
+        return Vec::new();
+    }
+
+    {
+        if let Some(search_index) = state.current_search_index() {
+            let mut batch = search_index.batch();
+            for (id, _) in &rewritten {
+                let _ = batch.reindex_from_disk(notes_root, id);
+            }
+            let _ = batch.commit();
+        }
+    }
+
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        for (id, content) in &rewritten {
+            link_index.update_note(id, content);
+        }
+    }
+
+    rewritten.into_iter().map(|(id, _)| id).collect()
+}
+
+/// Build the rename list for every note under a moved or renamed folder.
+fn folder_renames(state: &AppState, old_prefix: &str, new_prefix: &str) -> Vec<links::NoteRename> {
+    let link_index = state.link_index.read().expect("link index read lock");
+    link_index
+        .ids_with_prefix(old_prefix)
+        .into_iter()
+        .map(|old_id| links::NoteRename {
+            new_id: format!("{}{}", new_prefix, &old_id[old_prefix.len()..]),
+            old_id,
+            new_title: None,
+        })
+        .collect()
+}
+
+fn emit_links_updated(app: &AppHandle, changed_ids: Vec<String>) {
+    if !changed_ids.is_empty() {
+        let _ = app.emit("links-updated", LinksUpdatedEvent { changed_ids });
+    }
+}
+
+#[tauri::command]
+fn get_backlinks(id: String, state: State<AppState>) -> Vec<links::Backlink> {
+    let link_index = state.link_index.read().expect("link index read lock");
+    link_index.backlinks(&id)
+}
+
+#[tauri::command]
+fn get_outgoing_links(id: String, state: State<AppState>) -> Vec<links::OutgoingLink> {
+    let link_index = state.link_index.read().expect("link index read lock");
+    link_index.outgoing_links(&id)
+}
+
+#[tauri::command]
+fn list_tags(state: State<AppState>) -> Vec<tags::TagCount> {
+    let tag_index = state.tag_index.read().expect("tag index read lock");
+    tag_index.list()
+}
+
+/// Notes tagged with `tag` or one of its nested tags, newest first.
+#[tauri::command]
+async fn list_notes_by_tag(
+    tag: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<NoteMetadata>, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let folder_path = PathBuf::from(&folder);
+
+    let tag = tags::normalize_tag(&tag).ok_or("Invalid tag")?;
+    let ids = {
+        let tag_index = state.tag_index.read().expect("tag index read lock");
+        tag_index.notes_with_tag(&tag)
+    };
+
+    let mut notes = Vec::with_capacity(ids.len());
+    for id in ids {
+        let Ok(file_path) = abs_path_from_id(&folder_path, &id) else {
+            continue;
+        };
+        let Ok(content) = fs::read_to_string(&file_path).await else {
+            continue;
+        };
+        let file_metadata = fs::metadata(&file_path).await.ok();
+        let modified = file_metadata
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let frontmatter = frontmatter::parse(&content);
+        notes.push(NoteMetadata {
+            id,
+            title: extract_title(&content),
+            preview: generate_preview(&content),
+            modified,
+            created: note_order::created(&frontmatter, file_metadata.as_ref(), modified),
+            frontmatter,
+        });
+    }
+
+    notes.sort_by_key(|n| std::cmp::Reverse(n.modified));
+    Ok(notes)
+}
+
+/// Rename a tag (and its nested tags) in every note that uses it, in note
+/// bodies and frontmatter. Returns the IDs of the rewritten notes.
+#[tauri::command]
+async fn rename_tag(
+    old_tag: String,
+    new_tag: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let folder_path = PathBuf::from(&folder);
+
+    let old_tag = tags::normalize_tag(&old_tag).ok_or("Invalid tag")?;
+    let new_tag = tags::normalize_tag(&new_tag).ok_or("Invalid tag name")?;
+    if old_tag == new_tag {
+        return Ok(vec![]);
+    }
+
+    let ids = {
+        let tag_index = state.tag_index.read().expect("tag index read lock");
+        tag_index.notes_with_tag(&old_tag)
+    };
+
+    // Work out every change before writing any, so a bad ID or unparseable
+    // frontmatter fails the rename without leaving it half done
+    let mut updates: Vec<(String, PathBuf, String)> = Vec::new();
+    for id in ids {
+        let file_path = abs_path_from_id(&folder_path, &id)?;
+        let Ok(content) = fs::read_to_string(&file_path).await else {
+            continue;
+        };
+        if let Some(updated) = tags::rename_in_content(&content, &old_tag, &new_tag)
+            .map_err(|e| format!("{}: {}", id, e))?
+        {
+            updates.push((id, file_path, updated));
+        }
+    }
+
+    let mut rewritten: Vec<(String, String)> = Vec::new();
+    let mut write_error = None;
+    for (id, file_path, updated) in updates {
+        if let Err(e) = fsutil::write_atomic_async(&file_path, updated.as_str()).await {
+            // Stop, but still reindex the notes already written
+            write_error = Some(format!("Failed to write {}: {}", id, e));
+            break;
+        }
+        rewritten.push((id, updated));
+    }
+
+    reindex_rewritten_notes(&state, &folder_path, &rewritten);
+
+    match write_error {
+        Some(error) => Err(error),
+        None => Ok(rewritten.into_iter().map(|(id, _)| id).collect()),
+    }
+}
+
+/// Bring the search, link and tag indexes and the notes cache up to date after
+/// notes were rewritten in bulk.
+fn reindex_rewritten_notes(state: &AppState, notes_root: &Path, notes: &[(String, String)]) {
+    if let Some(search_index) = state.current_search_index() {
+        let mut batch = search_index.batch();
+        for (id, _) in notes {
+            let _ = batch.reindex_from_disk(notes_root, id);
+        }
+        let _ = batch.commit();
+    }
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        for (id, content) in notes {
+            link_index.update_note(id, content);
+            tag_index.update_note(id, content);
+        }
+    }
+    {
+        let mut cache = state.notes_cache.write().expect("cache write lock");
+        for (id, content) in notes {
+            let Some(meta) = cache.get_mut(id) else {
+                continue;
+            };
+            meta.title = extract_title(content);
+            meta.preview = generate_preview(content);
+            meta.frontmatter = frontmatter::parse(content);
+            if let Some(modified) = abs_path_from_id(notes_root, id)
+                .ok()
+                .and_then(|p| std::fs::metadata(p).ok())
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            {
+                meta.modified = modified.as_secs() as i64;
+            }
+        }
+    }
+}
+
+/// Find a literal string or regex in every note (or one folder), with the
+/// line, column and surrounding text of each match.
+#[tauri::command]
+async fn find_in_vault(
+    pattern: String,
+    options: Option<find_replace::FindOptions>,
+    state: State<'_, AppState>,
+) -> Result<Vec<find_replace::NoteMatches>, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let ignored_dirs = {
+        let settings = state.settings.read().expect("settings read lock");
+        get_effective_ignored_dirs(&settings)
+    };
+
+    let folder_path = PathBuf::from(&folder);
+    let options = options.unwrap_or_default();
+    tokio::task::spawn_blocking(move || {
+        find_replace::find(&folder_path, &ignored_dirs, &pattern, &options)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Replace matches of a pattern across notes. Either every note is written or
+/// none is. The previous contents are kept under the returned undo token.
+#[tauri::command]
+async fn replace_in_vault(
+    pattern: String,
+    replacement: String,
+    selection: Option<find_replace::ReplaceSelection>,
+    state: State<'_, AppState>,
+) -> Result<find_replace::ReplaceResult, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let (ignored_dirs, policy) = {
+        let settings = state.settings.read().expect("settings read lock");
+        (
+            get_effective_ignored_dirs(&settings),
+            get_history_policy(&settings),
+        )
+    };
+
+    let folder_path = PathBuf::from(&folder);
+    let selection = selection.unwrap_or_default();
+    let notes_root = folder_path.clone();
+    let (edits, replaced, undo_token) = tokio::task::spawn_blocking(move || {
+        let (edits, replaced) = find_replace::plan_replace(
+            &notes_root,
+            &ignored_dirs,
+            &pattern,
+            &replacement,
+            &selection,
+        )?;
+        if edits.is_empty() {
+            return Ok((edits, 0, None));
+        }
+
+        // Each note's history gets the version from before the replacement
+        for edit in &edits {
+            let _ = history::snapshot(
+                &notes_root,
+                &edit.id,
+                &edit.before,
+                history::SnapshotSource::Replace,
+                &policy,
+                false,
+            );
+        }
+        let undo_token = find_replace::save_undo(&notes_root, &edits)?;
+        if let Err(e) = find_replace::apply(&edits) {
+            find_replace::remove_undo(&notes_root, &undo_token);
+            return Err(e);
+        }
+        Ok::<_, String>((edits, replaced, Some(undo_token)))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let rewritten: Vec<(String, String)> = edits.into_iter().map(|e| (e.id, e.after)).collect();
+    reindex_rewritten_notes(&state, &folder_path, &rewritten);
+
+    Ok(find_replace::ReplaceResult {
+        undo_token,
+        changed_ids: rewritten.into_iter().map(|(id, _)| id).collect(),
+        replaced,
+    })
+}
+
+/// Undo a `replace_in_vault`. Notes edited since the replacement are left as
+/// they are. Returns the IDs of the notes that were restored.
+#[tauri::command]
+async fn undo_replace_in_vault(
+    undo_token: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+
+    let folder_path = PathBuf::from(&folder);
+    let notes_root = folder_path.clone();
+    let edits = tokio::task::spawn_blocking(move || {
+        let edits: Vec<find_replace::Edit> = find_replace::load_undo(&notes_root, &undo_token)?
+            .into_iter()
+            .filter_map(|undo| {
+                let path = abs_path_from_id(&notes_root, &undo.id).ok()?;
+                let current = std::fs::read_to_string(&path).ok()?;
+                (current == undo.after).then_some(find_replace::Edit {
+                    id: undo.id,
+                    path,
+                    before: undo.after,
+                    after: undo.before,
+                })
+            })
+            .collect();
+        find_replace::apply(&edits)?;
+        find_replace::remove_undo(&notes_root, &undo_token);
+        Ok::<_, String>(edits)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
 
-    Ok(results)
+    let restored: Vec<(String, String)> = edits.into_iter().map(|e| (e.id, e.after)).collect();
+    reindex_rewritten_notes(&state, &folder_path, &restored);
+    Ok(restored.into_iter().map(|(id, _)| id).collect())
 }
 
 // File watcher event payload
@@ -2164,7 +4364,9 @@ fn setup_file_watcher(
                         let now = Instant::now();
 
                         if map.len() > 100 {
-                            map.retain(|_, last| now.duration_since(*last) < Duration::from_secs(5));
+                            map.retain(|_, last| {
+                                now.duration_since(*last) < Duration::from_secs(5)
+                            });
                         }
 
                         if let Some(last) = map.get(path) {
@@ -2184,36 +4386,25 @@ fn setup_file_watcher(
                         _ => continue,
                     };
 
-                    // Update search index for external file changes
+                    // Update search index for external file changes (batched by the indexing worker)
                     if let Some(state) = app_handle.try_state::<AppState>() {
-                        let index = state.search_index.lock().expect("search index mutex");
-                        if let Some(ref search_index) = *index {
-                            match kind {
-                                "created" | "modified" => {
-                                    match std::fs::read_to_string(path) {
-                                        Ok(content) => {
-                                            let title = extract_title(&content);
-                                            let modified = std::fs::metadata(path)
-                                                .ok()
-                                                .and_then(|m| m.modified().ok())
-                                                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                                                .map(|d| d.as_secs() as i64)
-                                                .unwrap_or(0);
-                                            let _ = search_index.index_note(&note_id, &title, &content, modified);
-                                        }
-                                        Err(_) => {
-                                            // File gone between event and read — treat as deletion
-                                            if !path.exists() {
-                                                let _ = search_index.delete_note(&note_id);
-                                            }
-                                        }
-                                    }
-                                }
-                                "deleted" => {
-                                    let _ = search_index.delete_note(&note_id);
-                                }
-                                _ => {}
-                            }
+                        indexer::queue_notes(
+                            &app_handle,
+                            &state,
+                            &notes_root,
+                            vec![note_id.clone()],
+                        );
+                        embeddings::notes_changed(&app_handle, &state);
+                        state.git_sync.note_changed();
+
+                        // Update the metadata cache in place, and the link and tag indexes
+                        // with it (re-reads the file, or drops it if gone)
+                        let mut metadata_cache =
+                            state.metadata_cache.lock().expect("metadata cache mutex");
+                        if Path::new(metadata_cache.notes_folder()) == notes_root
+                            && metadata_cache.refresh_note(&note_id)
+                        {
+                            apply_note_metadata(&state, &metadata_cache, [&note_id]);
                         }
                     }
 
@@ -2263,11 +4454,7 @@ fn start_file_watcher(app: AppHandle, state: State<AppState>) -> Result<(), Stri
     // Clean up debounce map before starting
     cleanup_debounce_map(&state.debounce_map);
 
-    let watcher_state = setup_file_watcher(
-        app,
-        &folder,
-        Arc::clone(&state.debounce_map),
-    )?;
+    let watcher_state = setup_file_watcher(app, &folder, Arc::clone(&state.debounce_map))?;
 
     let mut file_watcher = state.file_watcher.lock().expect("file watcher mutex");
     *file_watcher = Some(watcher_state);
@@ -2407,7 +4594,7 @@ async fn copy_image_to_assets(
 }
 
 #[tauri::command]
-fn rebuild_search_index(state: State<AppState>) -> Result<(), String> {
+fn rebuild_search_index(app: AppHandle, state: State<AppState>) -> Result<(), String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
         app_config
@@ -2421,13 +4608,17 @@ fn rebuild_search_index(state: State<AppState>) -> Result<(), String> {
         get_effective_ignored_dirs(&settings)
     };
 
-    let index = state.search_index.lock().expect("search index mutex");
-    match index.as_ref() {
-        Some(search_index) => search_index
-            .rebuild_index(&PathBuf::from(&folder), &ignored_dirs)
-            .map_err(|e| e.to_string()),
-        None => Err("Search index not initialized".to_string()),
+    {
+        let mut link_index = state.link_index.write().expect("link index write lock");
+        link_index.rebuild(&PathBuf::from(&folder), &ignored_dirs);
     }
+    {
+        let mut tag_index = state.tag_index.write().expect("tag index write lock");
+        tag_index.rebuild(&PathBuf::from(&folder), &ignored_dirs);
+    }
+
+    // Runs in the background; searches keep using the current index meanwhile
+    indexer::queue_for_current_folder(&app, &state, true)
 }
 
 #[tauri::command]
@@ -2534,31 +4725,59 @@ async fn git_get_status(state: State<'_, AppState>) -> Result<git::GitStatus, St
         let app_config = state.app_config.read().expect("app_config read lock");
         app_config.notes_folder.clone()
     };
+    let ignored_dirs = {
+        let settings = state.settings.read().expect("settings read lock");
+        get_effective_ignored_dirs(&settings)
+    };
 
     match folder {
-        Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
-                git::get_status(&PathBuf::from(path))
-            })
-            .await
-            .map_err(|e| e.to_string())
-        }
+        Some(path) => tauri::async_runtime::spawn_blocking(move || {
+            let folder = PathBuf::from(path);
+            let mut status = git::get_status(&folder);
+            set_changed_note_ids(&folder, &mut status.changed_files, &ignored_dirs);
+            status
+        })
+        .await
+        .map_err(|e| e.to_string()),
         None => Ok(git::GitStatus::default()),
     }
 }
 
+/// Fill in the note IDs of changed files that are notes.
+fn set_changed_note_ids(folder: &Path, files: &mut [git::ChangedFile], ignored_dirs: &[String]) {
+    for file in files {
+        file.id = id_from_abs_path(folder, &folder.join(&file.path), ignored_dirs);
+    }
+}
+
+/// Run a git operation on the blocking pool, holding the git lock so it doesn't
+/// interleave with auto-sync or another command.
+async fn spawn_git<T: Send + 'static>(
+    state: &AppState,
+    operation: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, String> {
+    let git_lock = Arc::clone(&state.git_lock);
+    tauri::async_runtime::spawn_blocking(move || {
+        let _git = git_lock.lock().expect("git mutex");
+        operation()
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn git_init_repo(state: State<'_, AppState>) -> Result<(), String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
-        app_config.notes_folder.clone().ok_or("Notes folder not set")?
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
     };
 
-    tauri::async_runtime::spawn_blocking(move || {
-        git::git_init(&PathBuf::from(folder))
-    })
-    .await
-    .map_err(|e| e.to_string())?
+    tauri::async_runtime::spawn_blocking(move || git::git_init(&PathBuf::from(folder)))
+        .await
+        .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
@@ -2570,11 +4789,10 @@ async fn git_commit(message: String, state: State<'_, AppState>) -> Result<git::
 
     match folder {
         Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
+            spawn_git(&state, move || {
                 git::commit_all(&PathBuf::from(path), &message)
             })
             .await
-            .map_err(|e| e.to_string())
         }
         None => Ok(git::GitResult {
             success: false,
@@ -2592,13 +4810,7 @@ async fn git_push(state: State<'_, AppState>) -> Result<git::GitResult, String>
     };
 
     match folder {
-        Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
-                git::push(&PathBuf::from(path))
-            })
-            .await
-            .map_err(|e| e.to_string())
-        }
+        Some(path) => spawn_git(&state, move || git::push(&PathBuf::from(path))).await,
         None => Ok(git::GitResult {
             success: false,
             message: None,
@@ -2615,13 +4827,7 @@ async fn git_fetch(state: State<'_, AppState>) -> Result<git::GitResult, String>
     };
 
     match folder {
-        Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
-                git::fetch(&PathBuf::from(path))
-            })
-            .await
-            .map_err(|e| e.to_string())
-        }
+        Some(path) => spawn_git(&state, move || git::fetch(&PathBuf::from(path))).await,
         None => Ok(git::GitResult {
             success: false,
             message: None,
@@ -2638,13 +4844,7 @@ async fn git_pull(state: State<'_, AppState>) -> Result<git::GitResult, String>
     };
 
     match folder {
-        Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
-                git::pull(&PathBuf::from(path))
-            })
-            .await
-            .map_err(|e| e.to_string())
-        }
+        Some(path) => spawn_git(&state, move || git::pull(&PathBuf::from(path))).await,
         None => Ok(git::GitResult {
             success: false,
             message: None,
@@ -2661,13 +4861,11 @@ async fn git_add_remote(url: String, state: State<'_, AppState>) -> Result<git::
     };
 
     match folder {
-        Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
-                git::add_remote(&PathBuf::from(path), &url)
-            })
-            .await
-            .map_err(|e| e.to_string())
-        }
+        Some(path) => tauri::async_runtime::spawn_blocking(move || {
+            git::add_remote(&PathBuf::from(path), &url)
+        })
+        .await
+        .map_err(|e| e.to_string()),
         None => Ok(git::GitResult {
             success: false,
             message: None,
@@ -2677,20 +4875,21 @@ async fn git_add_remote(url: String, state: State<'_, AppState>) -> Result<git::
 }
 
 #[tauri::command]
-async fn git_set_remote_url(url: String, state: State<'_, AppState>) -> Result<git::GitResult, String> {
+async fn git_set_remote_url(
+    url: String,
+    state: State<'_, AppState>,
+) -> Result<git::GitResult, String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
         app_config.notes_folder.clone()
     };
 
     match folder {
-        Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
-                git::set_remote_url(&PathBuf::from(path), &url)
-            })
-            .await
-            .map_err(|e| e.to_string())
-        }
+        Some(path) => tauri::async_runtime::spawn_blocking(move || {
+            git::set_remote_url(&PathBuf::from(path), &url)
+        })
+        .await
+        .map_err(|e| e.to_string()),
         None => Ok(git::GitResult {
             success: false,
             message: None,
@@ -2708,11 +4907,9 @@ async fn git_remove_remote(state: State<'_, AppState>) -> Result<git::GitResult,
 
     match folder {
         Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
-                git::remove_remote(&PathBuf::from(path))
-            })
-            .await
-            .map_err(|e| e.to_string())
+            tauri::async_runtime::spawn_blocking(move || git::remove_remote(&PathBuf::from(path)))
+                .await
+                .map_err(|e| e.to_string())
         }
         None => Ok(git::GitResult {
             success: false,
@@ -2731,15 +4928,14 @@ async fn git_push_with_upstream(state: State<'_, AppState>) -> Result<git::GitRe
 
     match folder {
         Some(path) => {
-            tauri::async_runtime::spawn_blocking(move || {
+            spawn_git(&state, move || {
                 // Get current branch first
                 let status = git::get_status(&PathBuf::from(&path));
                 match status.current_branch {
                     Some(branch) => {
-                        if !branch
-                            .chars()
-                            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.'))
-                        {
+                        if !branch.chars().all(|c| {
+                            c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.')
+                        }) {
                             return git::GitResult {
                                 success: false,
                                 message: None,
@@ -2756,7 +4952,6 @@ async fn git_push_with_upstream(state: State<'_, AppState>) -> Result<git::GitRe
                 }
             })
             .await
-            .map_err(|e| e.to_string())
         }
         None => Ok(git::GitResult {
             success: false,
@@ -2766,6 +4961,327 @@ async fn git_push_with_upstream(state: State<'_, AppState>) -> Result<git::GitRe
     }
 }
 
+/// The notes folder, provided it's a git repository.
+fn git_notes_folder(state: &AppState) -> Result<PathBuf, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
+    };
+    let folder = PathBuf::from(folder);
+    if !git::is_git_repo(&folder) {
+        return Err("Notes folder is not a git repository".to_string());
+    }
+    Ok(folder)
+}
+
+/// A note's file path relative to the repository root.
+fn git_note_file(folder: &Path, id: &str) -> Result<String, String> {
+    abs_path_from_id(folder, id)?;
+    Ok(format!("{}.md", id))
+}
+
+/// Commits that changed a note, newest first, including ones from before it
+/// was renamed or moved.
+#[tauri::command]
+async fn git_log_note(
+    id: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<git::FileCommit>, String> {
+    let folder = git_notes_folder(&state)?;
+    let file = git_note_file(&folder, &id)?;
+
+    tauri::async_runtime::spawn_blocking(move || git::log_file(&folder, &file))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// A note's content as of a commit.
+#[tauri::command]
+async fn git_show_note_at(
+    id: String,
+    rev: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let folder = git_notes_folder(&state)?;
+    let file = git_note_file(&folder, &id)?;
+
+    tauri::async_runtime::spawn_blocking(move || git::show_file_at(&folder, &file, &rev))
+        .await
+        .map_err(|e| e.to_string())??
+        .ok_or_else(|| "The note didn't exist at this revision".to_string())
+}
+
+/// Line diff of a note between two commits, or between a commit and the
+/// note as it is now when `to` is omitted. A side where the note didn't
+/// exist counts as empty.
+#[tauri::command]
+async fn git_diff_note(
+    id: String,
+    from: String,
+    to: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<git::FileDiff, String> {
+    let folder = git_notes_folder(&state)?;
+    let file = git_note_file(&folder, &id)?;
+    let file_path = abs_path_from_id(&folder, &id)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let old = git::show_file_at(&folder, &file, &from)?.unwrap_or_default();
+        let new = match to {
+            Some(to) => git::show_file_at(&folder, &file, &to)?.unwrap_or_default(),
+            None => std::fs::read_to_string(&file_path).unwrap_or_default(),
+        };
+        git::diff_contents(&old, &new)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Write a note's content from a commit back to its file. The current content
+/// is kept in version history first.
+#[tauri::command]
+async fn git_restore_note(
+    id: String,
+    rev: String,
+    state: State<'_, AppState>,
+) -> Result<Note, String> {
+    let folder = git_notes_folder(&state)?;
+    let file = git_note_file(&folder, &id)?;
+    let file_path = abs_path_from_id(&folder, &id)?;
+
+    let content = {
+        let folder = folder.clone();
+        tauri::async_runtime::spawn_blocking(move || git::show_file_at(&folder, &file, &rev))
+            .await
+            .map_err(|e| e.to_string())??
+            .ok_or("The note didn't exist at this revision")?
+    };
+
+    if let Ok(current) = fs::read_to_string(&file_path).await {
+        let policy = {
+            let settings = state.settings.read().expect("settings read lock");
+            get_history_policy(&settings)
+        };
+        let _ = history::snapshot(
+            &folder,
+            &id,
+            &current,
+            history::SnapshotSource::Restore,
+            &policy,
+            false,
+        );
+    }
+
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    fsutil::write_atomic_async(&file_path, content.as_str())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    reindex_rewritten_notes(&state, &folder, &[(id.clone(), content.clone())]);
+
+    let (modified, modified_ns) = modified_times(&file_path).await;
+
+    Ok(Note {
+        id,
+        title: extract_title(&content),
+        content,
+        path: file_path.to_string_lossy().into_owned(),
+        modified,
+        modified_ns,
+    })
+}
+
+/// A note with unresolved merge conflicts.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConflictedNote {
+    id: String,
+    ours: Option<String>, // None where the note was deleted
+    theirs: Option<String>,
+    base: Option<String>,
+}
+
+/// How to resolve a conflicted note: keep our or their version (which may be
+/// a deletion), or save content the user merged.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum ConflictResolution {
+    Ours,
+    Theirs,
+    Merged { content: String },
+}
+
+#[tauri::command]
+async fn git_list_conflicts(state: State<'_, AppState>) -> Result<Vec<ConflictedNote>, String> {
+    let folder = git_notes_folder(&state)?;
+    let ignored_dirs = {
+        let settings = state.settings.read().expect("settings read lock");
+        get_effective_ignored_dirs(&settings)
+    };
+
+    let files = {
+        let folder = folder.clone();
+        tauri::async_runtime::spawn_blocking(move || git::list_conflicts(&folder))
+            .await
+            .map_err(|e| e.to_string())??
+    };
+    Ok(files
+        .into_iter()
+        .filter_map(|file| {
+            let id = id_from_abs_path(&folder, &folder.join(&file.path), &ignored_dirs)?;
+            Some(ConflictedNote {
+                id,
+                ours: file.ours,
+                theirs: file.theirs,
+                base: file.base,
+            })
+        })
+        .collect())
+}
+
+#[tauri::command]
+async fn git_resolve_conflict(
+    id: String,
+    resolution: ConflictResolution,
+    state: State<'_, AppState>,
+) -> Result<git::GitResult, String> {
+    let folder = git_notes_folder(&state)?;
+    let file = git_note_file(&folder, &id)?;
+    let file_path = abs_path_from_id(&folder, &id)?;
+
+    let (content, remaining) = {
+        let folder = folder.clone();
+        spawn_git(&state, move || -> Result<_, String> {
+            if !git::conflicted_paths(&folder)?.contains(&file) {
+                return Err("Note has no unresolved conflicts".to_string());
+            }
+            let content = match resolution {
+                // `None` means the file was deleted on that side, so it goes
+                ConflictResolution::Ours => {
+                    git::conflict_side(&folder, &file, git::ConflictSide::Ours)?
+                }
+                ConflictResolution::Theirs => {
+                    git::conflict_side(&folder, &file, git::ConflictSide::Theirs)?
+                }
+                ConflictResolution::Merged { content } => Some(content),
+            };
+            match &content {
+                Some(content) => {
+                    if let Some(parent) = file_path.parent() {
+                        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                    }
+                    fsutil::write_atomic(&file_path, content.as_bytes())
+                        .map_err(|e| e.to_string())?;
+                }
+                None => match std::fs::remove_file(&file_path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.to_string()),
+                    _ => {}
+                },
+            }
+            git::mark_resolved(&folder, &file)?;
+            Ok((content, git::conflicted_paths(&folder)?.len()))
+        })
+        .await??
+    };
+
+    // Deletions reach the indexes through the file watcher
+    if let Some(content) = content {
+        reindex_rewritten_notes(&state, &folder, &[(id, content)]);
+    }
+
+    Ok(git::GitResult {
+        success: true,
+        message: Some(if remaining == 0 {
+            "All conflicts resolved. Commit to finish the merge.".to_string()
+        } else {
+            format!("Conflict resolved, {} left", remaining)
+        }),
+        error: None,
+    })
+}
+
+#[tauri::command]
+async fn git_abort_merge(state: State<'_, AppState>) -> Result<git::GitResult, String> {
+    let folder = {
+        let app_config = state.app_config.read().expect("app_config read lock");
+        app_config.notes_folder.clone()
+    };
+
+    match folder {
+        Some(path) => spawn_git(&state, move || git::abort_merge(&PathBuf::from(path))).await,
+        None => Ok(git::GitResult {
+            success: false,
+            message: None,
+            error: Some("Notes folder not set".to_string()),
+        }),
+    }
+}
+
+#[tauri::command]
+fn get_git_sync_status(state: State<AppState>) -> git_sync::SyncStatus {
+    state.git_sync.status()
+}
+
+/// Commit, pull and push now instead of waiting for the auto-sync schedule.
+#[tauri::command]
+fn git_sync_now(state: State<AppState>) {
+    state.git_sync.sync_now();
+}
+
+/// Commit only the changes to the given notes, along with changed assets
+/// (images, attachments) they reference. Other changes stay uncommitted.
+#[tauri::command]
+async fn git_commit_paths(
+    ids: Vec<String>,
+    message: String,
+    state: State<'_, AppState>,
+) -> Result<git::GitResult, String> {
+    let folder = git_notes_folder(&state)?;
+    for id in &ids {
+        abs_path_from_id(&folder, id)?;
+    }
+    let ignored_dirs = {
+        let settings = state.settings.read().expect("settings read lock");
+        get_effective_ignored_dirs(&settings)
+    };
+
+    spawn_git(&state, move || {
+        let mut changes = git::changed_files(&folder)?;
+        set_changed_note_ids(&folder, &mut changes, &ignored_dirs);
+
+        let selected: HashSet<&str> = ids.iter().map(String::as_str).collect();
+        // Files the selected notes link to or embed, relative to the repository root
+        let referenced: HashSet<String> = ids
+            .iter()
+            .filter_map(|id| {
+                let content = std::fs::read_to_string(abs_path_from_id(&folder, id).ok()?).ok()?;
+                Some(links::linked_file_paths(id, &content, &folder))
+            })
+            .flatten()
+            .collect();
+
+        let files: Vec<git::ChangedFile> = changes
+            .into_iter()
+            .filter(|change| match &change.id {
+                Some(id) => selected.contains(id.as_str()),
+                None => referenced.contains(&change.path),
+            })
+            .collect();
+        if files.is_empty() {
+            return Err("The selected notes have no changes".to_string());
+        }
+        Ok(git::commit_files(&folder, &files, &message))
+    })
+    .await?
+}
+
 // Check if Claude CLI is installed
 fn get_expanded_path() -> String {
     let system_path = std::env::var("PATH").unwrap_or_default();
@@ -2871,26 +5387,42 @@ fn cli_target_path() -> PathBuf {
 #[tauri::command]
 fn get_cli_status() -> Result<CliStatus, String> {
     #[cfg(not(target_os = "macos"))]
-    return Ok(CliStatus { supported: false, installed: false, path: None });
+    return Ok(CliStatus {
+        supported: false,
+        installed: false,
+        path: None,
+    });
 
     #[cfg(target_os = "macos")]
     {
         let target = cli_target_path();
         if !target.exists() && target.symlink_metadata().is_err() {
-            return Ok(CliStatus { supported: true, installed: false, path: None });
+            return Ok(CliStatus {
+                supported: true,
+                installed: false,
+                path: None,
+            });
         }
         // Verify this is our wrapper (has marker) and points to the current binary
         let content = std::fs::read_to_string(&target).unwrap_or_default();
         if !content.contains(SCRATCH_CLI_MARKER) {
             // Foreign binary at this path — don't claim it as ours
-            return Ok(CliStatus { supported: true, installed: false, path: None });
+            return Ok(CliStatus {
+                supported: true,
+                installed: false,
+                path: None,
+            });
         }
         let current_exe = std::env::current_exe()
             .map(|p| p.to_string_lossy().into_owned())
             .unwrap_or_default();
         if !current_exe.is_empty() && !content.contains(&current_exe) {
             // Our wrapper but points to a moved/deleted binary — needs reinstall
-            return Ok(CliStatus { supported: true, installed: false, path: None });
+            return Ok(CliStatus {
+                supported: true,
+                installed: false,
+                path: None,
+            });
         }
         Ok(CliStatus {
             supported: true,
@@ -2929,8 +5461,8 @@ fn install_cli() -> Result<String, String> {
                 .map_err(|e| format!("Failed to remove existing file: {}", e))?;
         }
 
-        let exe_path = std::env::current_exe()
-            .map_err(|e| format!("Cannot find exe path: {}", e))?;
+        let exe_path =
+            std::env::current_exe().map_err(|e| format!("Cannot find exe path: {}", e))?;
 
         // Shell-escape the exe path using single quotes to prevent
         // interpretation of $, `, ", and other metacharacters.
@@ -2941,8 +5473,7 @@ fn install_cli() -> Result<String, String> {
         // the terminal is not blocked waiting for the GUI app to exit.
         let script = format!(
             "#!/bin/sh\n{}\nnohup {} \"$@\" >/dev/null 2>&1 &\n",
-            SCRATCH_CLI_MARKER,
-            escaped_exe
+            SCRATCH_CLI_MARKER, escaped_exe
         );
         std::fs::write(&target, script.as_bytes())
             .map_err(|e| format!("Failed to write CLI script: {}", e))?;
@@ -3230,7 +5761,10 @@ async fn ai_execute_claude(
 ) -> Result<AiExecutionResult, String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
-        app_config.notes_folder.clone().ok_or("Notes folder not set")?
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
     };
     let path = PathBuf::from(&file_path);
     let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
@@ -3247,6 +5781,8 @@ async fn ai_execute_claude(
         return Err("File must be within notes folder".to_string());
     }
 
+    snapshot_before_ai_edit(&state, &canonical);
+
     execute_ai_cli(
         "Claude",
         "claude".to_string(),
@@ -3264,7 +5800,13 @@ async fn ai_execute_claude(
 }
 
 #[tauri::command]
-async fn ai_execute_codex(file_path: String, prompt: String) -> Result<AiExecutionResult, String> {
+async fn ai_execute_codex(
+    file_path: String,
+    prompt: String,
+    state: State<'_, AppState>,
+) -> Result<AiExecutionResult, String> {
+    snapshot_before_ai_edit(&state, Path::new(&file_path));
+
     let stdin_input = format!(
         "Edit only this markdown file: {file_path}\n\
          Apply the user's instructions below directly to that file.\n\
@@ -3298,7 +5840,10 @@ async fn ai_execute_opencode(
 ) -> Result<AiExecutionResult, String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
-        app_config.notes_folder.clone().ok_or("Notes folder not set")?
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
     };
     let path = PathBuf::from(&file_path);
     let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
@@ -3323,6 +5868,8 @@ async fn ai_execute_opencode(
         prompt
     );
 
+    snapshot_before_ai_edit(&state, &canonical);
+
     execute_ai_cli(
         "OpenCode",
         "opencode".to_string(),
@@ -3365,7 +5912,10 @@ async fn ai_execute_ollama(
 ) -> Result<AiExecutionResult, String> {
     let folder = {
         let app_config = state.app_config.read().expect("app_config read lock");
-        app_config.notes_folder.clone().ok_or("Notes folder not set")?
+        app_config
+            .notes_folder
+            .clone()
+            .ok_or("Notes folder not set")?
     };
     let path = PathBuf::from(&file_path);
     let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
@@ -3467,7 +6017,10 @@ async fn ai_execute_ollama(
                 return Ok(AiExecutionResult {
                     success: false,
                     output: String::new(),
-                    error: Some("Authentication required. Run `ollama login` in your terminal to sign in.".to_string()),
+                    error: Some(
+                        "Authentication required. Run `ollama login` in your terminal to sign in."
+                            .to_string(),
+                    ),
                 });
             }
         }
@@ -3483,7 +6036,8 @@ async fn ai_execute_ollama(
                 error: Some("Ollama returned empty output. Please try again.".to_string()),
             });
         }
-        tokio::fs::write(&canonical, edited_content.as_bytes())
+        snapshot_before_ai_edit(&state, &canonical);
+        fsutil::write_atomic_async(&canonical, edited_content)
             .await
             .map_err(|e| format!("Failed to write edited file: {}", e))?;
 
@@ -3738,7 +6292,10 @@ pub fn run() {
                     Ok(normalized) => {
                         // Path is structurally valid but not currently a directory
                         // (e.g., unmounted drive). Preserve the user's preference.
-                        eprintln!("Notes folder not found (may be temporarily unavailable): {:?}", normalized);
+                        eprintln!(
+                            "Notes folder not found (may be temporarily unavailable): {:?}",
+                            normalized
+                        );
                     }
                     Err(_) => {
                         app_config.notes_folder = None;
@@ -3756,30 +6313,52 @@ pub fn run() {
 
             // Initialize search index if notes folder is set
             let ignored_dirs = get_effective_ignored_dirs(&settings);
-            let search_index = if let Some(ref folder) = app_config.notes_folder {
-                if let Ok(index_path) = get_search_index_path(app.handle()) {
-                    SearchIndex::new(&index_path).ok().inspect(|idx| {
-                        let _ = idx.rebuild_index(&PathBuf::from(folder), &ignored_dirs);
-                    })
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
+            let search_index = app_config.notes_folder.as_ref().and_then(|folder| {
+                open_search_index(app.handle(), Path::new(folder), &settings).ok()
+            });
+
+            // Purge expired trash
+            if let Some(ref folder) = app_config.notes_folder {
+                trash::purge_expired(&PathBuf::from(folder), get_trash_retention_days(&settings));
+            }
+            let notes_folder = app_config.notes_folder.clone();
 
             let state = AppState {
                 app_config: RwLock::new(app_config),
                 settings: RwLock::new(settings),
                 notes_cache: RwLock::new(HashMap::new()),
+                tag_index: RwLock::new(tags::TagIndex::default()),
                 file_watcher: Mutex::new(None),
                 search_index: Mutex::new(search_index),
+                index_jobs: Mutex::new(None),
+                link_index: RwLock::new(links::LinkIndex::default()),
+                saved_search_ids: Mutex::new(HashMap::new()),
+                embeddings: Mutex::new(None),
+                embedding_jobs: Mutex::new(None),
+                metadata_cache: Mutex::new(metadata_cache::MetadataCache::default()),
+                git_sync: git_sync::Scheduler::default(),
+                git_lock: Arc::new(Mutex::new(())),
                 debounce_map: Arc::new(Mutex::new(HashMap::new())),
             };
             app.manage(state);
+            let _ =
+                indexer::queue_for_current_folder(app.handle(), &app.state::<AppState>(), false);
+            git_sync::start(app.handle());
+
+            // Note metadata and the link and tag indexes, from the cache (reading changed notes)
+            if let Some(ref folder) = notes_folder {
+                spawn_note_metadata_reload(app.handle(), PathBuf::from(folder), ignored_dirs);
+            }
 
             // Add notes folder to asset protocol scope so images can be served
-            if let Some(ref folder) = app.state::<AppState>().app_config.read().expect("app_config read lock").notes_folder.clone() {
+            if let Some(ref folder) = app
+                .state::<AppState>()
+                .app_config
+                .read()
+                .expect("app_config read lock")
+                .notes_folder
+                .clone()
+            {
                 let _ = app.asset_protocol_scope().allow_directory(folder, true);
             }
 
@@ -3841,7 +6420,10 @@ pub fn run() {
             get_notes_folder,
             set_notes_folder,
             list_notes,
+            reorder_notes,
+            reorder_pinned_notes,
             read_note,
+            update_note_frontmatter,
             save_note,
             delete_note,
             create_note,
@@ -3851,12 +6433,30 @@ pub fn run() {
             rename_folder,
             move_note,
             move_folder,
+            list_trash,
+            restore_from_trash,
+            empty_trash,
+            list_note_versions,
+            read_note_version,
+            restore_note_version,
             get_settings,
             update_settings,
             update_git_enabled,
             preview_note_name,
             write_file,
             search_notes,
+            list_saved_searches,
+            run_saved_search,
+            semantic_search,
+            related_notes,
+            get_backlinks,
+            get_outgoing_links,
+            list_tags,
+            list_notes_by_tag,
+            rename_tag,
+            find_in_vault,
+            replace_in_vault,
+            undo_replace_in_vault,
             start_file_watcher,
             rebuild_search_index,
             get_default_ignored_patterns,
@@ -3877,6 +6477,16 @@ pub fn run() {
             git_set_remote_url,
             git_remove_remote,
             git_push_with_upstream,
+            git_log_note,
+            git_show_note_at,
+            git_diff_note,
+            git_restore_note,
+            git_list_conflicts,
+            git_resolve_conflict,
+            git_abort_merge,
+            get_git_sync_status,
+            git_sync_now,
+            git_commit_paths,
             ai_check_claude_cli,
             ai_check_codex_cli,
             ai_check_opencode_cli,
@@ -3899,16 +6509,26 @@ pub fn run() {
 
     // Use .run() callback to handle macOS "Open With" file events
     // RunEvent::Opened is macOS-only in Tauri v2
-    app.run(|_app_handle, _event| {
+    app.run(|app_handle, event| {
+        // Write out metadata changes not saved yet
+        if let tauri::RunEvent::Exit = event {
+            let state = app_handle.state::<AppState>();
+            let _ = state
+                .metadata_cache
+                .lock()
+                .expect("metadata cache mutex")
+                .save(true);
+        }
+
         #[cfg(target_os = "macos")]
-        if let tauri::RunEvent::Opened { urls } = _event {
+        if let tauri::RunEvent::Opened { urls } = event {
             for url in urls {
                 if let Ok(path) = url.to_file_path() {
                     if is_markdown_extension(&path)
                         && path.is_file()
-                        && !try_select_in_notes_folder(_app_handle, &path)
+                        && !try_select_in_notes_folder(app_handle, &path)
                     {
-                        let _ = create_preview_window(_app_handle, &path.to_string_lossy());
+                        let _ = create_preview_window(app_handle, &path.to_string_lossy());
                     }
                 }
             }
@@ -3944,8 +6564,7 @@ mod windows_title_bar {
 
         // Windows COLORREF is little-endian 0x00BBGGRR
         let (r, g, b) = rgb;
-        let caption_color: u32 =
-            ((b as u32) << 16) | ((g as u32) << 8) | (r as u32);
+        let caption_color: u32 = ((b as u32) << 16) | ((g as u32) << 8) | (r as u32);
 
         unsafe {
             let set_attr = |attr: u32, value: *const std::ffi::c_void, size: u32| {
@@ -3973,13 +6592,7 @@ mod windows_title_bar {
 }
 
 #[tauri::command]
-fn set_title_bar_theme(
-    app: AppHandle,
-    is_dark: bool,
-    r: u8,
-    g: u8,
-    b: u8,
-) -> Result<(), String> {
+fn set_title_bar_theme(app: AppHandle, is_dark: bool, r: u8, g: u8, b: u8) -> Result<(), String> {
     #[cfg(target_os = "windows")]
     {
         for (label, window) in app.webview_windows() {
@@ -0,0 +1,904 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::frontmatter;
+use crate::{abs_path_from_id, extract_title, id_from_abs_path, is_visible_notes_entry};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    Wikilink,
+    Markdown,
+}
+
+/// A link found in a note body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Link {
+    kind: LinkKind,
+    /// Target as written (wikilink text without alias/heading, or the markdown href)
+    target: String,
+    /// Normalized lookup key: lowercased wikilink target, or lowercased resolved note ID
+    key: String,
+    /// Trimmed source line, shown as context in the backlinks panel
+    context: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutgoingLink {
+    pub kind: LinkKind,
+    pub target: String,
+    pub target_id: Option<String>, // None if the link points to a note that doesn't exist
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backlink {
+    pub id: String,
+    pub title: String,
+    pub kind: LinkKind,
+    pub context: String,
+}
+
+/// A note whose ID (and possibly title) changed through a rename or move.
+#[derive(Debug, Clone)]
+pub struct NoteRename {
+    pub old_id: String,
+    pub new_id: String,
+    pub new_title: Option<String>, // Set when the title changed as well
+}
+
+struct IndexedNote {
+    title: String,
+    aliases: Vec<String>, // Frontmatter `aliases:`
+    links: Vec<Link>,
+}
+
+impl IndexedNote {
+    /// Keys this note resolves under: `target_keys` plus its aliases.
+    fn keys(&self, id: &str) -> Vec<String> {
+        let mut keys = target_keys(id, &self.title);
+        for alias in &self.aliases {
+            let alias = alias.to_lowercase();
+            if !keys.contains(&alias) {
+                keys.push(alias);
+            }
+        }
+        keys
+    }
+}
+
+/// In-memory index of wikilinks and relative markdown links between notes.
+/// Links are stored unresolved (by key) so that creating or renaming a note
+/// immediately changes what existing links point to, without re-reading sources.
+#[derive(Default)]
+pub struct LinkIndex {
+    notes: HashMap<String, IndexedNote>,
+    // link key -> source note IDs containing a link with that key
+    sources_by_key: HashMap<String, HashSet<String>>,
+    // lowercased ID / filename / title -> note IDs that answer to it
+    notes_by_key: HashMap<String, BTreeSet<String>>,
+}
+
+impl LinkIndex {
+    /// Re-extract links for a note from its current content.
+    pub fn update_note(&mut self, id: &str, content: &str) {
+        let title = extract_title(content);
+        let aliases = frontmatter::string_list(&frontmatter::parse(content), "aliases");
+        self.set_note(id, title, aliases, extract_links(id, content));
+    }
+
+    /// Index a note from already extracted parts (e.g. the metadata cache).
+    pub fn set_note(&mut self, id: &str, title: String, aliases: Vec<String>, links: Vec<Link>) {
+        self.remove_note(id);
+
+        for link in &links {
+            self.sources_by_key
+                .entry(link.key.clone())
+                .or_default()
+                .insert(id.to_string());
+        }
+        let note = IndexedNote {
+            title,
+            aliases,
+            links,
+        };
+        for key in note.keys(id) {
+            self.notes_by_key
+                .entry(key)
+                .or_default()
+                .insert(id.to_string());
+        }
+
+        self.notes.insert(id.to_string(), note);
+    }
+
+    pub fn remove_note(&mut self, id: &str) {
+        let Some(note) = self.notes.remove(id) else {
+            return;
+        };
+
+        for link in &note.links {
+            if let Some(sources) = self.sources_by_key.get_mut(&link.key) {
+                sources.remove(id);
+                if sources.is_empty() {
+                    self.sources_by_key.remove(&link.key);
+                }
+            }
+        }
+        for key in note.keys(id) {
+            if let Some(ids) = self.notes_by_key.get_mut(&key) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.notes_by_key.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Re-read a note from disk, dropping it from the index if the file is gone.
+    pub fn reindex_from_disk(&mut self, notes_root: &Path, id: &str) {
+        match abs_path_from_id(notes_root, id).map(std::fs::read_to_string) {
+            Ok(Ok(content)) => self.update_note(id, &content),
+            _ => self.remove_note(id),
+        }
+    }
+
+    /// Remove every note whose ID starts with `prefix` (e.g. "folder/").
+    pub fn remove_prefix(&mut self, prefix: &str) -> Vec<String> {
+        let ids = self.ids_with_prefix(prefix);
+        for id in &ids {
+            self.remove_note(id);
+        }
+        ids
+    }
+
+    pub fn rebuild(&mut self, notes_folder: &Path, ignored_dirs: &[String]) {
+        *self = Self::default();
+
+        if !notes_folder.exists() {
+            return;
+        }
+
+        use walkdir::WalkDir;
+        for entry in WalkDir::new(notes_folder)
+            .max_depth(10)
+            .into_iter()
+            .filter_entry(|e| is_visible_notes_entry(e, ignored_dirs))
+            .flatten()
+        {
+            let file_path = entry.path();
+            if !file_path.is_file() {
+                continue;
+            }
+            if let Some(id) = id_from_abs_path(notes_folder, file_path, ignored_dirs) {
+                if let Ok(content) = std::fs::read_to_string(file_path) {
+                    self.update_note(&id, &content);
+                }
+            }
+        }
+    }
+
+    pub fn title(&self, id: &str) -> Option<&str> {
+        self.notes.get(id).map(|note| note.title.as_str())
+    }
+
+    /// IDs of all indexed notes under a folder prefix (e.g. "folder/").
+    pub fn ids_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.notes
+            .keys()
+            .filter(|id| id.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Resolve a link key to a note ID. An exact ID match wins over a
+    /// title or filename match; ties are broken alphabetically.
+    fn resolve(&self, key: &str) -> Option<&String> {
+        let ids = self.notes_by_key.get(key)?;
+        ids.iter()
+            .find(|id| id.to_lowercase() == key)
+            .or_else(|| ids.iter().next())
+    }
+
+    pub fn outgoing_links(&self, id: &str) -> Vec<OutgoingLink> {
+        let Some(note) = self.notes.get(id) else {
+            return Vec::new();
+        };
+
+        let mut seen = HashSet::new();
+        note.links
+            .iter()
+            .filter(|link| seen.insert((link.kind, link.key.clone())))
+            .map(|link| OutgoingLink {
+                kind: link.kind,
+                target: link.target.clone(),
+                target_id: self.resolve(&link.key).cloned(),
+            })
+            .collect()
+    }
+
+    pub fn backlinks(&self, id: &str) -> Vec<Backlink> {
+        let Some(note) = self.notes.get(id) else {
+            return Vec::new();
+        };
+
+        let mut sources: BTreeSet<&String> = BTreeSet::new();
+        for key in note.keys(id) {
+            if let Some(ids) = self.sources_by_key.get(&key) {
+                sources.extend(ids.iter());
+            }
+        }
+
+        let mut results = Vec::new();
+        for source_id in sources {
+            if source_id == id {
+                continue;
+            }
+            let Some(source) = self.notes.get(source_id) else {
+                continue;
+            };
+            // A title can be shared by several notes, so only count links
+            // that actually resolve to this one.
+            if let Some(link) = source
+                .links
+                .iter()
+                .find(|link| self.resolve(&link.key).map(String::as_str) == Some(id))
+            {
+                results.push(Backlink {
+                    id: source_id.clone(),
+                    title: source.title.clone(),
+                    kind: link.kind,
+                    context: link.context.clone(),
+                });
+            }
+        }
+        results
+    }
+
+    /// Notes whose content may need rewriting after `renames`: every note linking
+    /// to a renamed note, plus the renamed notes themselves (their relative links
+    /// move with them). Returns pre-rename IDs.
+    pub fn rewrite_candidates(&self, renames: &[NoteRename]) -> Vec<String> {
+        let mut candidates: BTreeSet<String> = BTreeSet::new();
+        for rename in renames {
+            let title = self.title(&rename.old_id).unwrap_or("");
+            for key in target_keys(&rename.old_id, title) {
+                if let Some(ids) = self.sources_by_key.get(&key) {
+                    candidates.extend(ids.iter().cloned());
+                }
+            }
+            if self.notes.get(&rename.old_id).is_some_and(|note| {
+                note.links
+                    .iter()
+                    .any(|link| link.kind == LinkKind::Markdown)
+            }) {
+                candidates.insert(rename.old_id.clone());
+            }
+        }
+        candidates.into_iter().collect()
+    }
+
+    /// Rewrite links in `content` (the current text of the note that was
+    /// `source_id` before the renames) so they keep pointing at the same notes.
+    /// Must be called before the index itself is updated for the renames.
+    /// Returns None if nothing changed.
+    pub fn rewrite_links(
+        &self,
+        source_id: &str,
+        content: &str,
+        renames: &[NoteRename],
+    ) -> Option<String> {
+        let renamed: HashMap<&str, &NoteRename> =
+            renames.iter().map(|r| (r.old_id.as_str(), r)).collect();
+        let source_new_id = renamed
+            .get(source_id)
+            .map(|r| r.new_id.as_str())
+            .unwrap_or(source_id);
+
+        let mut output = String::with_capacity(content.len());
+        let mut changed = false;
+        let mut in_fence = false;
+
+        for raw_line in content.split_inclusive('\n') {
+            let line = raw_line.trim_end_matches(['\n', '\r']);
+            let line_ending = &raw_line[line.len()..];
+
+            if is_code_fence(line) {
+                in_fence = !in_fence;
+            }
+            if in_fence || is_code_fence(line) {
+                output.push_str(raw_line);
+                continue;
+            }
+
+            let masked = mask_inline_code(line);
+            let mut edits: Vec<(std::ops::Range<usize>, String)> = Vec::new();
+
+            for cap in wikilink_re().captures_iter(&masked) {
+                let inner = cap.get(1).unwrap();
+                let target_len = inner
+                    .as_str()
+                    .find(['|', '#'])
+                    .unwrap_or(inner.as_str().len());
+                let target = line[inner.start()..inner.start() + target_len].trim();
+                if let Some(replacement) = self.rewrite_wikilink_target(target, &renamed) {
+                    edits.push((inner.start()..inner.start() + target_len, replacement));
+                }
+            }
+
+            for cap in markdown_link_re().captures_iter(&masked) {
+                if cap[0].starts_with('!') {
+                    continue;
+                }
+                let href = cap.get(1).unwrap();
+                let original = &line[href.range()];
+                if let Some(replacement) =
+                    self.rewrite_markdown_href(source_id, source_new_id, original, &renamed)
+                {
+                    edits.push((href.range(), replacement));
+                }
+            }
+
+            if edits.is_empty() {
+                output.push_str(raw_line);
+                continue;
+            }
+
+            let mut new_line = line.to_string();
+            edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+            for (range, replacement) in edits {
+                new_line.replace_range(range, &replacement);
+            }
+            changed |= new_line != line;
+            output.push_str(&new_line);
+            output.push_str(line_ending);
+        }
+
+        changed.then_some(output)
+    }
+
+    /// New text for a wikilink target pointing at a renamed note, keeping the
+    /// form it was written in (full ID, filename or title).
+    fn rewrite_wikilink_target(
+        &self,
+        target: &str,
+        renamed: &HashMap<&str, &NoteRename>,
+    ) -> Option<String> {
+        let key = wikilink_key(target);
+        let old_id = self.resolve(&key)?;
+        let rename = renamed.get(old_id.as_str())?;
+
+        let old_leaf = old_id.rsplit('/').next().unwrap_or(old_id);
+        let replacement = if key == old_id.to_lowercase() {
+            rename.new_id.clone()
+        } else if key == old_leaf.to_lowercase() {
+            let new_leaf = rename.new_id.rsplit('/').next().unwrap_or(&rename.new_id);
+            new_leaf.to_string()
+        } else {
+            rename.new_title.clone()?
+        };
+
+        if replacement == target {
+            None
+        } else {
+            Some(replacement)
+        }
+    }
+
+    /// New href for a relative markdown link whose source or target moved.
+    fn rewrite_markdown_href(
+        &self,
+        source_id: &str,
+        source_new_id: &str,
+        href: &str,
+        renamed: &HashMap<&str, &NoteRename>,
+    ) -> Option<String> {
+        let path = markdown_href_path(href)?;
+        let target_id = resolve_relative_link(source_id, &path)?;
+        // Only rewrite links to notes we know about; leave dangling links untouched
+        let target_id = self.resolve(&target_id.to_lowercase())?;
+        let target_new_id = renamed
+            .get(target_id.as_str())
+            .map(|r| r.new_id.as_str())
+            .unwrap_or(target_id);
+
+        if source_new_id == source_id && target_new_id == target_id {
+            return None;
+        }
+
+        let new_path = if path.starts_with('/') {
+            format!("/{}.md", target_new_id)
+        } else {
+            relative_link_path(source_new_id, target_new_id)
+        };
+
+        // Split the href into path, #fragment and optional "title" trailer
+        let trimmed = href.trim();
+        let (angle, token, trailer) = if let Some(stripped) = trimmed.strip_prefix('<') {
+            let end = stripped.find('>').unwrap_or(stripped.len());
+            (
+                true,
+                &stripped[..end],
+                stripped.get(end + 1..).unwrap_or(""),
+            )
+        } else {
+            let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+            (false, &trimmed[..end], &trimmed[end..])
+        };
+        let fragment = token.find('#').map(|pos| &token[pos..]).unwrap_or("");
+
+        let encoded = if token.contains('%') {
+            new_path
+                .split('/')
+                .map(|segment| urlencoding::encode(segment).into_owned())
+                .collect::<Vec<_>>()
+                .join("/")
+        } else if !angle {
+            new_path.replace(' ', "%20")
+        } else {
+            new_path
+        };
+
+        Some(if angle {
+            format!("<{}{}>{}", encoded, fragment, trailer)
+        } else {
+            format!("{}{}{}", encoded, fragment, trailer)
+        })
+    }
+}
+
+/// Relative `.md` path from one note to another, e.g. "../other/note.md".
+fn relative_link_path(from_id: &str, to_id: &str) -> String {
+    let from_dirs: Vec<&str> = from_id.split('/').collect();
+    let from_dirs = &from_dirs[..from_dirs.len() - 1];
+    let to_parts: Vec<&str> = to_id.split('/').collect();
+
+    let common = from_dirs
+        .iter()
+        .zip(to_parts.iter())
+        .take_while(|(a, b)| a == b)
+        .count()
+        // Never consume the target's filename as a shared directory
+        .min(to_parts.len() - 1);
+
+    let mut parts: Vec<&str> = vec![".."; from_dirs.len() - common];
+    parts.extend(&to_parts[common..]);
+    format!("{}.md", parts.join("/"))
+}
+
+/// Keys a note can be linked by: its full ID, its filename and its title.
+fn target_keys(id: &str, title: &str) -> Vec<String> {
+    let mut keys = vec![id.to_lowercase()];
+    let leaf = id.rsplit('/').next().unwrap_or(id).to_lowercase();
+    if !keys.contains(&leaf) {
+        keys.push(leaf);
+    }
+    let title = title.trim().to_lowercase();
+    if !title.is_empty() && !keys.contains(&title) {
+        keys.push(title);
+    }
+    keys
+}
+
+fn wikilink_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    // Titles containing ']' are not supported, matching the editor's tokenizer
+    RE.get_or_init(|| regex::Regex::new(r"\[\[([^\]\n]+?)\]\]").unwrap())
+}
+
+fn markdown_link_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"!?\[[^\]\n]*\]\(([^)\n]+)\)").unwrap())
+}
+
+/// Split a wikilink body (`Target#Heading|Alias`) into its target part.
+pub(crate) fn wikilink_target(inner: &str) -> &str {
+    let target = inner.split('|').next().unwrap_or(inner);
+    let target = target.split('#').next().unwrap_or(target);
+    target.trim()
+}
+
+/// Normalize a wikilink target into a lookup key.
+fn wikilink_key(target: &str) -> String {
+    let target = target.trim().trim_start_matches('/');
+    let target = target.strip_suffix(".md").unwrap_or(target);
+    target.to_lowercase()
+}
+
+/// The destination of a markdown link href, without an optional link title
+/// (`[text](path "title")`) or angle brackets.
+fn href_destination(href: &str) -> &str {
+    let href = href.trim();
+    if let Some(stripped) = href.strip_prefix('<') {
+        stripped.split('>').next().unwrap_or(stripped)
+    } else {
+        href.split_whitespace().next().unwrap_or(href)
+    }
+}
+
+/// Decoded path of a relative href, or None for URLs and anchors.
+fn relative_href_path(href: &str) -> Option<String> {
+    let href = href_destination(href);
+    if href.contains("://") || href.starts_with("mailto:") || href.starts_with('#') {
+        return None;
+    }
+    let path = href.split('#').next().unwrap_or(href);
+    Some(urlencoding::decode(path).ok()?.into_owned())
+}
+
+/// Extract the path portion of a markdown link href, or None for external
+/// URLs, anchors and non-markdown files.
+pub(crate) fn markdown_href_path(href: &str) -> Option<String> {
+    relative_href_path(href).filter(|path| path.ends_with(".md"))
+}
+
+/// Resolve a relative markdown link path against the linking note's folder.
+/// A leading `/` is treated as the notes root. Returns None if the path escapes the root.
+pub(crate) fn resolve_relative_link(source_id: &str, path: &str) -> Option<String> {
+    let joined = resolve_relative_path(source_id, path)?;
+    let id = joined.strip_suffix(".md")?;
+    if id.is_empty() {
+        None
+    } else {
+        Some(id.to_string())
+    }
+}
+
+/// `resolve_relative_link` for any file: its path relative to the notes root.
+fn resolve_relative_path(source_id: &str, path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        source_id.split('/').collect()
+    };
+    // Drop the source note's own filename, keeping its folder
+    if !path.starts_with('/') {
+        parts.pop();
+    }
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            other => parts.push(other),
+        }
+    }
+
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// A file served through the asset protocol (how the editor embeds images),
+/// as a path relative to the notes root; None for other URLs or files outside it.
+fn asset_url_path(href: &str, notes_root: &Path) -> Option<String> {
+    let url = url::Url::parse(href_destination(href)).ok()?;
+    let is_asset = match url.scheme() {
+        "asset" => url.host_str() == Some("localhost"),
+        "http" | "https" => url.host_str() == Some("asset.localhost"),
+        _ => false,
+    };
+    if !is_asset {
+        return None;
+    }
+    // The absolute path is percent-encoded as a single segment
+    let encoded = url.path().strip_prefix('/')?;
+    let absolute = urlencoding::decode(encoded).ok()?.into_owned();
+    let relative = Path::new(&absolute).strip_prefix(notes_root).ok()?;
+    let parts: Vec<&str> = relative
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<_>>()?;
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// Files a note's markdown links and images point to, as paths relative to
+/// the notes root. Relative hrefs resolve from the note's folder, like
+/// `extract_links`; asset URLs from their absolute path.
+pub fn linked_file_paths(source_id: &str, content: &str, notes_root: &Path) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut in_fence = false;
+
+    for line in content.lines() {
+        if is_code_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        let masked = mask_inline_code(line);
+        for cap in markdown_link_re().captures_iter(&masked) {
+            let href = cap[1].trim();
+            let path = match relative_href_path(href) {
+                Some(path) => resolve_relative_path(source_id, &path),
+                None => asset_url_path(href, notes_root),
+            };
+            if let Some(path) = path.filter(|path| !paths.contains(path)) {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths
+}
+
+/// Blank out inline code spans so links inside them are ignored.
+/// Replaced bytes are spaces, so match offsets still line up with the original line.
+pub(crate) fn mask_inline_code(line: &str) -> String {
+    let mut masked = String::with_capacity(line.len());
+    let mut in_code = false;
+    for c in line.chars() {
+        if c == '`' {
+            in_code = !in_code;
+            masked.push(c);
+        } else if in_code {
+            masked.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        } else {
+            masked.push(c);
+        }
+    }
+    masked
+}
+
+/// Whether a line opens or closes a fenced code block.
+pub(crate) fn is_code_fence(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("```") || trimmed.starts_with("~~~")
+}
+
+/// Links in a note body, with relative links resolved from the note's location.
+pub fn extract_links(source_id: &str, content: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut in_fence = false;
+
+    for line in content.lines() {
+        if is_code_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        let masked = mask_inline_code(line);
+        let context: String = line.trim().chars().take(200).collect();
+
+        for cap in wikilink_re().captures_iter(&masked) {
+            let target = wikilink_target(&cap[1]);
+            if target.is_empty() {
+                continue;
+            }
+            links.push(Link {
+                kind: LinkKind::Wikilink,
+                target: target.to_string(),
+                key: wikilink_key(target),
+                context: context.clone(),
+            });
+        }
+
+        for cap in markdown_link_re().captures_iter(&masked) {
+            if cap[0].starts_with('!') {
+                continue;
+            }
+            let href = cap[1].trim();
+            let Some(path) = markdown_href_path(href) else {
+                continue;
+            };
+            let Some(target_id) = resolve_relative_link(source_id, &path) else {
+                continue;
+            };
+            links.push(Link {
+                kind: LinkKind::Markdown,
+                target: href.to_string(),
+                key: target_id.to_lowercase(),
+                context: context.clone(),
+            });
+        }
+    }
+
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(links: &[Link]) -> Vec<(LinkKind, &str, &str)> {
+        links
+            .iter()
+            .map(|link| (link.kind, link.target.as_str(), link.key.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn extracts_wikilinks_without_heading_or_alias() {
+        let links = extract_links(
+            "a",
+            "See [[Other Note#Intro|the intro]] and [[folder/Deep.md]].",
+        );
+        assert_eq!(
+            targets(&links),
+            vec![
+                (LinkKind::Wikilink, "Other Note", "other note"),
+                (LinkKind::Wikilink, "folder/Deep.md", "folder/deep"),
+            ]
+        );
+        assert_eq!(
+            links[0].context,
+            "See [[Other Note#Intro|the intro]] and [[folder/Deep.md]]."
+        );
+    }
+
+    #[test]
+    fn ignores_links_in_code() {
+        let content = "```\n[[Fenced]]\n```\n~~~md\n[x](fenced.md)\n~~~\n`[[Inline]]` [[Real]]\n";
+        let links = extract_links("a", content);
+        assert_eq!(targets(&links), vec![(LinkKind::Wikilink, "Real", "real")]);
+    }
+
+    #[test]
+    fn resolves_relative_markdown_links() {
+        let content = "[up](../Other%20Note.md) [root](/top.md#part) [same](./sib.md \"title\")\n\
+                       [ext](https://example.com/x.md) ![img](pic.md) [file](doc.pdf) [out](../../../x.md)";
+        let links = extract_links("dir/sub/note", content);
+        let keys: Vec<&str> = links.iter().map(|link| link.key.as_str()).collect();
+        assert_eq!(keys, vec!["dir/other note", "top", "dir/sub/sib"]);
+        assert!(links.iter().all(|link| link.kind == LinkKind::Markdown));
+    }
+
+    #[test]
+    fn backlinks_resolve_by_id_filename_title_and_alias() {
+        let mut index = LinkIndex::default();
+        index.update_note(
+            "notes/target",
+            "---\naliases: [Nick]\n---\n# Target Title\n",
+        );
+        index.update_note("by-id", "[[notes/target]]");
+        index.update_note("by-leaf", "[[Target]]");
+        index.update_note("by-title", "[[target title]]");
+        index.update_note("by-alias", "[[nick]]");
+        index.update_note("by-path", "[t](notes/target.md)");
+        index.update_note("unrelated", "[[Missing]]");
+
+        let sources: Vec<String> = index
+            .backlinks("notes/target")
+            .into_iter()
+            .map(|backlink| backlink.id)
+            .collect();
+        assert_eq!(
+            sources,
+            vec!["by-alias", "by-id", "by-leaf", "by-path", "by-title"]
+        );
+
+        let outgoing = index.outgoing_links("unrelated");
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].target_id, None);
+
+        // Creating the missing note makes the existing link resolve
+        index.update_note("Missing", "# Missing\n");
+        assert_eq!(
+            index.outgoing_links("unrelated")[0].target_id.as_deref(),
+            Some("Missing")
+        );
+    }
+
+    #[test]
+    fn exact_id_wins_over_title() {
+        let mut index = LinkIndex::default();
+        index.update_note("b", "# plan\n");
+        index.update_note("plan", "# Something else\n");
+        index.update_note("source", "[[Plan]]");
+        assert_eq!(
+            index.outgoing_links("source")[0].target_id.as_deref(),
+            Some("plan")
+        );
+    }
+
+    fn rename(old_id: &str, new_id: &str, new_title: Option<&str>) -> NoteRename {
+        NoteRename {
+            old_id: old_id.to_string(),
+            new_id: new_id.to_string(),
+            new_title: new_title.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn rewrites_wikilinks_in_the_form_they_were_written() {
+        let mut index = LinkIndex::default();
+        index.update_note("old/plan", "# Q3 Plan\n");
+        let source = "[[old/plan#Goals|goals]] [[q3 plan]] [[plan.md]] [[Other]]\n";
+        index.update_note("source", source);
+
+        let renames = [rename("old/plan", "new/roadmap", Some("Roadmap"))];
+        assert_eq!(index.rewrite_candidates(&renames), vec!["source"]);
+        assert_eq!(
+            index.rewrite_links("source", source, &renames).as_deref(),
+            Some("[[new/roadmap#Goals|goals]] [[Roadmap]] [[roadmap]] [[Other]]\n")
+        );
+    }
+
+    #[test]
+    fn title_links_stay_when_only_the_path_changed() {
+        let mut index = LinkIndex::default();
+        index.update_note("a/Note", "# Shown Title\n");
+        let source = "[[Shown Title]]";
+        index.update_note("source", source);
+        let renames = [rename("a/Note", "b/Note", None)];
+        assert_eq!(index.rewrite_links("source", source, &renames), None);
+    }
+
+    #[test]
+    fn rewrites_relative_markdown_links_when_either_end_moves() {
+        let mut index = LinkIndex::default();
+        index.update_note("docs/target", "# Target\n");
+        let source = "[t](../docs/target.md#part \"title\") [abs](/docs/target.md) [m](<../docs/target.md>)\n";
+        index.update_note("notes/source", source);
+
+        let target_moved = [rename("docs/target", "archive/2024/target", None)];
+        assert_eq!(
+            index.rewrite_links("notes/source", source, &target_moved).as_deref(),
+            Some("[t](../archive/2024/target.md#part \"title\") [abs](/archive/2024/target.md) [m](<../archive/2024/target.md>)\n")
+        );
+
+        let source_moved = [rename("notes/source", "source", None)];
+        assert_eq!(
+            index.rewrite_candidates(&source_moved),
+            vec!["notes/source"]
+        );
+        assert_eq!(
+            index
+                .rewrite_links("notes/source", source, &source_moved)
+                .as_deref(),
+            Some(
+                "[t](docs/target.md#part \"title\") [abs](/docs/target.md) [m](<docs/target.md>)\n"
+            )
+        );
+    }
+
+    #[test]
+    fn rewrite_keeps_encoding_and_skips_code_and_dangling_links() {
+        let mut index = LinkIndex::default();
+        index.update_note("My Note", "");
+        let source = "[a](My%20Note.md) [b](missing.md)\n```\n[[My Note]]\n```\n`[[My Note]]`\r\n";
+        index.update_note("source", source);
+        let renames = [rename("My Note", "Your Note", None)];
+        assert_eq!(
+            index.rewrite_links("source", source, &renames).as_deref(),
+            Some("[a](Your%20Note.md) [b](missing.md)\n```\n[[My Note]]\n```\n`[[My Note]]`\r\n")
+        );
+    }
+
+    #[test]
+    fn linked_files_resolve_from_the_note_and_the_notes_root() {
+        let root = Path::new("/home/me/notes");
+        let content = "![pic](../assets/a%20b.png) [doc](<files/Spec Sheet.pdf> \"Spec\")\n\
+                       ![](asset://localhost/%2Fhome%2Fme%2Fnotes%2Fassets%2Fc.png)\n\
+                       ![](http://asset.localhost/%2Fhome%2Fme%2Fnotes%2Fd.png)\n\
+                       ![](asset://localhost/%2Felsewhere%2Fe.png) ![](https://example.com/f.png)\n\
+                       [note](other.md#intro) [up](../../../g.png) `![](h.png)`\n\
+                       ```\n![](i.png)\n```\n![again](../assets/a%20b.png)\n";
+        assert_eq!(
+            linked_file_paths("work/note", content, root),
+            vec![
+                "assets/a b.png",
+                "work/files/Spec Sheet.pdf",
+                "assets/c.png",
+                "d.png",
+                "work/other.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_paths_between_notes() {
+        assert_eq!(relative_link_path("a/b/from", "a/c/to"), "../c/to.md");
+        assert_eq!(relative_link_path("from", "x/to"), "x/to.md");
+        assert_eq!(relative_link_path("x/from", "to"), "../to.md");
+        assert_eq!(relative_link_path("a/a", "a"), "../a.md");
+    }
+}
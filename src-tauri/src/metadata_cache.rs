@@ -0,0 +1,431 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::links::{self, LinkIndex};
+use crate::tags::{self, TagIndex};
+use crate::{
+    abs_path_from_id, extract_title, frontmatter, generate_preview, id_from_abs_path,
+    is_visible_notes_entry,
+};
+use crate::{note_order, NoteMetadata};
+
+/// The cache lives in app data, one file per notes folder:
+/// `metadata_cache/<folder key>.json`.
+const CACHE_DIR: &str = "metadata_cache";
+
+/// Bump when what's extracted from notes changes, so old caches are discarded.
+const CACHE_VERSION: u32 = 1;
+
+/// Changes are written out at most this often; anything not saved is simply
+/// re-read from its file next time.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What's known about a note file, valid while its mtime and size are unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedNote {
+    #[serde(flatten)]
+    pub meta: NoteMetadata,
+    pub mtime_ns: u64,
+    pub size: u64,
+    pub tags: Vec<String>,
+    pub links: Vec<links::Link>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct CacheFile {
+    version: u32,
+    notes_folder: String,
+    notes: HashMap<String, CachedNote>,
+}
+
+/// Notes added, changed or removed by a refresh.
+#[derive(Default)]
+pub struct Changes {
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Note metadata for a notes folder, kept on disk so listing notes and building
+/// the link and tag indexes only reads files that changed.
+#[derive(Default)]
+pub struct MetadataCache {
+    path: PathBuf,
+    notes_folder: String,
+    notes: HashMap<String, CachedNote>,
+    dirty: bool,
+    last_saved: Option<Instant>,
+}
+
+pub(crate) fn file_stamp(metadata: &std::fs::Metadata) -> (u64, u64) {
+    let mtime_ns = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    (mtime_ns, metadata.len())
+}
+
+/// Read and parse a note file.
+fn read_note(id: &str, path: &Path, metadata: &std::fs::Metadata) -> Option<CachedNote> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let (mtime_ns, size) = file_stamp(metadata);
+    let modified = (mtime_ns / 1_000_000_000) as i64;
+    let frontmatter = frontmatter::parse(&content);
+    Some(CachedNote {
+        meta: NoteMetadata {
+            id: id.to_string(),
+            title: extract_title(&content),
+            preview: generate_preview(&content),
+            modified,
+            created: note_order::created(&frontmatter, Some(metadata), modified),
+            frontmatter,
+        },
+        mtime_ns,
+        size,
+        tags: tags::extract_tags(&content),
+        links: links::extract_links(id, &content),
+    })
+}
+
+impl MetadataCache {
+    /// Load the saved cache for a notes folder, or start an empty one.
+    pub fn open(app_data: &Path, notes_folder: &Path) -> Self {
+        let path = app_data.join(CACHE_DIR).join(format!(
+            "{}.json",
+            crate::index_store::folder_key(notes_folder)
+        ));
+        let notes_folder = notes_folder.to_string_lossy().into_owned();
+        let notes = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<CacheFile>(&content).ok())
+            .filter(|file| file.version == CACHE_VERSION && file.notes_folder == notes_folder)
+            .map(|file| file.notes)
+            .unwrap_or_default();
+        Self {
+            path,
+            notes_folder,
+            notes,
+            ..Default::default()
+        }
+    }
+
+    pub fn notes_folder(&self) -> &str {
+        &self.notes_folder
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = &CachedNote> {
+        self.notes.values()
+    }
+
+    /// Bring the cache up to date with the notes folder. Files are only read
+    /// when their mtime or size differ from the cached entry.
+    pub fn refresh(&mut self, ignored_dirs: &[String]) -> Changes {
+        let root = PathBuf::from(&self.notes_folder);
+        let mut changes = Changes::default();
+        if !root.exists() {
+            changes.removed = self.notes.drain().map(|(id, _)| id).collect();
+            self.dirty |= !changes.removed.is_empty();
+            return changes;
+        }
+
+        use walkdir::WalkDir;
+        let mut seen = HashSet::new();
+        for entry in WalkDir::new(&root)
+            .max_depth(10)
+            .into_iter()
+            .filter_entry(|e| is_visible_notes_entry(e, ignored_dirs))
+            .flatten()
+        {
+            let file_path = entry.path();
+            if !file_path.is_file() {
+                continue;
+            }
+            let Some(id) = id_from_abs_path(&root, file_path, ignored_dirs) else {
+                continue;
+            };
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let unchanged = self
+                .notes
+                .get(&id)
+                .is_some_and(|note| (note.mtime_ns, note.size) == file_stamp(&metadata));
+            if !unchanged {
+                if let Some(note) = read_note(&id, file_path, &metadata) {
+                    self.notes.insert(id.clone(), note);
+                    changes.updated.push(id.clone());
+                }
+            }
+            seen.insert(id);
+        }
+
+        self.notes.retain(|id, _| {
+            let keep = seen.contains(id);
+            if !keep {
+                changes.removed.push(id.clone());
+            }
+            keep
+        });
+        self.dirty |= !changes.is_empty();
+        changes
+    }
+
+    /// Re-check one note (after a file event): re-read it if it changed, or
+    /// drop it if it's gone. Returns whether the cache changed.
+    pub fn refresh_note(&mut self, id: &str) -> bool {
+        let root = Path::new(&self.notes_folder);
+        let current = abs_path_from_id(root, id)
+            .ok()
+            .and_then(|path| {
+                std::fs::metadata(&path)
+                    .ok()
+                    .map(|metadata| (path, metadata))
+            })
+            .filter(|(_, metadata)| metadata.is_file());
+
+        let changed = match current {
+            Some((path, metadata)) => {
+                let unchanged = self
+                    .notes
+                    .get(id)
+                    .is_some_and(|note| (note.mtime_ns, note.size) == file_stamp(&metadata));
+                match (!unchanged)
+                    .then(|| read_note(id, &path, &metadata))
+                    .flatten()
+                {
+                    Some(note) => {
+                        self.notes.insert(id.to_string(), note);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            None => self.notes.remove(id).is_some(),
+        };
+        self.dirty |= changed;
+        changed
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CachedNote> {
+        self.notes.get(id)
+    }
+
+    /// Write the cache if it changed, at most every `SAVE_INTERVAL` unless `force`d.
+    pub fn save(&mut self, force: bool) -> Result<(), String> {
+        let due = force
+            || self
+                .last_saved
+                .is_none_or(|saved| saved.elapsed() >= SAVE_INTERVAL);
+        if !self.dirty || !due || self.notes_folder.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let file = CacheFile {
+            version: CACHE_VERSION,
+            notes_folder: self.notes_folder.clone(),
+            notes: std::mem::take(&mut self.notes),
+        };
+        let written = serde_json::to_string(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                crate::fsutil::write_atomic(&self.path, content.as_bytes())
+                    .map_err(|e| e.to_string())
+            });
+        self.notes = file.notes;
+        written?;
+        self.dirty = false;
+        self.last_saved = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Link and tag indexes built from the cached entries, without reading files.
+    pub fn build_indexes(&self) -> (LinkIndex, TagIndex) {
+        let mut link_index = LinkIndex::default();
+        let mut tag_index = TagIndex::default();
+        for note in self.notes.values() {
+            apply_note(&mut link_index, &mut tag_index, &note.meta.id, Some(note));
+        }
+        (link_index, tag_index)
+    }
+}
+
+/// Update a note in the link and tag indexes from its cache entry, or remove it.
+pub fn apply_note(
+    link_index: &mut LinkIndex,
+    tag_index: &mut TagIndex,
+    id: &str,
+    note: Option<&CachedNote>,
+) {
+    match note {
+        Some(note) => {
+            let aliases = frontmatter::string_list(&note.meta.frontmatter, "aliases");
+            link_index.set_note(id, note.meta.title.clone(), aliases, note.links.clone());
+            tag_index.set_note(id, note.tags.clone());
+        }
+        None => {
+            link_index.remove_note(id);
+            tag_index.remove_note(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch app data directory and notes folder, removed when dropped.
+    struct Dirs {
+        root: PathBuf,
+    }
+
+    impl Dirs {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "scratch-metadata-cache-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(root.join("notes")).unwrap();
+            Dirs { root }
+        }
+
+        fn app_data(&self) -> PathBuf {
+            self.root.join("app")
+        }
+
+        fn notes(&self) -> PathBuf {
+            self.root.join("notes")
+        }
+
+        fn open(&self) -> MetadataCache {
+            MetadataCache::open(&self.app_data(), &self.notes())
+        }
+
+        /// Write a note with the given mtime (unix seconds).
+        fn write(&self, id: &str, content: &str, modified: u64) {
+            let path = self.notes().join(format!("{}.md", id));
+            std::fs::write(&path, content).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(std::time::UNIX_EPOCH + Duration::from_secs(modified))
+                .unwrap();
+        }
+
+        fn cache_file(&self) -> PathBuf {
+            self.open().path
+        }
+    }
+
+    impl Drop for Dirs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn title(cache: &MetadataCache, id: &str) -> Option<String> {
+        cache.get(id).map(|note| note.meta.title.clone())
+    }
+
+    #[test]
+    fn refresh_only_reads_changed_files() {
+        let dirs = Dirs::new("refresh");
+        dirs.write("a", "# Alpha\n#tag [[b]]", 100);
+        dirs.write("b", "# Beta", 100);
+        let mut cache = dirs.open();
+
+        let mut changes = cache.refresh(&[]);
+        changes.updated.sort();
+        assert_eq!(changes.updated, vec!["a", "b"]);
+        assert_eq!(cache.get("a").unwrap().tags, vec!["tag"]);
+        assert_eq!(cache.get("a").unwrap().meta.modified, 100);
+
+        // Same mtime and size: the cached entry is trusted, not the file
+        dirs.write("b", "# Bets", 100);
+        assert!(cache.refresh(&[]).is_empty());
+        assert_eq!(title(&cache, "b").as_deref(), Some("Beta"));
+
+        // Same size, new mtime
+        dirs.write("b", "# Bees", 101);
+        assert_eq!(cache.refresh(&[]).updated, vec!["b"]);
+        assert_eq!(title(&cache, "b").as_deref(), Some("Bees"));
+
+        // Same mtime, new size
+        dirs.write("b", "# Beetle", 101);
+        assert_eq!(cache.refresh(&[]).updated, vec!["b"]);
+        assert_eq!(title(&cache, "b").as_deref(), Some("Beetle"));
+
+        std::fs::remove_file(dirs.notes().join("a.md")).unwrap();
+        let changes = cache.refresh(&[]);
+        assert!(changes.updated.is_empty());
+        assert_eq!(changes.removed, vec!["a"]);
+        assert!(cache.get("a").is_none());
+    }
+
+    #[test]
+    fn refresh_note_rereads_or_drops_one_note() {
+        let dirs = Dirs::new("refresh-note");
+        dirs.write("a", "# Alpha", 100);
+        let mut cache = dirs.open();
+
+        assert!(cache.refresh_note("a"));
+        assert!(!cache.refresh_note("a"));
+
+        dirs.write("a", "# Alpha two", 100);
+        assert!(cache.refresh_note("a"));
+        assert_eq!(title(&cache, "a").as_deref(), Some("Alpha two"));
+
+        std::fs::remove_file(dirs.notes().join("a.md")).unwrap();
+        assert!(cache.refresh_note("a"));
+        assert!(cache.get("a").is_none());
+        assert!(!cache.refresh_note("a"));
+        assert!(!cache.refresh_note("../outside"));
+    }
+
+    #[test]
+    fn saved_cache_is_reopened_for_the_same_folder_and_version() {
+        let dirs = Dirs::new("save");
+        dirs.write("a", "# Alpha", 100);
+        let mut cache = dirs.open();
+        cache.refresh(&[]);
+        cache.save(false).unwrap();
+        assert_eq!(title(&dirs.open(), "a").as_deref(), Some("Alpha"));
+
+        // Not written again until the interval passes, unless forced
+        dirs.write("a", "# Alpha two", 200);
+        cache.refresh(&[]);
+        cache.save(false).unwrap();
+        assert_eq!(title(&dirs.open(), "a").as_deref(), Some("Alpha"));
+        cache.save(true).unwrap();
+        let mut reopened = dirs.open();
+        assert_eq!(title(&reopened, "a").as_deref(), Some("Alpha two"));
+        assert!(reopened.refresh(&[]).is_empty());
+
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dirs.cache_file()).unwrap()).unwrap();
+        for (key, value) in [
+            ("version", serde_json::json!(CACHE_VERSION + 1)),
+            ("notesFolder", serde_json::json!("/elsewhere")),
+        ] {
+            let mut file = saved.clone();
+            file[key] = value;
+            std::fs::write(dirs.cache_file(), file.to_string()).unwrap();
+            assert!(dirs.open().get("a").is_none(), "{} mismatch", key);
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::frontmatter::Frontmatter;
+use crate::NoteMetadata;
+
+/// Folder path ("" for the root) -> note IDs in the order the user arranged them.
+pub type ManualOrder = HashMap<String, Vec<String>>;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Modified,
+    Created,
+    Title,
+    /// The folder's manual order; notes not placed yet come after, newest first.
+    Manual,
+}
+
+/// How `list_notes` orders notes. Pinned notes always come first, in the order
+/// they were pinned (or rearranged).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteSort {
+    #[serde(default)]
+    pub by: SortField,
+    #[serde(default)]
+    pub ascending: bool, // Defaults to newest (or Z) first
+}
+
+/// When a note was created: its `created` (or `date`) frontmatter field if it
+/// holds a date, else the file's birth time, else its modification time.
+pub fn created(
+    frontmatter: &Frontmatter,
+    metadata: Option<&std::fs::Metadata>,
+    modified: i64,
+) -> i64 {
+    ["created", "date"]
+        .iter()
+        .find_map(|key| frontmatter.get(*key).and_then(parse_date))
+        .or_else(|| {
+            metadata
+                .and_then(|m| m.created().ok())
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as i64)
+        })
+        .unwrap_or(modified)
+}
+
+/// A frontmatter date: unix seconds, RFC 3339, or a local `YYYY-MM-DD[ HH:MM[:SS]]`.
+fn parse_date(value: &Value) -> Option<i64> {
+    let text = match value {
+        Value::Number(n) => return n.as_i64(),
+        Value::String(s) => s.trim(),
+        _ => return None,
+    };
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(datetime.timestamp());
+    }
+    let local = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+    })?;
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|t| t.timestamp())
+}
+
+fn folder_of(id: &str) -> &str {
+    id.rfind('/').map_or("", |pos| &id[..pos])
+}
+
+/// Order notes: pinned first in `pinned` order, then the rest by `sort`.
+pub fn sort(notes: &mut [NoteMetadata], sort: NoteSort, pinned: &[String], manual: &ManualOrder) {
+    let pinned_rank: HashMap<&str, usize> = pinned
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.as_str(), i))
+        .collect();
+    let manual_rank: HashMap<&str, usize> = manual
+        .values()
+        .flat_map(|ids| ids.iter().enumerate().map(|(i, id)| (id.as_str(), i)))
+        .collect();
+
+    let by_field = |a: &NoteMetadata, b: &NoteMetadata| -> Ordering {
+        let ordering = match sort.by {
+            SortField::Modified => a.modified.cmp(&b.modified),
+            SortField::Created => a.created.cmp(&b.created),
+            SortField::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            SortField::Manual => {
+                // Manual order only applies within a folder
+                let placed = |n: &NoteMetadata| {
+                    manual_rank
+                        .get(n.id.as_str())
+                        .copied()
+                        .unwrap_or(usize::MAX)
+                };
+                return folder_of(&a.id)
+                    .cmp(folder_of(&b.id))
+                    .then_with(|| placed(a).cmp(&placed(b)))
+                    .then_with(|| b.modified.cmp(&a.modified));
+            }
+        };
+        if sort.ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    };
+
+    notes.sort_by(|a, b| {
+        match (
+            pinned_rank.get(a.id.as_str()),
+            pinned_rank.get(b.id.as_str()),
+        ) {
+            (Some(a_rank), Some(b_rank)) => a_rank.cmp(b_rank),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => by_field(a, b),
+        }
+        .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+/// A note moved or renamed: it leaves its old folder's order and, if the new
+/// folder is arranged manually, goes at the end of it (or keeps its place when
+/// only its name changed).
+pub fn rename_note(order: &mut ManualOrder, old_id: &str, new_id: &str) {
+    let (old_folder, new_folder) = (folder_of(old_id), folder_of(new_id));
+    if old_folder == new_folder {
+        if let Some(id) = order
+            .get_mut(old_folder)
+            .and_then(|ids| ids.iter_mut().find(|id| *id == old_id))
+        {
+            *id = new_id.to_string();
+        }
+        return;
+    }
+    remove_note(order, old_id);
+    if let Some(ids) = order.get_mut(new_folder) {
+        ids.push(new_id.to_string());
+    }
+}
+
+pub fn remove_note(order: &mut ManualOrder, id: &str) {
+    if let Some(ids) = order.get_mut(folder_of(id)) {
+        ids.retain(|existing| existing != id);
+    }
+}
+
+/// A folder moved or renamed: re-key it and its subfolders, and the notes in them.
+pub fn rename_folder(order: &mut ManualOrder, old_path: &str, new_path: &str) {
+    let old_prefix = format!("{}/", old_path);
+    let renamed = |path: &str| -> Option<String> {
+        if path == old_path {
+            Some(new_path.to_string())
+        } else {
+            path.strip_prefix(&old_prefix)
+                .map(|rest| format!("{}/{}", new_path, rest))
+        }
+    };
+
+    let moved: Vec<String> = order
+        .keys()
+        .filter(|folder| renamed(folder).is_some())
+        .cloned()
+        .collect();
+    for folder in moved {
+        let ids = order.remove(&folder).unwrap_or_default();
+        let new_folder = renamed(&folder).unwrap_or(folder);
+        let ids = ids
+            .iter()
+            .map(|id| renamed(id).unwrap_or_else(|| id.clone()))
+            .collect();
+        order.insert(new_folder, ids);
+    }
+}
+
+/// A folder was deleted: forget its order and its subfolders'.
+pub fn remove_folder(order: &mut ManualOrder, path: &str) {
+    let prefix = format!("{}/", path);
+    order.retain(|folder, _| folder != path && !folder.starts_with(&prefix));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(id: &str, title: &str, modified: i64, created: i64) -> NoteMetadata {
+        NoteMetadata {
+            id: id.to_string(),
+            title: title.to_string(),
+            preview: String::new(),
+            modified,
+            created,
+            frontmatter: Frontmatter::default(),
+        }
+    }
+
+    fn notes() -> Vec<NoteMetadata> {
+        vec![
+            note("b", "banana", 30, 1),
+            note("a", "Apple", 10, 3),
+            note("work/c", "cherry", 20, 2),
+            note("work/d", "date", 20, 4),
+        ]
+    }
+
+    fn sorted(
+        by: SortField,
+        ascending: bool,
+        pinned: &[&str],
+        manual: &ManualOrder,
+    ) -> Vec<String> {
+        let mut notes = notes();
+        let pinned: Vec<String> = pinned.iter().map(|id| id.to_string()).collect();
+        sort(&mut notes, NoteSort { by, ascending }, &pinned, manual);
+        notes.into_iter().map(|n| n.id).collect()
+    }
+
+    fn order(folders: &[(&str, &[&str])]) -> ManualOrder {
+        folders
+            .iter()
+            .map(|(folder, ids)| {
+                let ids = ids.iter().map(|id| id.to_string()).collect();
+                (folder.to_string(), ids)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sorts_by_field_with_ties_by_id() {
+        let none = ManualOrder::new();
+        assert_eq!(
+            sorted(SortField::Modified, false, &[], &none),
+            vec!["b", "work/c", "work/d", "a"]
+        );
+        assert_eq!(
+            sorted(SortField::Modified, true, &[], &none),
+            vec!["a", "work/c", "work/d", "b"]
+        );
+        assert_eq!(
+            sorted(SortField::Created, false, &[], &none),
+            vec!["work/d", "a", "work/c", "b"]
+        );
+        // Titles compare ignoring case
+        assert_eq!(
+            sorted(SortField::Title, true, &[], &none),
+            vec!["a", "b", "work/c", "work/d"]
+        );
+    }
+
+    #[test]
+    fn pinned_notes_come_first_in_pinned_order() {
+        assert_eq!(
+            sorted(
+                SortField::Title,
+                true,
+                &["work/d", "b", "gone"],
+                &ManualOrder::new()
+            ),
+            vec!["work/d", "b", "a", "work/c"]
+        );
+    }
+
+    #[test]
+    fn manual_order_applies_within_each_folder() {
+        let manual = order(&[("", &["a", "b"]), ("work", &["work/d"])]);
+        // Unplaced notes follow the placed ones, newest first; `ascending` doesn't apply
+        assert_eq!(
+            sorted(SortField::Manual, false, &[], &manual),
+            vec!["a", "b", "work/d", "work/c"]
+        );
+        assert_eq!(
+            sorted(SortField::Manual, true, &["work/c"], &manual),
+            vec!["work/c", "a", "b", "work/d"]
+        );
+    }
+
+    #[test]
+    fn renaming_a_note_keeps_or_moves_its_place() {
+        let mut manual = order(&[("", &["a", "b"]), ("work", &["work/c"])]);
+        rename_note(&mut manual, "a", "z");
+        assert_eq!(manual, order(&[("", &["z", "b"]), ("work", &["work/c"])]));
+
+        rename_note(&mut manual, "z", "work/z");
+        assert_eq!(
+            manual,
+            order(&[("", &["b"]), ("work", &["work/c", "work/z"])])
+        );
+
+        // Folders without a manual order stay that way
+        rename_note(&mut manual, "b", "other/b");
+        assert_eq!(manual, order(&[("", &[]), ("work", &["work/c", "work/z"])]));
+
+        remove_note(&mut manual, "work/c");
+        assert_eq!(manual, order(&[("", &[]), ("work", &["work/z"])]));
+    }
+
+    #[test]
+    fn renaming_a_folder_rekeys_it_and_its_subfolders() {
+        let mut manual = order(&[
+            ("work", &["work/a"]),
+            ("work/sub", &["work/sub/b"]),
+            ("workshop", &["workshop/c"]),
+            ("", &["d"]),
+        ]);
+        rename_folder(&mut manual, "work", "archive/job");
+        assert_eq!(
+            manual,
+            order(&[
+                ("archive/job", &["archive/job/a"]),
+                ("archive/job/sub", &["archive/job/sub/b"]),
+                ("workshop", &["workshop/c"]),
+                ("", &["d"]),
+            ])
+        );
+
+        remove_folder(&mut manual, "archive/job");
+        assert_eq!(
+            manual,
+            order(&[("workshop", &["workshop/c"]), ("", &["d"])])
+        );
+    }
+
+    #[test]
+    fn created_prefers_frontmatter_dates() {
+        let frontmatter = |key: &str, value: Value| {
+            let mut fm = Frontmatter::default();
+            fm.insert(key.to_string(), value);
+            fm
+        };
+        assert_eq!(
+            created(&frontmatter("created", Value::from(1234)), None, 9),
+            1234
+        );
+        assert_eq!(
+            created(
+                &frontmatter("date", Value::from("2024-01-31T10:00:00Z")),
+                None,
+                9
+            ),
+            1706695200
+        );
+        let local_midnight = Local
+            .from_local_datetime(
+                &NaiveDate::from_ymd_opt(2024, 1, 31)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap(),
+            )
+            .earliest()
+            .unwrap()
+            .timestamp();
+        assert_eq!(
+            created(&frontmatter("created", Value::from("2024-01-31")), None, 9),
+            local_midnight
+        );
+        assert_eq!(
+            created(&frontmatter("created", Value::from("soon")), None, 9),
+            9
+        );
+    }
+}
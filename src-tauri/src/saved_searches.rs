@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{search_query, AppState, SearchIndex, SearchResult};
+
+/// Most notes a saved search returns.
+const RESULT_LIMIT: usize = 200;
+
+/// A named query kept in the notes folder's settings.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedSearch {
+    pub id: String,
+    pub name: String,
+    pub query: String, // Search query language, see `search_query`
+    /// Re-run when notes change on disk, sending `saved-search-updated`.
+    #[serde(default)]
+    pub live: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SavedSearchUpdated {
+    pub id: String,
+    pub results: Vec<SearchResult>,
+}
+
+/// Check saved searches before they're stored: IDs unique, names set, and
+/// queries that parse.
+pub fn validate(searches: &[SavedSearch]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for search in searches {
+        if search.id.trim().is_empty() {
+            return Err("Saved search ID is empty".to_string());
+        }
+        if !ids.insert(search.id.as_str()) {
+            return Err(format!("Duplicate saved search ID \"{}\"", search.id));
+        }
+        if search.name.trim().is_empty() {
+            return Err("Saved search name is empty".to_string());
+        }
+        let clauses = search_query::parse(&search.query)
+            .map_err(|e| format!("Saved search \"{}\": {}", search.name, e))?;
+        if clauses.is_empty() {
+            return Err(format!("Saved search \"{}\" has no query", search.name));
+        }
+    }
+    Ok(())
+}
+
+/// Run a saved search, remembering which notes it returned so live updates
+/// can tell whether a change affects it.
+pub fn run(
+    state: &AppState,
+    index: &SearchIndex,
+    search: &SavedSearch,
+) -> Result<Vec<SearchResult>, String> {
+    let clauses = search_query::parse(&search.query)?;
+    let results = index
+        .search(&clauses, false, RESULT_LIMIT)
+        .map_err(|e| e.to_string())?;
+    state
+        .saved_search_ids
+        .lock()
+        .expect("saved search ids mutex")
+        .insert(
+            search.id.clone(),
+            results.iter().map(|r| r.id.clone()).collect(),
+        );
+    Ok(results)
+}
+
+/// Re-run the live saved searches affected by notes that changed on disk
+/// (once they're reindexed): those that listed a changed note or match one now.
+pub fn refresh_live(app: &AppHandle, index: &Arc<SearchIndex>, changed: &BTreeSet<String>) {
+    let Some(state) = app.try_state::<AppState>() else {
+        return;
+    };
+    // Changes to an index replaced by a folder switch don't concern the open folder
+    if !state
+        .current_search_index()
+        .is_some_and(|current| Arc::ptr_eq(&current, index))
+    {
+        return;
+    }
+    let live: Vec<SavedSearch> = {
+        let settings = state.settings.read().expect("settings read lock");
+        settings
+            .saved_searches
+            .iter()
+            .flatten()
+            .filter(|search| search.live)
+            .cloned()
+            .collect()
+    };
+    if live.is_empty() {
+        return;
+    }
+
+    // The reader reloads on its own shortly after a commit; searches here
+    // need the notes just committed
+    let _ = index.reader.reload();
+
+    for search in live {
+        let Ok(clauses) = search_query::parse(&search.query) else {
+            continue;
+        };
+        let Ok(matching) = index.matching_ids(&clauses, RESULT_LIMIT) else {
+            continue;
+        };
+        let previous = state
+            .saved_search_ids
+            .lock()
+            .expect("saved search ids mutex")
+            .get(&search.id)
+            .cloned()
+            .unwrap_or_default();
+        let affected = matching != previous
+            || matching
+                .iter()
+                .chain(&previous)
+                .any(|id| changed.contains(id));
+        if !affected {
+            continue;
+        }
+
+        match run(&state, index, &search) {
+            Ok(results) => {
+                let _ = app.emit(
+                    "saved-search-updated",
+                    SavedSearchUpdated {
+                        id: search.id.clone(),
+                        results,
+                    },
+                );
+            }
+            Err(e) => eprintln!("Saved search \"{}\" failed: {}", search.name, e),
+        }
+    }
+}
@@ -0,0 +1,474 @@
+//! Search query language.
+//!
+//! A query is a list of whitespace-separated clauses, all of which must match:
+//!
+//! - `word` matches notes containing the word in the title or content, allowing
+//!   a typo or two in longer words; while typing, the last word also matches
+//!   as a prefix (`proj` finds "project")
+//! - `"exact phrase"` matches the words in order, exactly
+//! - `title:word`, `title:"a phrase"` only look at the title
+//! - `tag:project` matches `#project` and nested tags like `#project/alpha`
+//! - `folder:work/notes` (or `path:`) matches notes in that folder or below it
+//! - `modified:>2024-01-01` (also `>=`, `<`, `<=`, or a bare date for that day);
+//!   instead of a date, `today`, `yesterday`, `7d` (seven days ago), `week` or
+//!   `month` (the current one), e.g. `modified:week` or `modified:>=7d`
+//! - `is:pinned` matches pinned notes
+//! - `has:task` matches notes with a task list item (`- [ ]` or `- [x]`)
+//!
+//! Prefix any clause with `-` to exclude matches (`-tag:archive`, `-"draft"`).
+//! Quote values containing spaces (`folder:"My Folder"`). A `word:` prefix that
+//! isn't one of the fields above is searched as plain text (e.g. `10:30`).
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate, TimeZone};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Unquoted text, matched against the title and content.
+    Text(String),
+    /// Quoted text, matched exactly against the title and content.
+    Phrase(String),
+    Title(String),
+    /// Lowercased tag name without `#`.
+    Tag(String),
+    /// Folder path relative to the notes root, without surrounding slashes.
+    Folder(String),
+    /// Modification time range in unix seconds: `start` inclusive, `end` exclusive.
+    Modified {
+        start: Option<i64>,
+        end: Option<i64>,
+    },
+    Pinned,
+    HasTask,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub negated: bool,
+    pub filter: Filter,
+}
+
+/// A clause as written, before its value is interpreted.
+struct Token {
+    negated: bool,
+    field: Option<String>, // `None` for plain words and phrases
+    value: String,         // Quoted sections keep their spaces
+    quoted: bool,
+}
+
+/// Split a query into raw clauses.
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let negated = chars.next_if_eq(&'-').is_some();
+        let mut raw = String::new();
+        let mut field = None;
+        let mut quoted = false;
+        let mut has_quotes = false;
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() && !quoted {
+                break;
+            }
+            chars.next();
+            match c {
+                '"' => {
+                    quoted = !quoted;
+                    has_quotes = true;
+                }
+                ':' if !quoted && field.is_none() && !raw.is_empty() => {
+                    let key = raw.to_lowercase();
+                    if is_field(&key) {
+                        field = Some(key);
+                        raw.clear();
+                    } else {
+                        raw.push(c);
+                    }
+                }
+                _ => raw.push(c),
+            }
+        }
+
+        if quoted {
+            return Err("Unterminated quote in search query".to_string());
+        }
+        if let Some(ref key) = field {
+            if raw.trim().is_empty() {
+                return Err(format!("Missing value after \"{}:\"", key));
+            }
+        }
+        if raw.is_empty() && field.is_none() {
+            if negated {
+                return Err("Nothing to exclude after \"-\"".to_string());
+            }
+            // Empty quotes
+            continue;
+        }
+        tokens.push(Token {
+            negated,
+            field,
+            value: raw,
+            quoted: has_quotes,
+        });
+    }
+
+    Ok(tokens)
+}
+
+fn is_field(key: &str) -> bool {
+    matches!(
+        key,
+        "tag" | "folder" | "path" | "title" | "modified" | "is" | "has"
+    )
+}
+
+/// Parse a query into clauses, or explain what's wrong with it.
+pub fn parse(query: &str) -> Result<Vec<Clause>, String> {
+    tokenize(query)?
+        .into_iter()
+        .map(
+            |Token {
+                 negated,
+                 field,
+                 value,
+                 quoted,
+             }| {
+                let filter = match field.as_deref() {
+                    None if quoted => Filter::Phrase(value),
+                    None => Filter::Text(value),
+                    Some("title") => Filter::Title(value),
+                    Some("tag") => Filter::Tag(
+                        crate::tags::normalize_tag(&value)
+                            .ok_or_else(|| format!("Invalid tag \"{}\"", value))?
+                            .to_lowercase(),
+                    ),
+                    Some("folder") | Some("path") => {
+                        let folder = value.replace('\\', "/").trim_matches('/').to_string();
+                        if folder.is_empty() {
+                            return Err("folder: needs a folder path".to_string());
+                        }
+                        Filter::Folder(folder)
+                    }
+                    Some("modified") => parse_modified(&value)?,
+                    Some("is") => match value.to_lowercase().as_str() {
+                        "pinned" => Filter::Pinned,
+                        _ => {
+                            return Err(format!(
+                                "Unknown filter \"is:{}\" (supported: is:pinned)",
+                                value
+                            ))
+                        }
+                    },
+                    Some("has") => match value.to_lowercase().as_str() {
+                        "task" | "tasks" => Filter::HasTask,
+                        _ => {
+                            return Err(format!(
+                                "Unknown filter \"has:{}\" (supported: has:task)",
+                                value
+                            ))
+                        }
+                    },
+                    Some(other) => return Err(format!("Unknown search field \"{}\"", other)),
+                };
+                Ok(Clause { negated, filter })
+            },
+        )
+        .collect()
+}
+
+/// `modified:` value: an optional comparison (`>`, `>=`, `<`, `<=`, `=`)
+/// followed by a local date (YYYY-MM-DD) or a date relative to today.
+fn parse_modified(value: &str) -> Result<Filter, String> {
+    let (op, date) = [">=", "<=", ">", "<", "="]
+        .iter()
+        .find_map(|op| value.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("=", value));
+
+    let (first_day, last_day) = period(date.trim(), Local::now().date_naive()).ok_or_else(|| {
+        format!(
+            "Invalid date in \"modified:{}\" (expected e.g. modified:>2024-01-31 or modified:today)",
+            value
+        )
+    })?;
+    let period_start = local_midnight(first_day)?;
+    let period_end = last_day
+        .succ_opt()
+        .map(local_midnight)
+        .transpose()?
+        .unwrap_or(i64::MAX);
+
+    let (start, end) = match op {
+        ">" => (Some(period_end), None),
+        ">=" => (Some(period_start), None),
+        "<" => (None, Some(period_start)),
+        "<=" => (None, Some(period_end)),
+        _ => (Some(period_start), Some(period_end)),
+    };
+    Ok(Filter::Modified { start, end })
+}
+
+/// First and last day a `modified:` date stands for. Relative dates are
+/// resolved when the query is parsed, so a saved query keeps meaning "today".
+fn period(value: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let day = |date: NaiveDate| Some((date, date));
+    match value.to_lowercase().as_str() {
+        "today" => day(today),
+        "yesterday" => day(today.pred_opt()?),
+        "week" => {
+            let monday = today - Days::new(u64::from(today.weekday().num_days_from_monday()));
+            Some((monday, monday + Days::new(6)))
+        }
+        "month" => {
+            let first = today.with_day(1)?;
+            Some((first, (first + Months::new(1)).pred_opt()?))
+        }
+        relative if relative.ends_with('d') => {
+            let days: u64 = relative.strip_suffix('d')?.parse().ok()?;
+            day(today.checked_sub_days(Days::new(days))?)
+        }
+        date => day(NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?),
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> Result<i64, String> {
+    let midnight = date.and_hms_opt(0, 0, 0).ok_or("Invalid date")?;
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|t| t.timestamp())
+        .ok_or_else(|| "Invalid date".to_string())
+}
+
+/// Whether the query is plain text only, i.e. can be answered by substring search.
+pub fn is_plain_text(clauses: &[Clause]) -> bool {
+    clauses
+        .iter()
+        .all(|c| !c.negated && matches!(c.filter, Filter::Text(_) | Filter::Phrase(_)))
+}
+
+/// The text clauses joined by spaces, for substring search.
+pub fn plain_text(clauses: &[Clause]) -> String {
+    clauses
+        .iter()
+        .filter_map(|c| match &c.filter {
+            Filter::Text(text) | Filter::Phrase(text) if !c.negated => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether a note contains a task list item, for `has:task`.
+pub fn has_task(content: &str) -> bool {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    RE.get_or_init(|| regex::Regex::new(r"(?m)^\s*(?:[-*+]|\d+[.)])\s+\[[ xX]\]").unwrap())
+        .is_match(content)
+}
+
+/// Typos allowed in a search word: none for short words, where a single edit
+/// already matches too much, up to two for long ones.
+pub fn fuzzy_distance(word: &str) -> u8 {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Whether `token` is within `distance` edits of `word` (a swap of adjacent
+/// characters counts as one edit), or with `prefix`, starts with something
+/// that is. Mirrors tantivy's fuzzy matching, for highlighting fuzzy hits.
+pub fn fuzzy_matches(token: &str, word: &str, distance: u8, prefix: bool) -> bool {
+    let a: Vec<char> = word.chars().collect();
+    let b: Vec<char> = token.chars().collect();
+    let max = usize::from(distance);
+    if !prefix && a.len().abs_diff(b.len()) > max {
+        return false;
+    }
+
+    // Optimal string alignment distance; rows are prefixes of `word`
+    let mut rows: Vec<Vec<usize>> = vec![(0..=b.len()).collect()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+
+    let last = &rows[a.len()];
+    if prefix {
+        last.iter().any(|&d| d <= max)
+    } else {
+        last[b.len()] <= max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clause(negated: bool, filter: Filter) -> Clause {
+        Clause { negated, filter }
+    }
+
+    #[test]
+    fn parses_words_phrases_and_fields() {
+        assert_eq!(
+            parse(
+                r#"  plan "weekly review" -draft TITLE:Q3 tag:#Project/Alpha is:pinned has:tasks "#
+            )
+            .unwrap(),
+            vec![
+                clause(false, Filter::Text("plan".into())),
+                clause(false, Filter::Phrase("weekly review".into())),
+                clause(true, Filter::Text("draft".into())),
+                clause(false, Filter::Title("Q3".into())),
+                clause(false, Filter::Tag("project/alpha".into())),
+                clause(false, Filter::Pinned),
+                clause(false, Filter::HasTask),
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        assert_eq!(
+            parse(r#"folder:"/My Folder\Sub/" -title:"a phrase" -"not this""#).unwrap(),
+            vec![
+                clause(false, Filter::Folder("My Folder/Sub".into())),
+                clause(true, Filter::Title("a phrase".into())),
+                clause(true, Filter::Phrase("not this".into())),
+            ]
+        );
+        // A colon inside quotes or after an unknown field is plain text
+        assert_eq!(
+            parse(r#"10:30 "tag:x""#).unwrap(),
+            vec![
+                clause(false, Filter::Text("10:30".into())),
+                clause(false, Filter::Phrase("tag:x".into())),
+            ]
+        );
+        assert_eq!(parse(r#""" "#).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        assert!(parse(r#"title:"open"#).is_err());
+        assert!(parse("tag:").is_err());
+        assert!(parse("tag:123").is_err());
+        assert!(parse("folder:/").is_err());
+        assert!(parse("- word").is_err());
+        assert!(parse("is:archived").is_err());
+        assert!(parse("has:image").is_err());
+        assert!(parse("modified:soon").is_err());
+    }
+
+    #[test]
+    fn modified_comparisons_bound_the_day() {
+        let start = local_midnight(NaiveDate::from_ymd_opt(2024, 1, 31).unwrap()).unwrap();
+        let end = local_midnight(NaiveDate::from_ymd_opt(2024, 2, 1).unwrap()).unwrap();
+        let modified = |value: &str| parse_modified(value).unwrap();
+        let range = |start, end| Filter::Modified { start, end };
+
+        assert_eq!(modified("2024-01-31"), range(Some(start), Some(end)));
+        assert_eq!(modified("=2024-01-31"), range(Some(start), Some(end)));
+        assert_eq!(modified(">2024-01-31"), range(Some(end), None));
+        assert_eq!(modified(">=2024-01-31"), range(Some(start), None));
+        assert_eq!(modified("<2024-01-31"), range(None, Some(start)));
+        assert_eq!(modified("<=2024-01-31"), range(None, Some(end)));
+    }
+
+    #[test]
+    fn relative_periods() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        // A Wednesday
+        let today = date(2024, 3, 6);
+
+        assert_eq!(period("Today", today), Some((today, today)));
+        assert_eq!(
+            period("yesterday", date(2024, 3, 1)),
+            Some((date(2024, 2, 29), date(2024, 2, 29)))
+        );
+        assert_eq!(
+            period("7d", today),
+            Some((date(2024, 2, 28), date(2024, 2, 28)))
+        );
+        assert_eq!(period("0d", today), Some((today, today)));
+        assert_eq!(
+            period("week", today),
+            Some((date(2024, 3, 4), date(2024, 3, 10)))
+        );
+        assert_eq!(
+            period("month", date(2024, 2, 15)),
+            Some((date(2024, 2, 1), date(2024, 2, 29)))
+        );
+        assert_eq!(
+            period("2023-12-25", today),
+            Some((date(2023, 12, 25), date(2023, 12, 25)))
+        );
+        assert_eq!(period("xd", today), None);
+        assert_eq!(period("2023-13-01", today), None);
+    }
+
+    #[test]
+    fn plain_text_queries() {
+        let clauses = parse(r#"hello "big world""#).unwrap();
+        assert!(is_plain_text(&clauses));
+        assert_eq!(plain_text(&clauses), "hello big world");
+
+        let clauses = parse("hello -bye tag:x").unwrap();
+        assert!(!is_plain_text(&clauses));
+        assert_eq!(plain_text(&clauses), "hello");
+    }
+
+    #[test]
+    fn fuzzy_distance_grows_with_word_length() {
+        assert_eq!(fuzzy_distance("cat"), 0);
+        assert_eq!(fuzzy_distance("note"), 1);
+        assert_eq!(fuzzy_distance("notebook"), 2);
+        // Counted in characters, not bytes
+        assert_eq!(fuzzy_distance("été"), 0);
+    }
+
+    #[test]
+    fn fuzzy_matches_within_distance() {
+        assert!(fuzzy_matches("project", "project", 0, false));
+        assert!(fuzzy_matches("projcet", "project", 1, false)); // Swap counts once
+        assert!(fuzzy_matches("projet", "project", 1, false));
+        assert!(fuzzy_matches("prjects", "project", 2, false));
+        assert!(!fuzzy_matches("prjcet", "project", 1, false));
+        assert!(!fuzzy_matches("projects", "project", 0, false));
+        assert!(fuzzy_matches("CAFÉ", "CAFE", 1, false));
+    }
+
+    #[test]
+    fn fuzzy_prefix_matches_the_start_of_a_token() {
+        assert!(fuzzy_matches("project", "proj", 0, true));
+        assert!(fuzzy_matches("project", "proj", 1, true));
+        assert!(fuzzy_matches("project", "porj", 1, true));
+        assert!(!fuzzy_matches("project", "jec", 0, true));
+        assert!(!fuzzy_matches("pr", "proj", 1, true));
+        assert!(fuzzy_matches("anything", "", 0, true));
+    }
+
+    #[test]
+    fn detects_task_items() {
+        assert!(has_task("Intro\n  - [ ] todo\n"));
+        assert!(has_task("1. [x] done"));
+        assert!(!has_task("- [link](x) and [ ] brackets"));
+    }
+}
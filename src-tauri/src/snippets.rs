@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use tantivy::SnippetGenerator;
+
+/// Most snippets returned per search result.
+const MAX_SNIPPETS: usize = 3;
+/// Longest snippet, in characters (tantivy's default fragment size).
+const MAX_SNIPPET_CHARS: usize = 150;
+/// Characters of context kept before the first match in substring snippets.
+const LEADING_CONTEXT_CHARS: usize = 40;
+
+/// Matched text within a snippet or title, in UTF-16 code units so it can be
+/// used directly with JavaScript string offsets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighlightRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A piece of note content around one or more query matches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchSnippet {
+    pub text: String,
+    pub highlights: Vec<HighlightRange>,
+    pub line: usize, // 1-based line in the note the snippet comes from
+}
+
+/// Convert byte ranges within `text` to UTF-16 offsets.
+fn to_utf16_ranges(text: &str, ranges: &[Range<usize>]) -> Vec<HighlightRange> {
+    let utf16_offset = |byte: usize| text[..byte].encode_utf16().count();
+    ranges
+        .iter()
+        .filter(|r| {
+            r.end <= text.len() && text.is_char_boundary(r.start) && text.is_char_boundary(r.end)
+        })
+        .map(|r| HighlightRange {
+            start: utf16_offset(r.start),
+            end: utf16_offset(r.end),
+        })
+        .collect()
+}
+
+/// Pick the best line snippets (most matches, then earliest) and return them
+/// in document order.
+fn best_of(mut candidates: Vec<SearchSnippet>) -> Vec<SearchSnippet> {
+    candidates.sort_by_key(|s| (std::cmp::Reverse(s.highlights.len()), s.line));
+    candidates.truncate(MAX_SNIPPETS);
+    candidates.sort_by_key(|s| s.line);
+    candidates
+}
+
+/// Highlight ranges for a title from a title-field snippet generator.
+pub fn title_highlights(generator: &SnippetGenerator, title: &str) -> Vec<HighlightRange> {
+    let snippet = generator.snippet(title);
+    if snippet.fragment() != title {
+        return Vec::new();
+    }
+    to_utf16_ranges(title, snippet.highlighted())
+}
+
+/// Snippets for an indexed search hit, using tantivy's tokenizer-aware matching
+/// so stemmed and multi-term queries highlight what actually matched.
+pub fn from_generator(generator: &SnippetGenerator, content: &str) -> Vec<SearchSnippet> {
+    let candidates = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(i, line)| {
+            let snippet = generator.snippet(line);
+            if snippet.highlighted().is_empty() {
+                return None;
+            }
+            let text = snippet.fragment().to_string();
+            let highlights = to_utf16_ranges(&text, snippet.highlighted());
+            Some(SearchSnippet {
+                text,
+                highlights,
+                line: i + 1,
+            })
+        })
+        .collect();
+    best_of(candidates)
+}
+
+/// Case-insensitive matcher for a literal substring.
+fn substring_regex(needle: &str) -> Option<regex::Regex> {
+    regex::RegexBuilder::new(&regex::escape(needle))
+        .case_insensitive(true)
+        .build()
+        .ok()
+}
+
+/// Snippets for substring search, mirroring `from_generator`: a window of up
+/// to `MAX_SNIPPET_CHARS` around the first match on each matching line.
+pub fn from_substring(content: &str, needle: &str) -> Vec<SearchSnippet> {
+    let Some(re) = substring_regex(needle) else {
+        return Vec::new();
+    };
+
+    let candidates = content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let matches: Vec<Range<usize>> = re.find_iter(line).map(|m| m.range()).collect();
+            let first = matches.first()?;
+
+            // Window start: a little context before the first match
+            let start = line[..first.start]
+                .char_indices()
+                .rev()
+                .nth(LEADING_CONTEXT_CHARS.saturating_sub(1))
+                .map_or(0, |(idx, _)| idx);
+            let end = line[start..]
+                .char_indices()
+                .nth(MAX_SNIPPET_CHARS)
+                .map_or(line.len(), |(idx, _)| start + idx);
+
+            let text = line[start..end].to_string();
+            let ranges: Vec<Range<usize>> = matches
+                .iter()
+                .filter(|m| m.start >= start && m.end <= end)
+                .map(|m| m.start - start..m.end - start)
+                .collect();
+            let highlights = to_utf16_ranges(&text, &ranges);
+            Some(SearchSnippet {
+                text,
+                highlights,
+                line: i + 1,
+            })
+        })
+        .collect();
+    best_of(candidates)
+}
+
+/// Substring highlight ranges for a title.
+pub fn substring_title_highlights(title: &str, needle: &str) -> Vec<HighlightRange> {
+    let Some(re) = substring_regex(needle) else {
+        return Vec::new();
+    };
+    let ranges: Vec<Range<usize>> = re.find_iter(title).map(|m| m.range()).collect();
+    to_utf16_ranges(title, &ranges)
+}
@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::frontmatter;
+use crate::links::{is_code_fence, mask_inline_code};
+use crate::{abs_path_from_id, id_from_abs_path, is_visible_notes_entry};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize, // Notes carrying the tag or one of its nested tags
+}
+
+/// In-memory index of the tags each note carries, from `#tags` in the body and
+/// the frontmatter `tags:` field. Tags compare case-insensitively and nest on
+/// `/`: a note tagged `#project/alpha` is also listed under `project`.
+#[derive(Default)]
+pub struct TagIndex {
+    notes: HashMap<String, Vec<String>>, // Note ID -> tags as written
+}
+
+impl TagIndex {
+    pub fn update_note(&mut self, id: &str, content: &str) {
+        self.set_note(id, extract_tags(content));
+    }
+
+    /// Index a note's already extracted tags (e.g. from the metadata cache).
+    pub fn set_note(&mut self, id: &str, tags: Vec<String>) {
+        if tags.is_empty() {
+            self.notes.remove(id);
+        } else {
+            self.notes.insert(id.to_string(), tags);
+        }
+    }
+
+    pub fn remove_note(&mut self, id: &str) {
+        self.notes.remove(id);
+    }
+
+    /// Re-read a note from disk, dropping it from the index if the file is gone.
+    pub fn reindex_from_disk(&mut self, notes_root: &Path, id: &str) {
+        match abs_path_from_id(notes_root, id).map(std::fs::read_to_string) {
+            Ok(Ok(content)) => self.update_note(id, &content),
+            _ => self.remove_note(id),
+        }
+    }
+
+    /// Remove every note whose ID starts with `prefix` (e.g. "folder/").
+    pub fn remove_prefix(&mut self, prefix: &str) {
+        self.notes.retain(|id, _| !id.starts_with(prefix));
+    }
+
+    pub fn rebuild(&mut self, notes_folder: &Path, ignored_dirs: &[String]) {
+        *self = Self::default();
+
+        if !notes_folder.exists() {
+            return;
+        }
+
+        use walkdir::WalkDir;
+        for entry in WalkDir::new(notes_folder)
+            .max_depth(10)
+            .into_iter()
+            .filter_entry(|e| is_visible_notes_entry(e, ignored_dirs))
+            .flatten()
+        {
+            let file_path = entry.path();
+            if !file_path.is_file() {
+                continue;
+            }
+            if let Some(id) = id_from_abs_path(notes_folder, file_path, ignored_dirs) {
+                if let Ok(content) = std::fs::read_to_string(file_path) {
+                    self.update_note(&id, &content);
+                }
+            }
+        }
+    }
+
+    /// Every tag with the number of notes carrying it. Parent tags of nested
+    /// tags are listed too, so the result can be shown as a tree.
+    pub fn list(&self) -> Vec<TagCount> {
+        // Lowercased tag -> (spelling, notes)
+        let mut counts: BTreeMap<String, (String, usize)> = BTreeMap::new();
+
+        for tags in self.notes.values() {
+            let mut seen: HashSet<String> = HashSet::new();
+            for tag in tags {
+                for (i, _) in tag.match_indices('/').chain([(tag.len(), "")]) {
+                    let name = &tag[..i];
+                    let key = name.to_lowercase();
+                    if !seen.insert(key.clone()) {
+                        continue;
+                    }
+                    let entry = counts.entry(key).or_insert_with(|| (name.to_string(), 0));
+                    // Pick one spelling deterministically when notes disagree on case
+                    if name < entry.0.as_str() {
+                        entry.0 = name.to_string();
+                    }
+                    entry.1 += 1;
+                }
+            }
+        }
+
+        counts
+            .into_values()
+            .map(|(tag, count)| TagCount { tag, count })
+            .collect()
+    }
+
+    /// IDs of notes carrying `tag` or one of its nested tags, sorted.
+    pub fn notes_with_tag(&self, tag: &str) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .notes
+            .iter()
+            .filter(|(_, tags)| tags.iter().any(|t| tag_matches(t, tag)))
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+}
+
+fn tag_re() -> &'static regex::Regex {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    // A tag starts at the line start or after whitespace (so URL fragments and
+    // `[[Note#Heading]]` don't count) and can't be purely numeric (`#1` is an issue reference)
+    RE.get_or_init(|| {
+        regex::Regex::new(r"(?:^|\s)#([\p{L}\p{N}_\-/]*[\p{L}_\-][\p{L}\p{N}_\-/]*)").unwrap()
+    })
+}
+
+/// Whether `tag` is `parent` or nested below it, ignoring case.
+fn tag_matches(tag: &str, parent: &str) -> bool {
+    nested_part(tag, parent).is_some()
+}
+
+/// What follows `parent` in `tag` ("" or "/child..."), if `tag` is `parent` or
+/// nested below it. Compares char by char, since lowercasing can change a
+/// string's byte length.
+fn nested_part<'a>(tag: &'a str, parent: &str) -> Option<&'a str> {
+    let mut chars = tag.char_indices();
+    let mut end = 0;
+    for expected in parent.chars() {
+        let (i, c) = chars.next()?;
+        if !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+        end = i + c.len_utf8();
+    }
+    let rest = &tag[end..];
+    (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
+
+/// Validate a user-supplied tag name, dropping a leading `#`.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().trim_start_matches('#').trim_matches('/');
+    let prefixed = format!("#{}", tag);
+    let caps = tag_re().captures(&prefixed)?;
+    (caps[1].len() == tag.len()).then(|| tag.to_string())
+}
+
+/// Body `#tag` matches in a line, as byte ranges of the tag name (without `#`).
+fn tag_ranges(line: &str) -> Vec<std::ops::Range<usize>> {
+    let masked = mask_inline_code(line);
+    tag_re()
+        .captures_iter(&masked)
+        .filter_map(|cap| {
+            let m = cap.get(1)?;
+            // `#a/` is the tag `a`
+            let len = m.as_str().trim_end_matches('/').len();
+            Some(m.start()..m.start() + len)
+        })
+        .filter(|range| !range.is_empty())
+        .collect()
+}
+
+/// Tags in a note: frontmatter `tags:` first, then body tags in order of
+/// appearance, skipping code. Duplicates (ignoring case) are dropped.
+pub fn extract_tags(content: &str) -> Vec<String> {
+    let mut tags = frontmatter::string_list(&frontmatter::parse(content), "tags");
+    let mut in_fence = false;
+
+    for line in frontmatter::body(content).lines() {
+        if is_code_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        for range in tag_ranges(line) {
+            tags.push(line[range].to_string());
+        }
+    }
+
+    let mut seen = HashSet::new();
+    tags.retain(|tag| !tag.is_empty() && seen.insert(tag.to_lowercase()));
+    tags
+}
+
+/// Rename `old` (and tags nested below it) to `new` in a note's body and
+/// frontmatter. Returns `None` if the note doesn't use the tag.
+pub fn rename_in_content(content: &str, old: &str, new: &str) -> Result<Option<String>, String> {
+    let rename = |tag: &str| -> Option<String> {
+        nested_part(tag, old).map(|nested| format!("{}{}", new, nested))
+    };
+
+    // Body tags
+    let body = frontmatter::body(content);
+    let mut updated = content[..content.len() - body.len()].to_string();
+    let mut changed = false;
+    let mut in_fence = false;
+
+    for line in body.split_inclusive('\n') {
+        if is_code_fence(line) {
+            in_fence = !in_fence;
+        }
+        if in_fence || is_code_fence(line) {
+            // Fence lines and code block contents are copied as-is
+            updated.push_str(line);
+            continue;
+        }
+
+        let mut last = 0;
+        for range in tag_ranges(line) {
+            if let Some(renamed) = rename(&line[range.clone()]) {
+                updated.push_str(&line[last..range.start]);
+                updated.push_str(&renamed);
+                last = range.end;
+                changed = true;
+            }
+        }
+        updated.push_str(&line[last..]);
+    }
+
+    // Frontmatter `tags:`
+    let fm_tags = frontmatter::string_list(&frontmatter::parse(&updated), "tags");
+    if fm_tags.iter().any(|tag| tag_matches(tag, old)) {
+        let mut seen = HashSet::new();
+        let renamed: Vec<Value> = fm_tags
+            .iter()
+            .map(|tag| rename(tag).unwrap_or_else(|| tag.clone()))
+            .filter(|tag| seen.insert(tag.to_lowercase()))
+            .map(Value::String)
+            .collect();
+        let mut patch = frontmatter::Frontmatter::new();
+        patch.insert("tags".to_string(), Value::Array(renamed));
+        updated = frontmatter::apply_patch(&updated, &patch)?;
+        changed = true;
+    }
+
+    Ok(changed.then_some(updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_body_and_frontmatter_tags() {
+        let content = "---\ntags: [Alpha, '#beta']\n---\n#alpha and #gamma/delta, #123 issue\n\
+                       url http://x.com/#frag [[Note#Heading]] `#code`\n```\n#fenced\n```\n#trail/\n";
+        assert_eq!(
+            extract_tags(content),
+            vec!["Alpha", "beta", "gamma/delta", "trail"]
+        );
+    }
+
+    #[test]
+    fn lists_nested_tags_under_their_parents() {
+        let mut index = TagIndex::default();
+        index.update_note("a", "#project/alpha #Project/beta");
+        index.update_note("b", "#project");
+        index.update_note("c", "#projects");
+        let listed: Vec<(String, usize)> =
+            index.list().into_iter().map(|t| (t.tag, t.count)).collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("project".to_string(), 2),
+                ("project/alpha".to_string(), 1),
+                ("Project/beta".to_string(), 1),
+                ("projects".to_string(), 1),
+            ]
+        );
+        assert_eq!(index.notes_with_tag("PROJECT"), vec!["a", "b"]);
+        assert_eq!(index.notes_with_tag("project/alpha"), vec!["a"]);
+    }
+
+    #[test]
+    fn normalizes_tag_names() {
+        assert_eq!(normalize_tag(" #work/ ").as_deref(), Some("work"));
+        assert_eq!(normalize_tag("a/b").as_deref(), Some("a/b"));
+        assert_eq!(normalize_tag("42"), None);
+        assert_eq!(normalize_tag("two words"), None);
+        assert_eq!(normalize_tag(""), None);
+    }
+
+    #[test]
+    fn renames_tag_and_nested_tags_in_body_and_frontmatter() {
+        let content = "---\ntags: [work, other]\n---\n#Work/meetings and #work #workshop\n`#work`\n```\n#work\n```\n";
+        assert_eq!(
+            rename_in_content(content, "work", "job").unwrap().as_deref(),
+            Some("---\ntags:\n- job\n- other\n---\n#job/meetings and #job #workshop\n`#work`\n```\n#work\n```\n")
+        );
+        assert_eq!(rename_in_content("#other", "work", "job").unwrap(), None);
+    }
+
+    #[test]
+    fn renames_when_case_folding_changes_byte_length() {
+        // 'ẞ' (3 bytes) lowercases to 'ß' (2 bytes); 'K' (Kelvin sign, 3 bytes) to 'k' (1 byte)
+        assert_eq!(
+            rename_in_content("#ẞtraße/sub", "ßtraße", "street")
+                .unwrap()
+                .as_deref(),
+            Some("#street/sub")
+        );
+        assert_eq!(
+            rename_in_content("#\u{212A}ey/nested", "key", "lock")
+                .unwrap()
+                .as_deref(),
+            Some("#lock/nested")
+        );
+        assert_eq!(nested_part("keys", "key"), None);
+        assert_eq!(nested_part("ke", "key"), None);
+    }
+
+    #[test]
+    fn merges_duplicate_frontmatter_tags_after_rename() {
+        let content = "---\ntags: [old, new]\n---\nBody\n";
+        assert_eq!(
+            rename_in_content(content, "old", "new").unwrap().as_deref(),
+            Some("---\ntags:\n- new\n---\nBody\n")
+        );
+    }
+}
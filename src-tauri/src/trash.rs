@@ -0,0 +1,388 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Trash lives inside the notes folder so deleted items stay on the same volume
+/// (moves are plain renames). `.trash` is in `EXCLUDED_DIRS`, so its contents
+/// never show up as notes.
+const TRASH_DIR: &str = ".trash";
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Default number of days deleted items are kept before being purged.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+// Serializes manifest read-modify-write cycles across concurrent commands
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashItemKind {
+    Note,
+    Folder,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashEntry {
+    pub id: String,            // Directory name under .trash/ holding the item
+    pub original_path: String, // Note ID or folder path, relative to the notes root
+    pub kind: TrashItemKind,
+    pub deleted_at: i64, // Unix seconds
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Manifest {
+    entries: Vec<TrashEntry>,
+}
+
+fn trash_dir(notes_root: &Path) -> PathBuf {
+    notes_root.join(TRASH_DIR)
+}
+
+fn load_manifest(notes_root: &Path) -> Manifest {
+    std::fs::read_to_string(trash_dir(notes_root).join(MANIFEST_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_manifest(notes_root: &Path, manifest: &Manifest) -> Result<(), String> {
+    let dir = trash_dir(notes_root);
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let content = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+    crate::fsutil::write_atomic(&dir.join(MANIFEST_FILE), content.as_bytes())
+        .map_err(|e| e.to_string())
+}
+
+fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Absolute path of an item relative to the notes root (POSIX separators).
+fn item_path(notes_root: &Path, rel_path: &str, kind: TrashItemKind) -> PathBuf {
+    let path = notes_root.join(rel_path.replace('/', std::path::MAIN_SEPARATOR_STR));
+    match kind {
+        TrashItemKind::Note => {
+            let mut os = path.into_os_string();
+            os.push(".md");
+            PathBuf::from(os)
+        }
+        TrashItemKind::Folder => path,
+    }
+}
+
+/// File or folder name the item is stored under inside its trash entry directory.
+fn item_name(rel_path: &str, kind: TrashItemKind) -> String {
+    let leaf = rel_path.rsplit('/').next().unwrap_or(rel_path);
+    match kind {
+        TrashItemKind::Note => format!("{}.md", leaf),
+        TrashItemKind::Folder => leaf.to_string(),
+    }
+}
+
+/// Move a note (by ID) or folder (by relative path) into the trash.
+pub fn move_to_trash(
+    notes_root: &Path,
+    rel_path: &str,
+    kind: TrashItemKind,
+) -> Result<TrashEntry, String> {
+    let source = item_path(notes_root, rel_path, kind);
+    if !source.exists() {
+        return Err("Item not found".to_string());
+    }
+
+    let _guard = MANIFEST_LOCK.lock().expect("trash manifest mutex");
+    let mut manifest = load_manifest(notes_root);
+
+    let deleted_at = now_secs();
+    let mut entry_id = deleted_at.to_string();
+    let mut counter = 1;
+    while trash_dir(notes_root).join(&entry_id).exists() {
+        entry_id = format!("{}-{}", deleted_at, counter);
+        counter += 1;
+    }
+
+    let entry_dir = trash_dir(notes_root).join(&entry_id);
+    std::fs::create_dir_all(&entry_dir).map_err(|e| e.to_string())?;
+    if let Err(e) = std::fs::rename(&source, entry_dir.join(item_name(rel_path, kind))) {
+        let _ = std::fs::remove_dir(&entry_dir);
+        return Err(format!("Failed to move to trash: {}", e));
+    }
+
+    let entry = TrashEntry {
+        id: entry_id,
+        original_path: rel_path.to_string(),
+        kind,
+        deleted_at,
+    };
+    manifest.entries.push(entry.clone());
+    save_manifest(notes_root, &manifest)?;
+
+    Ok(entry)
+}
+
+/// List trashed items, most recently deleted first.
+pub fn list(notes_root: &Path) -> Vec<TrashEntry> {
+    let _guard = MANIFEST_LOCK.lock().expect("trash manifest mutex");
+    let mut entries = load_manifest(notes_root).entries;
+    entries.sort_by_key(|e| std::cmp::Reverse(e.deleted_at));
+    entries
+}
+
+/// Restore a trashed item to its original location. If that location is taken,
+/// a numeric suffix is appended. Returns the restored entry with its final path.
+pub fn restore(notes_root: &Path, entry_id: &str) -> Result<TrashEntry, String> {
+    let _guard = MANIFEST_LOCK.lock().expect("trash manifest mutex");
+    let mut manifest = load_manifest(notes_root);
+
+    let pos = manifest
+        .entries
+        .iter()
+        .position(|e| e.id == entry_id)
+        .ok_or("Trash item not found")?;
+    let mut entry = manifest.entries[pos].clone();
+
+    let entry_dir = trash_dir(notes_root).join(&entry.id);
+    let source = entry_dir.join(item_name(&entry.original_path, entry.kind));
+    if !source.exists() {
+        // Stale manifest entry (removed by hand); drop it
+        manifest.entries.remove(pos);
+        save_manifest(notes_root, &manifest)?;
+        return Err("Trashed file no longer exists".to_string());
+    }
+
+    let mut restored_path = entry.original_path.clone();
+    let mut counter = 1;
+    while item_path(notes_root, &restored_path, entry.kind).exists() {
+        restored_path = format!("{}-{}", entry.original_path, counter);
+        counter += 1;
+    }
+
+    let dest = item_path(notes_root, &restored_path, entry.kind);
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::rename(&source, &dest).map_err(|e| format!("Failed to restore: {}", e))?;
+    let _ = std::fs::remove_dir_all(&entry_dir);
+
+    manifest.entries.remove(pos);
+    save_manifest(notes_root, &manifest)?;
+
+    entry.original_path = restored_path;
+    Ok(entry)
+}
+
+/// Permanently delete everything in the trash.
+pub fn empty(notes_root: &Path) -> Result<(), String> {
+    let _guard = MANIFEST_LOCK.lock().expect("trash manifest mutex");
+    let dir = trash_dir(notes_root);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Permanently delete trashed items older than `retention_days`.
+/// A retention of 0 keeps items until the trash is emptied manually.
+/// Returns the number of purged entries.
+pub fn purge_expired(notes_root: &Path, retention_days: u32) -> usize {
+    if retention_days == 0 {
+        return 0;
+    }
+
+    let _guard = MANIFEST_LOCK.lock().expect("trash manifest mutex");
+    let mut manifest = load_manifest(notes_root);
+    let cutoff = now_secs() - i64::from(retention_days) * 24 * 60 * 60;
+
+    let before = manifest.entries.len();
+    manifest.entries.retain(|entry| {
+        if entry.deleted_at >= cutoff {
+            return true;
+        }
+        let entry_dir = trash_dir(notes_root).join(&entry.id);
+        std::fs::remove_dir_all(&entry_dir).is_err() && entry_dir.exists()
+    });
+    let purged = before - manifest.entries.len();
+
+    if purged > 0 {
+        let _ = save_manifest(notes_root, &manifest);
+    }
+    purged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch notes folder, removed when dropped.
+    struct Vault(PathBuf);
+
+    impl Vault {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("scratch-trash-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Vault(root)
+        }
+
+        fn write(&self, rel_path: &str, content: &str) {
+            let path = self.0.join(rel_path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        fn read(&self, rel_path: &str) -> Option<String> {
+            std::fs::read_to_string(self.0.join(rel_path)).ok()
+        }
+
+        /// Entry directories under `.trash`, which should match the manifest.
+        fn entry_dirs(&self) -> Vec<String> {
+            let mut dirs: Vec<String> = std::fs::read_dir(trash_dir(&self.0))
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .filter(|e| e.path().is_dir())
+                        .map(|e| e.file_name().to_string_lossy().into_owned())
+                        .collect()
+                })
+                .unwrap_or_default();
+            dirs.sort();
+            dirs
+        }
+
+        fn manifest_ids(&self) -> Vec<String> {
+            let mut ids: Vec<String> = load_manifest(&self.0)
+                .entries
+                .into_iter()
+                .map(|e| e.id)
+                .collect();
+            ids.sort();
+            ids
+        }
+    }
+
+    impl Drop for Vault {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn trashes_and_restores_a_note() {
+        let vault = Vault::new("note");
+        vault.write("work/plan.md", "# Plan");
+
+        let entry = move_to_trash(&vault.0, "work/plan", TrashItemKind::Note).unwrap();
+        assert_eq!(vault.read("work/plan.md"), None);
+        assert_eq!(
+            vault
+                .read(&format!(".trash/{}/plan.md", entry.id))
+                .as_deref(),
+            Some("# Plan")
+        );
+        assert_eq!(list(&vault.0)[0].original_path, "work/plan");
+        assert_eq!(vault.entry_dirs(), vault.manifest_ids());
+
+        // The folder it came from may be gone by now
+        std::fs::remove_dir(vault.0.join("work")).unwrap();
+        let restored = restore(&vault.0, &entry.id).unwrap();
+        assert_eq!(restored.original_path, "work/plan");
+        assert_eq!(vault.read("work/plan.md").as_deref(), Some("# Plan"));
+        assert!(list(&vault.0).is_empty());
+        assert!(vault.entry_dirs().is_empty());
+
+        assert!(restore(&vault.0, &entry.id).is_err());
+        assert!(move_to_trash(&vault.0, "missing", TrashItemKind::Note).is_err());
+    }
+
+    #[test]
+    fn trashes_and_restores_a_folder() {
+        let vault = Vault::new("folder");
+        vault.write("projects/a.md", "A");
+        vault.write("projects/sub/b.md", "B");
+
+        let entry = move_to_trash(&vault.0, "projects", TrashItemKind::Folder).unwrap();
+        assert!(!vault.0.join("projects").exists());
+        assert_eq!(
+            vault
+                .read(&format!(".trash/{}/projects/sub/b.md", entry.id))
+                .as_deref(),
+            Some("B")
+        );
+
+        let restored = restore(&vault.0, &entry.id).unwrap();
+        assert_eq!(restored.original_path, "projects");
+        assert_eq!(vault.read("projects/a.md").as_deref(), Some("A"));
+        assert_eq!(vault.read("projects/sub/b.md").as_deref(), Some("B"));
+    }
+
+    #[test]
+    fn restore_picks_a_free_name_when_the_original_is_taken() {
+        let vault = Vault::new("collision");
+        vault.write("note.md", "first");
+        let first = move_to_trash(&vault.0, "note", TrashItemKind::Note).unwrap();
+        vault.write("note.md", "second");
+        let second = move_to_trash(&vault.0, "note", TrashItemKind::Note).unwrap();
+        // Same second, so the second entry gets a suffix
+        assert_ne!(first.id, second.id);
+        vault.write("note.md", "third");
+
+        assert_eq!(
+            restore(&vault.0, &first.id).unwrap().original_path,
+            "note-1"
+        );
+        assert_eq!(
+            restore(&vault.0, &second.id).unwrap().original_path,
+            "note-2"
+        );
+        assert_eq!(vault.read("note.md").as_deref(), Some("third"));
+        assert_eq!(vault.read("note-1.md").as_deref(), Some("first"));
+        assert_eq!(vault.read("note-2.md").as_deref(), Some("second"));
+    }
+
+    #[test]
+    fn restore_drops_entries_whose_files_are_gone() {
+        let vault = Vault::new("stale");
+        vault.write("a.md", "A");
+        let entry = move_to_trash(&vault.0, "a", TrashItemKind::Note).unwrap();
+        std::fs::remove_dir_all(trash_dir(&vault.0).join(&entry.id)).unwrap();
+
+        assert_eq!(
+            restore(&vault.0, &entry.id).unwrap_err(),
+            "Trashed file no longer exists"
+        );
+        assert!(list(&vault.0).is_empty());
+    }
+
+    #[test]
+    fn purges_only_expired_entries() {
+        let vault = Vault::new("purge");
+        vault.write("old.md", "old");
+        vault.write("new.md", "new");
+        let old = move_to_trash(&vault.0, "old", TrashItemKind::Note).unwrap();
+        let new = move_to_trash(&vault.0, "new", TrashItemKind::Note).unwrap();
+
+        let mut manifest = load_manifest(&vault.0);
+        for entry in &mut manifest.entries {
+            if entry.id == old.id {
+                entry.deleted_at -= 31 * 24 * 60 * 60;
+            }
+        }
+        save_manifest(&vault.0, &manifest).unwrap();
+
+        // 0 keeps everything
+        assert_eq!(purge_expired(&vault.0, 0), 0);
+        assert_eq!(purge_expired(&vault.0, 30), 1);
+        assert_eq!(vault.manifest_ids(), vec![new.id.clone()]);
+        assert_eq!(vault.entry_dirs(), vec![new.id]);
+        assert_eq!(purge_expired(&vault.0, 30), 0);
+
+        empty(&vault.0).unwrap();
+        assert!(list(&vault.0).is_empty());
+        assert!(!trash_dir(&vault.0).exists());
+    }
+}
@@ -250,6 +250,7 @@ export function NoteList({
     isLoading,
     searchQuery,
     searchResults,
+    searchError,
   } = useNotes();
 
   const [deleteDialogOpen, setDeleteDialogOpen] = useState(false);
@@ -300,7 +301,8 @@ export function NoteList({
       return searchResults.map((r) => ({
         id: r.id,
         title: r.title,
-        preview: r.preview,
+        // Show where the query matched when the content did
+        preview: r.snippets[0]?.text ?? r.preview,
         modified: r.modified,
       }));
     }
@@ -341,6 +343,14 @@ export function NoteList({
     );
   }
 
+  if (isSearching && searchError) {
+    return (
+      <div className="p-4 text-center text-sm text-text-muted select-none">
+        {searchError}
+      </div>
+    );
+  }
+
   if (isSearching && displayItems.length === 0) {
     return (
       <div className="p-4 text-center text-sm text-text-muted select-none">
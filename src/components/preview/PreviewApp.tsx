@@ -4,6 +4,7 @@ import { getCurrentWindow } from "@tauri-apps/api/window";
 import { toast } from "sonner";
 import { Editor, type PreviewModeData } from "../editor/Editor";
 import * as filesService from "../../services/files";
+import { isSaveConflict } from "../../types/note";
 
 interface PreviewAppProps {
   filePath: string;
@@ -17,6 +18,8 @@ export function PreviewApp({ filePath }: PreviewAppProps) {
   const [reloadVersion, setReloadVersion] = useState(0);
   const [focusMode, setFocusMode] = useState(false);
   const recentlySavedRef = useRef(false);
+  // Modification time of the version on screen, to detect external edits on save
+  const modifiedNsRef = useRef<string | undefined>(undefined);
 
   // Load file on mount
   useEffect(() => {
@@ -26,6 +29,7 @@ export function PreviewApp({ filePath }: PreviewAppProps) {
         setContent(result.content);
         setTitle(result.title);
         setModified(result.modified);
+        modifiedNsRef.current = result.modifiedNs;
       })
       .catch((error) => {
         console.error("Failed to load file:", error);
@@ -57,12 +61,22 @@ export function PreviewApp({ filePath }: PreviewAppProps) {
   const save = useCallback(
     async (newContent: string) => {
       try {
-        const result = await filesService.saveFileDirect(filePath, newContent);
+        const result = await filesService.saveFileDirect(
+          filePath,
+          newContent,
+          modifiedNsRef.current,
+        );
         recentlySavedRef.current = true;
         setModified(result.modified);
+        modifiedNsRef.current = result.modifiedNs;
         setTitle(result.title);
         setHasExternalChanges(false);
       } catch (error) {
+        if (isSaveConflict(error)) {
+          setHasExternalChanges(true);
+          toast.error("The file changed on disk. Reload it to see the changes.");
+          return;
+        }
         console.error("Failed to save file:", error);
         toast.error(`Failed to save: ${error}`);
       }
@@ -76,6 +90,7 @@ export function PreviewApp({ filePath }: PreviewAppProps) {
       setContent(result.content);
       setTitle(result.title);
       setModified(result.modified);
+      modifiedNsRef.current = result.modifiedNs;
       setHasExternalChanges(false);
       setReloadVersion((v) => v + 1);
     } catch (error) {
@@ -9,7 +9,7 @@ import {
   type ReactNode,
 } from "react";
 import { listen } from "@tauri-apps/api/event";
-import type { Note, NoteMetadata } from "../types/note";
+import { isSaveConflict, type Note, type NoteMetadata } from "../types/note";
 import * as notesService from "../services/notes";
 import type { SearchResult } from "../services/notes";
 
@@ -24,6 +24,7 @@ interface NotesDataContextValue {
   error: string | null;
   searchQuery: string;
   searchResults: SearchResult[];
+  searchError: string | null; // Invalid query syntax, shown in place of results
   isSearching: boolean;
   hasExternalChanges: boolean;
   reloadVersion: number;
@@ -65,6 +66,7 @@ export function NotesProvider({ children }: { children: ReactNode }) {
   const [error, setError] = useState<string | null>(null);
   const [searchQuery, setSearchQuery] = useState("");
   const [searchResults, setSearchResults] = useState<SearchResult[]>([]);
+  const [searchError, setSearchError] = useState<string | null>(null);
   const [isSearching, setIsSearching] = useState(false);
   const [hasExternalChanges, setHasExternalChanges] = useState(false);
   // Increments when user manually refreshes, so Editor knows to reload content
@@ -86,6 +88,17 @@ export function NotesProvider({ children }: { children: ReactNode }) {
   const searchRequestIdRef = useRef(0);
   // Tracks the ID of a newly created note so Editor can focus its title.
   const pendingNewNoteIdRef = useRef<string | null>(null);
+  // Modification time of each note as last read or saved, sent with saves so
+  // edits made on disk in the meantime aren't overwritten
+  const modifiedNsRef = useRef<Map<string, string>>(new Map());
+  // Saves run one at a time, so each is checked against the previous one's result
+  const saveQueueRef = useRef<Promise<void>>(Promise.resolve());
+
+  useEffect(() => {
+    if (currentNote) {
+      modifiedNsRef.current.set(currentNote.id, currentNote.modifiedNs);
+    }
+  }, [currentNote]);
 
   const refreshNotes = useCallback(async () => {
     if (!notesFolder) return;
@@ -190,14 +203,26 @@ export function NotesProvider({ children }: { children: ReactNode }) {
       // Use provided noteId (for flush saves) or fall back to currentNote.id
       const savingNoteId = noteId || currentNote?.id;
       if (!savingNoteId) return;
+      const previousSave = saveQueueRef.current;
+      let finishSave = () => {};
+      saveQueueRef.current = new Promise((resolve) => {
+        finishSave = resolve;
+      });
+      await previousSave;
       let updatedId: string | null = null;
 
       try {
         // Mark this note as recently saved to ignore file-change events from our own save
         recentlySavedRef.current.add(savingNoteId);
 
-        const updated = await notesService.saveNote(savingNoteId, content);
+        const updated = await notesService.saveNote(
+          savingNoteId,
+          content,
+          modifiedNsRef.current.get(savingNoteId),
+        );
         updatedId = updated.id;
+        modifiedNsRef.current.delete(savingNoteId);
+        modifiedNsRef.current.set(updated.id, updated.modifiedNs);
 
         // If the note was renamed (ID changed), also mark the new ID
         if (updated.id !== savingNoteId) {
@@ -242,10 +267,20 @@ export function NotesProvider({ children }: { children: ReactNode }) {
           if (updatedId) recentlySavedRef.current.delete(updatedId);
         }, 1000);
       } catch (err) {
-        setError(err instanceof Error ? err.message : "Failed to save note");
+        if (isSaveConflict(err)) {
+          // Keep the disk version; the editor offers to reload it
+          if (selectedNoteIdRef.current === savingNoteId) {
+            setHasExternalChanges(true);
+          }
+          setError("This note changed on disk. Reload it to see the changes.");
+        } else {
+          setError(err instanceof Error ? err.message : "Failed to save note");
+        }
         // Clean up immediately on error to avoid leaving stale entries
         recentlySavedRef.current.delete(savingNoteId);
         if (updatedId) recentlySavedRef.current.delete(updatedId);
+      } finally {
+        finishSave();
       }
     },
     [currentNote, scheduleRefresh]
@@ -536,6 +571,7 @@ export function NotesProvider({ children }: { children: ReactNode }) {
   const search = useCallback(async (query: string) => {
     const requestId = ++searchRequestIdRef.current;
     setSearchQuery(query);
+    setSearchError(null);
 
     const trimmedQuery = query.trim();
     if (!trimmedQuery) {
@@ -559,6 +595,8 @@ export function NotesProvider({ children }: { children: ReactNode }) {
         preview: note.preview,
         modified: note.modified,
         score: 0,
+        titleHighlights: [],
+        snippets: [],
       }));
 
     // Show instant local matches immediately; clear stale results if none match.
@@ -585,6 +623,10 @@ export function NotesProvider({ children }: { children: ReactNode }) {
       }
     } catch (err) {
       console.error("Search failed:", err);
+      if (requestId === searchRequestIdRef.current) {
+        setSearchResults([]);
+        setSearchError(err instanceof Error ? err.message : String(err));
+      }
     }
     if (requestId !== searchRequestIdRef.current) return;
     setIsSearching(false);
@@ -594,6 +636,7 @@ export function NotesProvider({ children }: { children: ReactNode }) {
     searchRequestIdRef.current += 1;
     setSearchQuery("");
     setSearchResults([]);
+    setSearchError(null);
     setIsSearching(false);
   }, []);
 
@@ -673,6 +716,32 @@ export function NotesProvider({ children }: { children: ReactNode }) {
     };
   }, [selectNote, refreshNotes]);
 
+  // Listen for "links-updated" events: notes rewritten by the backend after a rename/move
+  useEffect(() => {
+    const unlisten = listen<{ changed_ids: string[] }>("links-updated", (event) => {
+      const changedIds = event.payload.changed_ids || [];
+      refreshNotes();
+      const currentId = selectedNoteIdRef.current;
+      if (currentId && changedIds.includes(currentId)) {
+        selectNote(currentId);
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [selectNote, refreshNotes]);
+
+  // Listen for "notes-metadata-loaded": the backend finished reading the notes
+  // folder in the background, so titles, tags and links are complete
+  useEffect(() => {
+    const unlisten = listen<string>("notes-metadata-loaded", () => {
+      refreshNotes();
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [refreshNotes]);
+
   // Refresh notes when folder changes
   useEffect(() => {
     if (notesFolder) {
@@ -691,6 +760,7 @@ export function NotesProvider({ children }: { children: ReactNode }) {
       error,
       searchQuery,
       searchResults,
+      searchError,
       isSearching,
       hasExternalChanges,
       reloadVersion,
@@ -704,6 +774,7 @@ export function NotesProvider({ children }: { children: ReactNode }) {
       error,
       searchQuery,
       searchResults,
+      searchError,
       isSearching,
       hasExternalChanges,
       reloadVersion,
@@ -5,17 +5,25 @@ export interface FileContent {
   content: string;
   title: string;
   modified: number;
+  modifiedNs: string;
 }
 
 export async function readFileDirect(path: string): Promise<FileContent> {
   return invoke("read_file_direct", { path });
 }
 
+// Pass expectedModifiedNs (from FileContent.modifiedNs) to reject the save
+// with a SaveConflict if the file changed on disk since it was read.
 export async function saveFileDirect(
   path: string,
   content: string,
+  expectedModifiedNs?: string,
 ): Promise<FileContent> {
-  return invoke("save_file_direct", { path, content });
+  return invoke("save_file_direct", {
+    path,
+    content,
+    expectedModifiedNs: expectedModifiedNs ?? null,
+  });
 }
 
 export async function openFilePreview(path: string): Promise<void> {
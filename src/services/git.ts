@@ -1,4 +1,5 @@
 import { invoke } from "@tauri-apps/api/core";
+import type { Note } from "../types/note";
 
 export interface GitStatus {
   isRepo: boolean;
@@ -6,18 +7,92 @@ export interface GitStatus {
   hasUpstream: boolean;
   remoteUrl: string | null;
   changedCount: number;
+  changedFiles: GitChangedFile[];
   aheadCount: number;
   behindCount: number;
   currentBranch: string | null;
+  mergeInProgress: boolean; // A pull stopped on conflicts
+  conflictCount: number;
   error: string | null;
 }
 
+// Untracked files count as added, conflicted ones as modified
+export interface GitChangedFile {
+  path: string; // Relative to the notes folder
+  status: "added" | "modified" | "renamed" | "deleted";
+  oldPath: string | null; // Path before a staged rename
+  id: string | null; // Note ID, for notes
+}
+
 export interface GitResult {
   success: boolean;
   message: string | null;
   error: string | null;
 }
 
+export interface GitFileCommit {
+  hash: string;
+  shortHash: string;
+  author: string;
+  email: string;
+  date: number; // Unix seconds
+  message: string;
+  change: "added" | "modified" | "renamed" | "deleted";
+  path: string; // The note's file path in this commit
+  oldPath: string | null; // Its path before, when this commit renamed it
+}
+
+export interface GitDiffLine {
+  kind: "context" | "added" | "removed";
+  content: string;
+  oldLine: number | null;
+  newLine: number | null;
+}
+
+export interface GitDiffHunk {
+  oldStart: number;
+  oldLines: number;
+  newStart: number;
+  newLines: number;
+  lines: GitDiffLine[];
+}
+
+export interface GitFileDiff {
+  hunks: GitDiffHunk[];
+  additions: number;
+  deletions: number;
+}
+
+// A missing side means the note was deleted there
+export interface GitConflictedNote {
+  id: string;
+  ours: string | null;
+  theirs: string | null;
+  base: string | null;
+}
+
+export type GitConflictResolution =
+  | { kind: "ours" }
+  | { kind: "theirs" }
+  | { kind: "merged"; content: string };
+
+// Payload of the "git-sync-status" event, sent whenever auto-sync's state changes
+export interface GitSyncStatus {
+  state:
+    | "disabled"
+    | "idle"
+    | "committing"
+    | "pulling"
+    | "pushing"
+    | "conflicts"
+    | "failed";
+  error: string | null;
+  lastCommit: number | null; // Unix seconds
+  lastSync: number | null; // Unix seconds
+  nextSync: number | null; // Unix seconds, also when a failure is retried
+  failures: number;
+}
+
 export async function isGitAvailable(): Promise<boolean> {
   return invoke("git_is_available");
 }
@@ -38,6 +113,14 @@ export async function gitPush(): Promise<GitResult> {
   return invoke("git_push");
 }
 
+// Commits only these notes, plus changed assets they reference
+export async function gitCommitPaths(
+  ids: string[],
+  message: string
+): Promise<GitResult> {
+  return invoke("git_commit_paths", { ids, message });
+}
+
 export async function gitFetch(): Promise<GitResult> {
   return invoke("git_fetch");
 }
@@ -61,3 +144,47 @@ export async function removeRemote(): Promise<GitResult> {
 export async function pushWithUpstream(): Promise<GitResult> {
   return invoke("git_push_with_upstream");
 }
+
+export async function gitLogNote(id: string): Promise<GitFileCommit[]> {
+  return invoke("git_log_note", { id });
+}
+
+export async function gitShowNoteAt(id: string, rev: string): Promise<string> {
+  return invoke("git_show_note_at", { id, rev });
+}
+
+// Omit `to` to diff against the note as it is now
+export async function gitDiffNote(
+  id: string,
+  from: string,
+  to?: string
+): Promise<GitFileDiff> {
+  return invoke("git_diff_note", { id, from, to: to ?? null });
+}
+
+export async function gitRestoreNote(id: string, rev: string): Promise<Note> {
+  return invoke("git_restore_note", { id, rev });
+}
+
+export async function gitListConflicts(): Promise<GitConflictedNote[]> {
+  return invoke("git_list_conflicts");
+}
+
+export async function gitResolveConflict(
+  id: string,
+  resolution: GitConflictResolution
+): Promise<GitResult> {
+  return invoke("git_resolve_conflict", { id, resolution });
+}
+
+export async function gitAbortMerge(): Promise<GitResult> {
+  return invoke("git_abort_merge");
+}
+
+export async function getGitSyncStatus(): Promise<GitSyncStatus> {
+  return invoke("get_git_sync_status");
+}
+
+export async function gitSyncNow(): Promise<void> {
+  return invoke("git_sync_now");
+}
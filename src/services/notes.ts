@@ -1,5 +1,15 @@
 import { invoke } from "@tauri-apps/api/core";
-import type { Note, NoteMetadata, Settings } from "../types/note";
+import type {
+  Backlink,
+  Note,
+  NoteMetadata,
+  NoteVersion,
+  OutgoingLink,
+  SavedSearch,
+  Settings,
+  TagCount,
+  TrashEntry,
+} from "../types/note";
 
 export async function getNotesFolder(): Promise<string | null> {
   return invoke("get_notes_folder");
@@ -13,12 +23,40 @@ export async function listNotes(): Promise<NoteMetadata[]> {
   return invoke("list_notes");
 }
 
+// Manual order of the notes directly in a folder ("" for the root).
+export async function reorderNotes(folder: string, ids: string[]): Promise<void> {
+  return invoke("reorder_notes", { folder, ids });
+}
+
+// ids must be exactly the pinned notes, in their new order.
+export async function reorderPinnedNotes(ids: string[]): Promise<void> {
+  return invoke("reorder_pinned_notes", { ids });
+}
+
 export async function readNote(id: string): Promise<Note> {
   return invoke("read_note", { id });
 }
 
-export async function saveNote(id: string | null, content: string): Promise<Note> {
-  return invoke("save_note", { id, content });
+// Set frontmatter fields on a note; a null value removes the field.
+export async function updateNoteFrontmatter(
+  id: string,
+  patch: Record<string, unknown>,
+): Promise<Note> {
+  return invoke("update_note_frontmatter", { id, patch });
+}
+
+// Pass expectedModifiedNs (from Note.modifiedNs) to reject the save with a
+// SaveConflict if the note changed on disk since it was read.
+export async function saveNote(
+  id: string | null,
+  content: string,
+  expectedModifiedNs?: string,
+): Promise<Note> {
+  return invoke("save_note", {
+    id,
+    content,
+    expectedModifiedNs: expectedModifiedNs ?? null,
+  });
 }
 
 export async function deleteNote(id: string): Promise<void> {
@@ -53,6 +91,30 @@ export async function moveFolder(path: string, targetParent: string): Promise<vo
   return invoke("move_folder", { path, targetParent });
 }
 
+export async function listTrash(): Promise<TrashEntry[]> {
+  return invoke("list_trash");
+}
+
+export async function restoreFromTrash(entryId: string): Promise<TrashEntry> {
+  return invoke("restore_from_trash", { entryId });
+}
+
+export async function emptyTrash(): Promise<void> {
+  return invoke("empty_trash");
+}
+
+export async function listNoteVersions(id: string): Promise<NoteVersion[]> {
+  return invoke("list_note_versions", { id });
+}
+
+export async function readNoteVersion(id: string, versionId: string): Promise<string> {
+  return invoke("read_note_version", { id, versionId });
+}
+
+export async function restoreNoteVersion(id: string, versionId: string): Promise<Note> {
+  return invoke("restore_note_version", { id, versionId });
+}
+
 export async function duplicateNote(id: string): Promise<Note> {
   // Read the original note, then create a new one in the same folder
   const original = await readNote(id);
@@ -82,18 +144,150 @@ export async function updateGitEnabled(
   });
 }
 
+// Offsets are UTF-16 code units, i.e. usable with String.prototype.slice.
+export interface HighlightRange {
+  start: number;
+  end: number;
+}
+
+export interface SearchSnippet {
+  text: string;
+  highlights: HighlightRange[];
+  line: number; // 1-based line in the note
+}
+
 export interface SearchResult {
   id: string;
   title: string;
   preview: string;
   modified: number;
   score: number;
+  titleHighlights: HighlightRange[];
+  snippets: SearchSnippet[]; // Where the query matched, in document order
 }
 
 export async function searchNotes(query: string): Promise<SearchResult[]> {
   return invoke("search_notes", { query });
 }
 
+export async function listSavedSearches(): Promise<SavedSearch[]> {
+  return invoke("list_saved_searches");
+}
+
+export async function runSavedSearch(id: string): Promise<SearchResult[]> {
+  return invoke("run_saved_search", { id });
+}
+
+// Payload of the "saved-search-updated" event, sent for live saved searches
+// when notes they list or now match change on disk.
+export interface SavedSearchUpdated {
+  id: string;
+  results: SearchResult[];
+}
+
+export interface SemanticResult {
+  id: string;
+  title: string;
+  score: number; // cosine similarity of the best matching part of the note
+  line: number; // 1-based line where that part starts
+  excerpt: string;
+}
+
+// Ranks the notes embedded so far; notes changed since are embedded in the
+// background afterwards (reporting "embedding-progress" events), so results
+// fill in once the first embedding finishes.
+export async function semanticSearch(query: string): Promise<SemanticResult[]> {
+  return invoke("semantic_search", { query });
+}
+
+export async function relatedNotes(id: string): Promise<SemanticResult[]> {
+  return invoke("related_notes", { id });
+}
+
+export async function getBacklinks(id: string): Promise<Backlink[]> {
+  return invoke("get_backlinks", { id });
+}
+
+export async function getOutgoingLinks(id: string): Promise<OutgoingLink[]> {
+  return invoke("get_outgoing_links", { id });
+}
+
+export async function listTags(): Promise<TagCount[]> {
+  return invoke("list_tags");
+}
+
+export async function listNotesByTag(tag: string): Promise<NoteMetadata[]> {
+  return invoke("list_notes_by_tag", { tag });
+}
+
+// Returns the IDs of the notes that were rewritten.
+export async function renameTag(
+  oldTag: string,
+  newTag: string,
+): Promise<string[]> {
+  return invoke("rename_tag", { oldTag, newTag });
+}
+
+export interface FindOptions {
+  regex?: boolean; // Pattern is a regular expression rather than literal text
+  caseSensitive?: boolean;
+  wholeWord?: boolean;
+  folder?: string; // Only notes in this folder (and below)
+}
+
+export interface VaultMatch {
+  line: number; // 1-based
+  column: number; // 1-based, in UTF-16 code units
+  length: number;
+  context: string; // The line around the match
+  highlight: HighlightRange; // The match within context
+}
+
+export interface NoteMatches {
+  id: string;
+  title: string;
+  matches: VaultMatch[];
+}
+
+export interface MatchRef {
+  id: string;
+  line: number;
+  column: number;
+}
+
+export interface ReplaceResult {
+  undoToken: string | null; // null if nothing was replaced
+  changedIds: string[];
+  replaced: number;
+}
+
+export async function findInVault(
+  pattern: string,
+  options?: FindOptions,
+): Promise<NoteMatches[]> {
+  return invoke("find_in_vault", { pattern, options: options ?? null });
+}
+
+// Replaces every match unless `matches` picks some of them (as returned by
+// findInVault). All notes are written or none are.
+export async function replaceInVault(
+  pattern: string,
+  replacement: string,
+  options?: FindOptions,
+  matches?: MatchRef[],
+): Promise<ReplaceResult> {
+  return invoke("replace_in_vault", {
+    pattern,
+    replacement,
+    selection: { options: options ?? {}, matches: matches ?? null },
+  });
+}
+
+// Returns the IDs of the notes restored; notes edited since are left alone.
+export async function undoReplaceInVault(undoToken: string): Promise<string[]> {
+  return invoke("undo_replace_in_vault", { undoToken });
+}
+
 export async function startFileWatcher(): Promise<void> {
   return invoke("start_file_watcher");
 }
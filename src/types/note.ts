@@ -3,6 +3,8 @@ export interface NoteMetadata {
   title: string;
   preview: string;
   modified: number;
+  created: number; // frontmatter created/date, else file creation time
+  frontmatter: Record<string, unknown>;
 }
 
 export interface Note {
@@ -11,6 +13,23 @@ export interface Note {
   content: string;
   path: string;
   modified: number;
+  modifiedNs: string; // Exact modification time, passed back to saveNote
+}
+
+// Rejection value of save commands when the file changed on disk since it was read
+export interface SaveConflict {
+  kind: "conflict";
+  diskContent: string;
+  diskModified: number;
+  diskModifiedNs: string;
+}
+
+export function isSaveConflict(err: unknown): err is SaveConflict {
+  return (
+    typeof err === "object" &&
+    err !== null &&
+    (err as { kind?: unknown }).kind === "conflict"
+  );
 }
 
 export interface ThemeSettings {
@@ -49,7 +68,9 @@ export interface Settings {
   editorFont?: EditorFontSettings;
   gitEnabled?: boolean;
   foldersEnabled?: boolean;
-  pinnedNoteIds?: string[];
+  pinnedNoteIds?: string[]; // listed first, in this order
+  noteSort?: NoteSort;
+  manualNoteOrder?: Record<string, string[]>; // folder ("" = root) -> note IDs
   textDirection?: TextDirection;
   editorWidth?: EditorWidth;
   customEditorWidthPx?: number;
@@ -60,6 +81,40 @@ export interface Settings {
   ignoredPatterns?: string[];
   customColorsLight?: CustomColors;
   customColorsDark?: CustomColors;
+  trashRetentionDays?: number; // days; 0 keeps deleted notes until the trash is emptied
+  historyIntervalMinutes?: number; // minimum minutes between snapshots of a note
+  historyMaxVersions?: number; // 0 = unlimited
+  historyRetentionDays?: number; // 0 = unlimited
+  searchAnalyzer?: SearchAnalyzerSettings; // changing it rebuilds the search index
+  savedSearches?: SavedSearch[]; // shown as smart folders
+  embeddingModel?: string; // Ollama model for semantic search, defaults to nomic-embed-text
+  gitAutoSync?: GitAutoSyncSettings; // only applies while gitEnabled
+}
+
+export interface GitAutoSyncSettings {
+  enabled: boolean;
+  commitAfterMinutes?: number; // minutes without changes before committing, default 5
+  syncIntervalMinutes?: number; // default 15; 0 only commits
+  fetchOnStartup?: boolean; // default true
+  messageTemplate?: string; // {titles}, {count} and {timestamp} are filled in
+}
+
+export interface SavedSearch {
+  id: string;
+  name: string;
+  query: string; // same syntax as the search box
+  live?: boolean; // re-run on file changes, sending saved-search-updated
+}
+
+export interface NoteSort {
+  by: "modified" | "created" | "title" | "manual";
+  ascending?: boolean; // default is newest (or Z) first
+}
+
+export interface SearchAnalyzerSettings {
+  stemming?: string | null; // e.g. "english"; null matches words as written
+  cjk?: boolean; // split Chinese/Japanese/Korean text into character pairs
+  asciiFolding?: boolean; // "cafe" finds "café"
 }
 
 export interface FolderNode {
@@ -68,3 +123,37 @@ export interface FolderNode {
   children: FolderNode[];
   notes: NoteMetadata[];
 }
+
+export type LinkKind = "wikilink" | "markdown";
+
+export interface OutgoingLink {
+  kind: LinkKind;
+  target: string;
+  targetId: string | null;
+}
+
+export interface Backlink {
+  id: string;
+  title: string;
+  kind: LinkKind;
+  context: string;
+}
+
+export interface TagCount {
+  tag: string;
+  count: number; // Notes carrying the tag or one of its nested tags
+}
+
+export interface TrashEntry {
+  id: string;
+  originalPath: string;
+  kind: "note" | "folder";
+  deletedAt: number;
+}
+
+export interface NoteVersion {
+  id: string;
+  created: number;
+  source: "save" | "ai" | "restore" | "replace";
+  size: number;
+}